@@ -1,11 +1,13 @@
 use crate::{
+    cache::Cache,
     config::structs::{
-        Filters, ModLoader, Profile, ReleaseChannel, Source, SourceId, SourceKind,
+        Filters, Host, ModLoader, Profile, ReleaseChannel, Source, SourceId, SourceKind,
         SourceKindWithModpack,
     },
     get_tmp_dir,
     iter_ext::IterExt as _,
-    upgrade::{calculate_sha512, check, Metadata},
+    rate_limit, search,
+    upgrade::{calculate_sha512, check, from_mr_version, try_from_cf_file, Metadata},
     CURSEFORGE_API, GITHUB_API, MODRINTH_API,
 };
 use serde::Deserialize;
@@ -44,6 +46,8 @@ pub enum Error {
     Io(#[from] std::io::Error),
     #[error(transparent)]
     Reqwest(#[from] reqwest::Error),
+    #[error(transparent)]
+    Search(#[from] search::Error),
 }
 type Result<T> = std::result::Result<T, Error>;
 
@@ -93,18 +97,194 @@ struct ReleaseAsset {
     name: String,
 }
 
-pub fn parse_id(id: String) -> SourceId {
+/// Parse `id` as a CurseForge project ID, a URL, a GitHub repository, a file path, or a Modrinth slug
+///
+/// URLs of Modrinth, CurseForge, and GitHub pages are recognised using [`parse_url`],
+/// other URLs are downloaded as they are.
+pub async fn parse_id(cache: &Cache, id: String) -> Result<SourceId> {
     if let Ok(id) = id.parse() {
-        return SourceId::Curseforge(id);
-    } else if let Ok(id) = id.parse() {
-        return SourceId::Url(id);
+        return Ok(SourceId::Curseforge(id));
+    } else if let Ok(url) = id.parse::<Url>() {
+        return Ok(parse_url(cache, &url).await?.unwrap_or(SourceId::Url(url)));
     } else if let Some((owner, repo)) = id.split_once('/') {
-        return SourceId::Github(owner.to_owned(), repo.to_owned());
+        return Ok(SourceId::Github(owner.to_owned(), repo.to_owned()));
     } else if !id.chars().all(|c| c.is_alphabetic()) {
-        return SourceId::File(id.parse().expect("PathBuf parse is infallible"));
+        return Ok(SourceId::File(
+            id.parse().expect("PathBuf parse is infallible"),
+        ));
+    }
+
+    Ok(SourceId::Modrinth(id))
+}
+
+/// The kinds of projects in the URLs of Modrinth's pages
+const MODRINTH_PAGES: &[&str] = &[
+    "mod",
+    "plugin",
+    "datapack",
+    "resourcepack",
+    "shader",
+    "modpack",
+    "project",
+];
+
+/// Get the source of the Modrinth, CurseForge, or GitHub page or file at `url`,
+/// or `None` if it isn't from one of them
+///
+/// URLs of a specific Modrinth version, CurseForge file, or GitHub release asset become pinned sources.
+/// CurseForge pages only contain the project's slug and GitHub downloads only contain the asset's name,
+/// so their IDs are looked up.
+pub async fn parse_url(cache: &Cache, url: &Url) -> Result<Option<SourceId>> {
+    let host = url
+        .host_str()
+        .unwrap_or_default()
+        .trim_start_matches("www.");
+    let segments = url
+        .path_segments()
+        .map(|segments| segments.filter(|segment| !segment.is_empty()).collect_vec())
+        .unwrap_or_default();
+
+    Ok(Some(match (host, segments.as_slice()) {
+        ("modrinth.com", [page, project, "version", version, ..])
+            if MODRINTH_PAGES.contains(page) =>
+        {
+            // Version pages use either the ID or the number of the version
+            let version = rate_limit::send(Host::Modrinth, || {
+                MODRINTH_API.get_version_from_number(project, version)
+            })
+            .await?;
+            SourceId::PinnedModrinth(version.project_id, version.id)
+        }
+        ("modrinth.com", [page, project, ..]) if MODRINTH_PAGES.contains(page) => {
+            SourceId::Modrinth((*project).to_owned())
+        }
+        ("cdn.modrinth.com", ["data", project, "versions", version, ..]) => {
+            SourceId::PinnedModrinth((*project).to_owned(), (*version).to_owned())
+        }
+
+        // Project URLs use either the ID or the slug of the project
+        ("curseforge.com" | "legacy.curseforge.com", ["projects", project, ..]) => {
+            SourceId::Curseforge(match project.parse() {
+                Ok(id) => id,
+                Err(_) => curseforge_project(cache, None, project).await?,
+            })
+        }
+        (
+            "curseforge.com" | "legacy.curseforge.com",
+            ["minecraft", class, slug, "files" | "download", file_id, ..],
+        ) => match file_id.parse() {
+            Ok(file_id) => curseforge_file(file_id).await?,
+            // Pages listing the files, e.g. `files/all`, are of the project
+            Err(_) => SourceId::Curseforge(
+                curseforge_project(cache, curseforge_class(class), slug).await?,
+            ),
+        },
+        ("curseforge.com" | "legacy.curseforge.com", ["minecraft", class, slug, ..]) => {
+            SourceId::Curseforge(curseforge_project(cache, curseforge_class(class), slug).await?)
+        }
+        // Files on CurseForge's CDN are stored under their ID split in two, e.g. `files/4464/891`
+        (host, ["files", high, low, ..]) if host.ends_with("forgecdn.net") => {
+            let (Ok(high), Ok(low)) = (high.parse::<i32>(), low.parse::<i32>()) else {
+                return Ok(None);
+            };
+            let Some(file_id) = high.checked_mul(1000).and_then(|id| id.checked_add(low)) else {
+                return Ok(None);
+            };
+            curseforge_file(file_id).await?
+        }
+
+        ("github.com", [owner, repo, "releases", "download", tag, _]) => {
+            let release = rate_limit::send(Host::Github, || async {
+                GITHUB_API
+                    .repos(*owner, *repo)
+                    .releases()
+                    .get_by_tag(tag)
+                    .await
+            })
+            .await?;
+            let asset = release
+                .assets
+                .into_iter()
+                .find(|asset| asset.browser_download_url == *url)
+                .ok_or(Error::DoesNotExist)?;
+            SourceId::PinnedGithub(
+                ((*owner).to_owned(), (*repo).to_owned()),
+                github_asset_id(asset.id.into_inner())?,
+            )
+        }
+        ("api.github.com", ["repos", owner, repo, "releases", "assets", id]) => {
+            let Ok(id) = id.parse() else { return Ok(None) };
+            SourceId::PinnedGithub(((*owner).to_owned(), (*repo).to_owned()), id)
+        }
+        ("github.com", [owner, repo, ..]) => SourceId::Github(
+            (*owner).to_owned(),
+            repo.trim_end_matches(".git").to_owned(),
+        ),
+
+        _ => return Ok(None),
+    }))
+}
+
+/// Get the pinned source of the CurseForge file with `file_id`
+async fn curseforge_file(file_id: i32) -> Result<SourceId> {
+    let file = rate_limit::send(Host::Curseforge, || CURSEFORGE_API.get_files(vec![file_id]))
+        .await?
+        .into_iter()
+        .next()
+        .ok_or(Error::DoesNotExist)?;
+    Ok(SourceId::PinnedCurseforge(file.mod_id, file.id))
+}
+
+/// Get the class ID of the CurseForge projects with pages under `segment`, e.g. `mc-mods`
+fn curseforge_class(segment: &str) -> Option<i32> {
+    match segment {
+        "mc-mods" => Some(6),
+        "texture-packs" => Some(12),
+        "shaders" => Some(6552),
+        "modpacks" => Some(4471),
+        _ => None,
     }
+}
+
+/// Get the ID of the CurseForge project with `slug`, of `class_id` if it is known
+async fn curseforge_project(cache: &Cache, class_id: Option<i32>, slug: &str) -> Result<i32> {
+    search::curseforge_slug(cache, class_id, slug)
+        .await?
+        .ok_or(Error::DoesNotExist)
+}
+
+/// Convert a GitHub release asset ID to the type pinned GitHub sources use
+fn github_asset_id(id: u64) -> Result<i32> {
+    i32::try_from(id)
+        .map_err(|_| Error::GitHubError(format!("the asset ID {id} is too large to be pinned")))
+}
 
-    SourceId::Modrinth(id)
+/// Get the metadata of a GitHub release asset named `name`,
+/// reading the game versions and mod loaders it supports from its name
+fn asset_metadata(
+    title: String,
+    description: String,
+    channel: ReleaseChannel,
+    name: String,
+) -> Metadata {
+    let parts = || {
+        name.trim_end_matches(".jar")
+            .trim_end_matches(".zip")
+            .split(['-', '_', '+'])
+    };
+    Metadata {
+        title,
+        description,
+        channel,
+        game_versions: parts()
+            .map(|s| s.trim_start_matches("mc"))
+            .map(ToOwned::to_owned)
+            .collect_vec(),
+        loaders: parts()
+            .filter_map(|s| ModLoader::from_str(s).ok())
+            .collect_vec(),
+        filename: name,
+    }
 }
 
 /// Adds mods from `identifiers`, and returns successful mods with their names, and unsuccessful mods with an error
@@ -123,6 +303,9 @@ pub async fn add(
     let mut gh_ids = Vec::new();
     let mut file_ids = Vec::new();
     let mut url_ids = Vec::new();
+    let mut cf_pins = Vec::new();
+    let mut mr_pins = Vec::new();
+    let mut gh_pins = Vec::new();
     let mut errors = Vec::new();
 
     for id in identifiers {
@@ -132,39 +315,76 @@ pub async fn add(
             SourceId::Github(o, r) => gh_ids.push((o, r)),
             SourceId::File(path) => file_ids.push(path),
             SourceId::Url(url) => url_ids.push(url),
-            SourceId::PinnedCurseforge(_, _)
-            | SourceId::PinnedModrinth(_, _)
-            | SourceId::PinnedGithub(_, _) => {
-                todo!("\nAdding pinned projects from `add` is not yet supported\nPlease manually add the pinned project into the profile, like so: `my_mod = \"{id}\"`\n")
-            }
+            SourceId::PinnedCurseforge(id, pin) => cf_pins.push((id, pin)),
+            SourceId::PinnedModrinth(id, pin) => mr_pins.push((id, pin)),
+            SourceId::PinnedGithub(repo, pin) => gh_pins.push((repo, pin)),
         }
     }
 
-    let cf_projects = if !cf_ids.is_empty() {
-        cf_ids.sort_unstable();
-        cf_ids.dedup();
-        CURSEFORGE_API.get_mods(cf_ids.clone()).await?
+    let cf_files = if !cf_pins.is_empty() {
+        let file_ids = cf_pins.iter().map(|(_, pin)| *pin).collect_vec();
+        rate_limit::send(Host::Curseforge, || {
+            CURSEFORGE_API.get_files(file_ids.clone())
+        })
+        .await?
     } else {
         Vec::new()
     };
 
-    let mr_projects = if !mr_ids.is_empty() {
-        mr_ids.sort_unstable();
-        mr_ids.dedup();
-        MODRINTH_API
-            .get_multiple_projects(&mr_ids.iter().map(AsRef::as_ref).collect_vec())
-            .await?
+    cf_ids.sort_unstable();
+    cf_ids.dedup();
+    // The projects of pinned files are looked up in the same batch
+    let mut cf_project_ids = cf_ids
+        .iter()
+        .copied()
+        .chain(cf_pins.iter().map(|(id, _)| *id))
+        .collect_vec();
+    cf_project_ids.sort_unstable();
+    cf_project_ids.dedup();
+    let cf_projects = if !cf_project_ids.is_empty() {
+        rate_limit::send(Host::Curseforge, || {
+            CURSEFORGE_API.get_mods(cf_project_ids.clone())
+        })
+        .await?
     } else {
         Vec::new()
     };
 
-    let gh_repos =
-        {
-            // Construct GraphQl query using raw strings
-            let mut graphql_query = "{".to_string();
-            for (i, (owner, name)) in gh_ids.iter().enumerate() {
-                graphql_query.push_str(&format!(
-                    "_{i}: repository(owner: \"{owner}\", name: \"{name}\") {{
+    let mr_versions = if !mr_pins.is_empty() {
+        let version_ids = mr_pins.iter().map(|(_, pin)| pin.as_str()).collect_vec();
+        rate_limit::send(Host::Modrinth, || {
+            MODRINTH_API.get_multiple_versions(&version_ids)
+        })
+        .await?
+    } else {
+        Vec::new()
+    };
+
+    mr_ids.sort_unstable();
+    mr_ids.dedup();
+    // The projects of pinned versions are looked up in the same batch
+    let mut mr_project_ids = mr_ids
+        .iter()
+        .chain(mr_versions.iter().map(|version| &version.project_id))
+        .map(AsRef::as_ref)
+        .collect_vec();
+    mr_project_ids.sort_unstable();
+    mr_project_ids.dedup();
+    let mr_projects = if !mr_project_ids.is_empty() {
+        rate_limit::send(Host::Modrinth, || {
+            MODRINTH_API.get_multiple_projects(&mr_project_ids)
+        })
+        .await?
+    } else {
+        Vec::new()
+    };
+
+    let gh_repos = {
+        // Construct GraphQl query using raw strings
+        let mut graphql_query = "{".to_string();
+        for (i, (owner, name)) in gh_ids.iter().enumerate() {
+            graphql_query.push_str(&format!(
+                "_{i}: repository(owner: \"{owner}\", name: \"{name}\") {{
                     owner {{
                         login
                     }}
@@ -182,83 +402,67 @@ pub async fn add(
                         }}
                     }}
                 }}"
-                ));
+            ));
+        }
+        graphql_query.push('}');
+
+        // Send the query
+        let response: GraphQlResponse = if !gh_ids.is_empty() {
+            let query = HashMap::from([("query", graphql_query)]);
+            rate_limit::send(Host::Github, || GITHUB_API.graphql(&query)).await?
+        } else {
+            GraphQlResponse {
+                data: HashMap::new(),
+                errors: Vec::new(),
             }
-            graphql_query.push('}');
+        };
 
-            // Send the query
-            let response: GraphQlResponse = if !gh_ids.is_empty() {
-                GITHUB_API
-                    .graphql(&HashMap::from([("query", graphql_query)]))
-                    .await?
-            } else {
-                GraphQlResponse {
-                    data: HashMap::new(),
-                    errors: Vec::new(),
-                }
-            };
+        errors.extend(response.errors.into_iter().map(|v| {
+            (
+                {
+                    let id = &gh_ids[v.path[0]
+                        .strip_prefix('_')
+                        .and_then(|s| s.parse::<usize>().ok())
+                        .expect("Unexpected response data")];
+                    format!("{}/{}", id.0, id.1)
+                },
+                if v.type_ == "NOT_FOUND" {
+                    Error::DoesNotExist
+                } else {
+                    Error::GitHubError(v.message)
+                },
+            )
+        }));
 
-            errors.extend(response.errors.into_iter().map(|v| {
+        response
+            .data
+            .into_values()
+            .flatten()
+            .map(|d| {
                 (
-                    {
-                        let id = &gh_ids[v.path[0]
-                            .strip_prefix('_')
-                            .and_then(|s| s.parse::<usize>().ok())
-                            .expect("Unexpected response data")];
-                        format!("{}/{}", id.0, id.1)
-                    },
-                    if v.type_ == "NOT_FOUND" {
-                        Error::DoesNotExist
-                    } else {
-                        Error::GitHubError(v.message)
-                    },
-                )
-            }));
-
-            response
-                .data
-                .into_values()
-                .flatten()
-                .map(|d| {
-                    (
-                        (d.owner.login, d.name),
-                        d.releases
-                            .nodes
-                            .into_iter()
-                            .flat_map(|release| {
-                                release.release_assets.nodes.into_iter().map(move |asset| {
-                                    Metadata {
-                                        title: release.name.clone(),
-                                        description: release.description.clone(),
-                                        channel: if release.is_prerelease {
-                                            ReleaseChannel::Beta
-                                        } else {
-                                            ReleaseChannel::Release
-                                        },
-                                        game_versions: asset
-                                            .name
-                                            .trim_end_matches(".jar")
-                                            .trim_end_matches(".zip")
-                                            .split(['-', '_', '+'])
-                                            .map(|s| s.trim_start_matches("mc"))
-                                            .map(ToOwned::to_owned)
-                                            .collect_vec(),
-                                        loaders: asset
-                                            .name
-                                            .trim_end_matches(".jar")
-                                            .trim_end_matches(".zip")
-                                            .split(['-', '_', '+'])
-                                            .filter_map(|s| ModLoader::from_str(s).ok())
-                                            .collect_vec(),
-                                        filename: asset.name,
-                                    }
-                                })
+                    (d.owner.login, d.name),
+                    d.releases
+                        .nodes
+                        .into_iter()
+                        .flat_map(|release| {
+                            release.release_assets.nodes.into_iter().map(move |asset| {
+                                asset_metadata(
+                                    release.name.clone(),
+                                    release.description.clone(),
+                                    if release.is_prerelease {
+                                        ReleaseChannel::Beta
+                                    } else {
+                                        ReleaseChannel::Release
+                                    },
+                                    asset.name,
+                                )
                             })
-                            .collect_vec(),
-                    )
-                })
-                .collect_vec()
-        };
+                        })
+                        .collect_vec(),
+                )
+            })
+            .collect_vec()
+    };
 
     let mut success_names = Vec::new();
 
@@ -278,10 +482,12 @@ pub async fn add(
         }
     }
 
-    for project in cf_projects {
-        if let Some(i) = cf_ids.iter().position(|&id| id == project.id) {
-            cf_ids.swap_remove(i);
-        }
+    for project in &cf_projects {
+        let Some(i) = cf_ids.iter().position(|&id| id == project.id) else {
+            // Only looked up for a pinned file
+            continue;
+        };
+        cf_ids.swap_remove(i);
 
         let name = to_name(
             &project.name,
@@ -290,7 +496,7 @@ pub async fn add(
                 .and_then(SourceKindWithModpack::from_cf_class_id),
         );
 
-        match curseforge(&project, profile, perform_checks, filters.clone()).await {
+        match curseforge(project, None, profile, perform_checks, filters.clone()).await {
             Ok(_) => success_names.push(name),
             Err(err) => errors.push((format!("{} ({})", name, project.id), err)),
         }
@@ -301,20 +507,56 @@ pub async fn add(
             .map(|id| (id.to_string(), Error::DoesNotExist)),
     );
 
-    for project in mr_projects {
-        if let Some(i) = mr_ids
+    for (id, pin) in cf_pins {
+        let project = cf_projects.iter().find(|project| project.id == id);
+        let file = cf_files
             .iter()
-            .position(|id| id == &project.id || project.slug.eq_ignore_ascii_case(id))
+            .find(|file| file.id == pin && file.mod_id == id);
+        let (Some(project), Some(file)) = (project, file) else {
+            errors.push((
+                SourceId::PinnedCurseforge(id, pin).to_string(),
+                Error::DoesNotExist,
+            ));
+            continue;
+        };
+
+        let name = to_name(
+            &project.name,
+            project
+                .class_id
+                .and_then(SourceKindWithModpack::from_cf_class_id),
+        );
+
+        match curseforge(
+            project,
+            Some(file),
+            profile,
+            perform_checks,
+            filters.clone(),
+        )
+        .await
         {
-            mr_ids.swap_remove(i);
+            Ok(_) => success_names.push(name),
+            Err(err) => errors.push((format!("{} ({})", name, project.id), err)),
         }
+    }
+
+    for project in &mr_projects {
+        let Some(i) = mr_ids
+            .iter()
+            .position(|id| id == &project.id || project.slug.eq_ignore_ascii_case(id))
+        else {
+            // Only looked up for a pinned version
+            continue;
+        };
+        mr_ids.swap_remove(i);
 
         let name = to_name(
             &project.title,
             SourceKindWithModpack::from_mr_project_type(project.project_type.clone()),
         );
 
-        match modrinth(&project, profile, perform_checks, filters.clone()).await {
+        match modrinth(project, None, profile, perform_checks, filters.clone()).await {
             Ok(_) => success_names.push(name),
             Err(err) => errors.push((format!("{} ({})", name, project.id), err)),
         }
@@ -325,13 +567,83 @@ pub async fn add(
             .map(|id| (id.to_string(), Error::DoesNotExist)),
     );
 
+    for (id, pin) in mr_pins {
+        let version = mr_versions.iter().find(|version| version.id == pin);
+        let project = version.and_then(|version| {
+            mr_projects.iter().find(|project| {
+                project.id == version.project_id
+                    && (project.id == id || project.slug.eq_ignore_ascii_case(&id))
+            })
+        });
+        let (Some(version), Some(project)) = (version, project) else {
+            errors.push((
+                SourceId::PinnedModrinth(id, pin).to_string(),
+                Error::DoesNotExist,
+            ));
+            continue;
+        };
+
+        let name = to_name(
+            &project.title,
+            SourceKindWithModpack::from_mr_project_type(project.project_type.clone()),
+        );
+
+        match modrinth(
+            project,
+            Some(version),
+            profile,
+            perform_checks,
+            filters.clone(),
+        )
+        .await
+        {
+            Ok(_) => success_names.push(name),
+            Err(err) => errors.push((format!("{} ({})", name, project.id), err)),
+        }
+    }
+
     for (repo, asset_names) in gh_repos {
-        match github(&repo, profile, Some(asset_names), filters.clone()).await {
+        match github(&repo, None, profile, Some(asset_names), filters.clone()).await {
             Ok(_) => success_names.push(format!("{}/{}", repo.0, repo.1)),
             Err(err) => errors.push((format!("{}/{}", repo.0, repo.1), err)),
         }
     }
 
+    // GitHub can't look up release assets in a batch
+    for ((owner, repo), pin) in gh_pins {
+        let result = async {
+            let asset = rate_limit::send(Host::Github, || async {
+                GITHUB_API
+                    .repos(&owner, &repo)
+                    .release_assets()
+                    .get(pin as u64)
+                    .await
+            })
+            .await?;
+            // Assets don't carry release metadata, so use the asset itself
+            let metadata = asset_metadata(
+                asset.name.clone(),
+                String::new(),
+                ReleaseChannel::Release,
+                asset.name,
+            );
+            github(
+                &(&owner, &repo),
+                Some(pin),
+                profile,
+                perform_checks.then(|| vec![metadata]),
+                filters.clone(),
+            )
+            .await
+        }
+        .await;
+
+        match result {
+            Ok(_) => success_names.push(format!("{owner}/{repo}")),
+            Err(err) => errors.push((format!("{owner}/{repo}"), err)),
+        }
+    }
+
     for path in file_ids {
         match file(&path, profile, perform_checks, filters.clone()).await {
             Ok(_) => success_names.push(format!("{}", path.display())),
@@ -353,8 +665,11 @@ pub async fn add(
 /// If so, add it to the `profile`.
 ///
 /// Returns the name of the repository to display to the user
+///
+/// If `pin` is set, the release asset with that ID is added instead of the repository's latest one.
 pub async fn github(
     id: &(impl AsRef<str> + ToString, impl AsRef<str> + ToString),
+    pin: Option<i32>,
     profile: &mut Profile,
     perform_checks: Option<Vec<Metadata>>,
     filters: Filters,
@@ -367,30 +682,41 @@ pub async fn github(
     let repo = id.0.as_ref().trim();
     let user = id.1.as_ref().trim();
 
+    let source = match pin {
+        Some(pin) => Source::from_id(
+            SourceId::PinnedGithub((repo.into(), user.into()), pin),
+            filters,
+        ),
+        None => Source::github(repo.into(), user.into(), filters),
+    };
+
     // Add it to the profile
-    profile.push(
-        SourceKind::Mods,
-        format!("{repo}/{user}"),
-        Source::github(repo.into(), user.into(), filters),
-    )?;
+    profile.push(SourceKind::Mods, format!("{repo}/{user}"), source)?;
 
     Ok(())
 }
 
-use ferinth::structures::project::{Project, ProjectType};
+use ferinth::structures::{
+    project::{Project, ProjectType},
+    version::Version,
+};
 
 /// Check if the project of `project_id` has not already been added and is compatible with `profile`.
 /// If so, add it to the `profile`.
+///
+/// If `pin` is set, that version is checked and added instead of the project's latest one.
 pub async fn modrinth(
     project: &Project,
+    pin: Option<&Version>,
     profile: &mut Profile,
     perform_checks: bool,
     filters: Filters,
 ) -> Result<()> {
     // Check if the project is compatible
     if perform_checks {
-        check::select_latest(
-            [Metadata {
+        let metadata = match pin {
+            Some(version) => from_mr_version(SourceKind::Mods, version.clone(), project).0,
+            None => Metadata {
                 filename: "".to_owned(),
                 title: "".to_owned(),
                 description: "".to_owned(),
@@ -401,15 +727,19 @@ pub async fn modrinth(
                     .filter_map(|s| ModLoader::from_str(s).ok())
                     .collect_vec(),
                 channel: ReleaseChannel::Release,
-            }]
-            .iter(),
-            vec![&profile.filters, &filters],
-        )
-        .await?;
+            },
+        };
+        check::select_latest([metadata].iter(), vec![&profile.filters, &filters]).await?;
     }
 
     let id = project.slug.clone();
-    let source = Source::modrinth(project.id.clone(), filters);
+    let source = match pin {
+        Some(version) => Source::from_id(
+            SourceId::PinnedModrinth(project.id.clone(), version.id.clone()),
+            filters,
+        ),
+        None => Source::modrinth(project.id.clone(), filters),
+    };
 
     // Add it to the profile
     let kind = SourceKindWithModpack::from_mr_project_type(project.project_type.clone())
@@ -421,8 +751,11 @@ pub async fn modrinth(
 
 /// Check if the mod of `project_id` has not already been added, is a mod, and is compatible with `profile`.
 /// If so, add it to the `profile`.
+///
+/// If `pin` is set, that file is checked and added instead of the project's latest one.
 pub async fn curseforge(
     project: &furse::structures::mod_structs::Mod,
+    pin: Option<&furse::structures::file_structs::File>,
     profile: &mut Profile,
     perform_checks: bool,
     filters: Filters,
@@ -434,8 +767,13 @@ pub async fn curseforge(
     // Check if the mod is compatible
     } else {
         if perform_checks {
-            check::select_latest(
-                [Metadata {
+            let metadata = match pin {
+                Some(file) => {
+                    try_from_cf_file(SourceKind::Mods, file.clone(), project.class_id)
+                        .map_err(|_| Error::DistributionDenied)?
+                        .0
+                }
+                None => Metadata {
                     filename: "".to_owned(),
                     title: "".to_owned(),
                     description: "".to_owned(),
@@ -454,13 +792,16 @@ pub async fn curseforge(
                         })
                         .collect_vec(),
                     channel: ReleaseChannel::Release,
-                }]
-                .iter(),
-                vec![&profile.filters, &filters],
-            )
-            .await?;
+                },
+            };
+            check::select_latest([metadata].iter(), vec![&profile.filters, &filters]).await?;
         }
 
+        let source = match pin {
+            Some(file) => Source::from_id(SourceId::PinnedCurseforge(project.id, file.id), filters),
+            None => Source::curseforge(project.id, filters),
+        };
+
         // Add it to the profile
         profile.push(
             project
@@ -469,7 +810,7 @@ pub async fn curseforge(
                 .ok_or(Error::UnsupportedClassId(project.class_id))?
                 .into(),
             project.slug.clone(),
-            Source::curseforge(project.id, filters),
+            source,
         )?;
 
         Ok(())
@@ -0,0 +1,193 @@
+use crate::{upgrade::Hash, PROJECT_DIRS};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, SystemTime},
+};
+
+/// How long project details are reused for
+pub const PROJECT_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+/// How long lists of versions, files, and releases are reused for
+pub const VERSIONS_TTL: Duration = Duration::from_secs(60 * 60);
+/// How long pinned versions, files, and assets are reused for, these rarely change
+pub const PINNED_TTL: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// Where API responses and downloaded files are cached, and how the cache is used
+#[derive(Debug, Clone)]
+pub struct Cache {
+    /// The directory the cache is stored in
+    pub dir: PathBuf,
+    /// Whether files and API responses may only come from the cache,
+    /// regardless of how old the cached responses are
+    pub offline: bool,
+    /// Whether cached lists of versions are fetched again regardless of their age,
+    /// so that the latest versions are resolved
+    pub refresh: bool,
+}
+
+impl Default for Cache {
+    fn default() -> Self {
+        Self {
+            dir: PROJECT_DIRS.cache_dir().to_owned(),
+            offline: false,
+            refresh: false,
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("{0} isn't cached, so it can't be fetched offline")]
+/// Contains a description of what wasn't cached
+pub struct NotCached(pub String);
+
+/// Escape `part` so that it can be used as a path component
+fn escape(part: &str) -> String {
+    part.bytes()
+        .map(|byte| {
+            if byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_' {
+                char::from(byte).to_string()
+            } else {
+                format!("%{byte:02x}")
+            }
+        })
+        .collect()
+}
+
+/// Create the file at `path` by calling `write` with a temporary path,
+/// so that parallel readers never see a partially written file
+fn write_atomically(path: &Path, write: impl FnOnce(&Path) -> io::Result<()>) -> io::Result<()> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let tmp_path = path.with_extension(format!(
+        "{}-{}.part",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    write(&tmp_path)?;
+    fs::rename(tmp_path, path)
+}
+
+impl Cache {
+    /// How long lists of versions, files, and releases are reused for, not at all if refreshing
+    pub fn versions_ttl(&self) -> Duration {
+        if self.refresh {
+            Duration::ZERO
+        } else {
+            VERSIONS_TTL
+        }
+    }
+
+    /// Get the API response cached under `key`, or `fetch` it and cache it for next time
+    ///
+    /// Cached responses older than `ttl` are fetched again, unless offline.
+    ///
+    /// # Errors
+    ///
+    /// If the response can't be fetched, or if offline and the response isn't cached.
+    pub async fn fetch<T, E, R>(
+        &self,
+        key: &[&str],
+        ttl: Duration,
+        fetch: impl std::future::Future<Output = Result<T, E>>,
+    ) -> Result<T, R>
+    where
+        T: Serialize + DeserializeOwned,
+        R: From<E> + From<NotCached>,
+    {
+        let mut path = self.dir.join("api");
+        path.extend(key.iter().map(|part| escape(part)));
+        let path = path.with_extension("json");
+
+        let fresh = fs::metadata(&path)
+            .and_then(|metadata| metadata.modified())
+            .is_ok_and(|modified| {
+                self.offline
+                    || SystemTime::now()
+                        .duration_since(modified)
+                        .is_ok_and(|age| age < ttl)
+            });
+        if fresh {
+            // A response that can't be read is fetched again
+            if let Some(cached) = fs::read(&path)
+                .ok()
+                .and_then(|json| serde_json::from_slice(&json).ok())
+            {
+                return Ok(cached);
+            }
+        }
+        if self.offline {
+            return Err(NotCached(key.join(" ")).into());
+        }
+
+        let response = fetch.await?;
+        if let Ok(json) = serde_json::to_vec(&response) {
+            // The cache is only an optimisation, so failing to write it isn't an error
+            let _ = write_atomically(&path, |tmp_path| fs::write(tmp_path, json));
+        }
+        Ok(response)
+    }
+
+    /// Get the paths a file with `hash` or `user_hash` would be stored at in the cache
+    ///
+    /// Only full length user hashes can be used to find a file.
+    pub fn file_paths(&self, hash: Option<&Hash>, user_hash: &[String]) -> Vec<PathBuf> {
+        let sha512 = hash
+            .and_then(Hash::sha512)
+            .into_iter()
+            .chain(
+                user_hash
+                    .iter()
+                    .map(String::as_str)
+                    .filter(|hash| hash.len() == 128),
+            )
+            .map(|hash| ("sha512", hash));
+        let sha1 = hash.and_then(Hash::sha1).map(|hash| ("sha1", hash));
+
+        sha512
+            .chain(sha1)
+            .filter(|(_, hash)| hash.bytes().all(|byte| byte.is_ascii_hexdigit()))
+            .map(|(algo, hash)| {
+                self.dir
+                    .join("files")
+                    .join(algo)
+                    .join(hash.to_ascii_lowercase())
+            })
+            .collect()
+    }
+}
+
+/// Find a cached file at one of the `paths` from [`Cache::file_paths`]
+pub fn find_file(paths: &[PathBuf]) -> Option<&PathBuf> {
+    paths.iter().find(|path| path.is_file())
+}
+
+/// Hard link the file at `from` to `to`, or copy it if they are on different filesystems
+///
+/// Copies still share their data on filesystems that support it, e.g. APFS, Btrfs, and XFS.
+///
+/// # Errors
+///
+/// If the file can neither be linked nor copied.
+pub fn link_file(from: &Path, to: &Path) -> io::Result<()> {
+    if fs::hard_link(from, to).is_err() {
+        fs::copy(from, to)?;
+    }
+    Ok(())
+}
+
+/// Store the file at `path` at the `cached` paths from [`Cache::file_paths`], linking it if possible
+///
+/// # Errors
+///
+/// If the file can't be linked or copied to the cache.
+pub fn store_file(path: &Path, cached: &[PathBuf]) -> io::Result<()> {
+    for cached_path in cached.iter().filter(|cached_path| !cached_path.exists()) {
+        write_atomically(cached_path, |tmp_path| link_file(path, tmp_path))?;
+    }
+    Ok(())
+}
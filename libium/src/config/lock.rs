@@ -0,0 +1,237 @@
+use super::structs::{Filters, Side, Source, SourceId, SourceKind, SourceKindWithModpack};
+use crate::upgrade::{DownloadData, DownloadSource, Hash, Metadata};
+use ferinth::structures::version::Hash as MRHash;
+use furse::structures::file_structs::{FileHash as CFHash, HashAlgo as CFHashAlgo};
+use md5::Digest as _;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+};
+use url::Url;
+
+/// The resolved files of a profile, written next to the profile file.
+///
+/// Upgrading installs the files recorded here instead of resolving the latest
+/// compatible version again, unless an update is explicitly requested.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct Lockfile {
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub mods: BTreeMap<String, LockedFile>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub shaders: BTreeMap<String, LockedFile>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub modpacks: BTreeMap<String, LockedFile>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub resourcepacks: BTreeMap<String, LockedFile>,
+}
+
+impl Lockfile {
+    pub fn map_mut(&mut self, kind: SourceKind) -> &mut BTreeMap<String, LockedFile> {
+        match kind {
+            SourceKind::Mods => &mut self.mods,
+            SourceKind::Resourcepacks => &mut self.resourcepacks,
+            SourceKind::Shaders => &mut self.shaders,
+            SourceKind::Modpacks => &mut self.modpacks,
+        }
+    }
+
+    pub fn map(&self, kind: SourceKind) -> &BTreeMap<String, LockedFile> {
+        match kind {
+            SourceKind::Mods => &self.mods,
+            SourceKind::Resourcepacks => &self.resourcepacks,
+            SourceKind::Shaders => &self.shaders,
+            SourceKind::Modpacks => &self.modpacks,
+        }
+    }
+
+    pub fn get(&self, kind: SourceKind, name: &str) -> Option<&LockedFile> {
+        self.map(kind).get(name)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        SourceKind::ARRAY
+            .iter()
+            .all(|kind| self.map(*kind).is_empty())
+    }
+}
+
+/// A single file resolved from a source.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct LockedFile {
+    /// A checksum of the source and filters the file was resolved with
+    ///
+    /// If the source or filters change, the file is resolved again.
+    pub checksum: String,
+    /// The pinned identifier of the resolved file, `None` if the file isn't from a platform
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<SourceId>,
+    /// The title of the resolved file
+    pub title: String,
+    pub url: Url,
+    /// Other URLs to download the file from if `url` fails, e.g. mirrors listed in a modpack
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fallback_urls: Vec<Url>,
+    /// The path of the file relative to the output directory
+    pub output: PathBuf,
+    /// The length of the file in bytes
+    pub length: u64,
+    #[serde(default, skip_serializing_if = "LockedHashes::is_empty")]
+    pub hashes: LockedHashes,
+    /// User-provided hashes in sha512 (base16) format
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub user_hashes: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dependencies: Vec<SourceId>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub optional_dependencies: Vec<SourceId>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub conflicts: Vec<SourceId>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<SourceKindWithModpack>,
+    /// The side of the game the file is exclusively for
+    #[serde(default, skip_serializing_if = "Side::is_both")]
+    pub side: Side,
+}
+
+/// The hashes provided by the platform for a file, in base16 format.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct LockedHashes {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha1: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha512: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub md5: Option<String>,
+}
+
+impl LockedHashes {
+    pub fn is_empty(&self) -> bool {
+        self.sha1.is_none() && self.sha512.is_none() && self.md5.is_none()
+    }
+}
+
+impl From<&Hash> for LockedHashes {
+    fn from(value: &Hash) -> Self {
+        match value {
+            Hash::Modrinth(hash) => Self {
+                sha1: Some(hash.sha1.clone()),
+                sha512: Some(hash.sha512.clone()),
+                md5: None,
+            },
+            Hash::Curseforge(hashes) => {
+                let mut locked = Self::default();
+                for hash in hashes {
+                    match hash.algo {
+                        CFHashAlgo::Sha1 => locked.sha1 = Some(hash.value.clone()),
+                        CFHashAlgo::Md5 => locked.md5 = Some(hash.value.clone()),
+                    }
+                }
+                locked
+            }
+        }
+    }
+}
+
+impl LockedHashes {
+    /// Convert back into a hash that can be checked when downloading
+    pub fn to_hash(&self) -> Option<Hash> {
+        match self {
+            Self {
+                sha1: Some(sha1),
+                sha512: Some(sha512),
+                ..
+            } => Some(Hash::Modrinth(MRHash {
+                sha512: sha512.clone(),
+                sha1: sha1.clone(),
+                others: HashMap::new(),
+            })),
+            Self { sha1, md5, .. } => {
+                let hashes = [(sha1, CFHashAlgo::Sha1), (md5, CFHashAlgo::Md5)]
+                    .into_iter()
+                    .filter_map(|(value, algo)| value.clone().map(|value| CFHash { value, algo }))
+                    .collect::<Vec<_>>();
+
+                if hashes.is_empty() {
+                    None
+                } else {
+                    Some(Hash::Curseforge(hashes))
+                }
+            }
+        }
+    }
+}
+
+impl LockedFile {
+    /// Create a locked file from resolved download data
+    ///
+    /// Returns `None` if the data isn't downloaded from a URL, e.g local files,
+    /// since those are always resolved again.
+    pub fn new(checksum: String, metadata: &Metadata, data: &DownloadData) -> Option<Self> {
+        let DownloadSource::Urls(urls) = &data.src else {
+            return None;
+        };
+        let (url, fallback_urls) = urls.split_first()?;
+
+        Some(Self {
+            checksum,
+            id: data.id.clone(),
+            title: metadata.title.clone(),
+            url: url.clone(),
+            fallback_urls: fallback_urls.to_vec(),
+            output: data.output.clone(),
+            length: data.length,
+            hashes: data.hash.as_ref().map(Into::into).unwrap_or_default(),
+            user_hashes: data.user_hash.clone(),
+            dependencies: data.dependencies.clone(),
+            optional_dependencies: data.optional_dependencies.clone(),
+            conflicts: data.conflicts.clone(),
+            kind: data.kind,
+            side: data.side,
+        })
+    }
+
+    pub fn filename(&self) -> String {
+        self.output
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string()
+    }
+
+    pub fn to_download_data(&self) -> DownloadData {
+        DownloadData {
+            id: self.id.clone(),
+            src: DownloadSource::Urls(
+                std::iter::once(&self.url)
+                    .chain(&self.fallback_urls)
+                    .cloned()
+                    .collect(),
+            ),
+            output: self.output.clone(),
+            length: self.length,
+            dependencies: self.dependencies.clone(),
+            optional_dependencies: self.optional_dependencies.clone(),
+            conflicts: self.conflicts.clone(),
+            kind: self.kind,
+            side: self.side,
+            hash: self.hashes.to_hash(),
+            user_hash: self.user_hashes.clone(),
+        }
+    }
+}
+
+/// Calculate the checksum of a `source` resolved with `filters`
+pub fn checksum(source: &Source, filters: &Filters) -> String {
+    let mut hasher = sha1::Sha1::new();
+    // Serialising these can't fail since they don't contain any maps with non-string keys
+    hasher.update(serde_json::to_string(&(source, filters)).unwrap_or_default());
+    base16ct::lower::encode_string(&hasher.finalize())
+}
+
+/// Get the path of the lockfile belonging to the profile at `profile_path`
+pub fn lockfile_path(profile_path: &Path) -> PathBuf {
+    profile_path.with_extension("lock")
+}
@@ -1,9 +1,10 @@
 mod legacy;
+pub mod lock;
 pub mod modpack;
 pub mod options;
 pub mod structs;
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     fs::{self, create_dir_all},
     io::Result,
     path::Path,
@@ -44,6 +45,21 @@ pub fn read_profile(path: impl AsRef<Path>) -> Result<Option<structs::Profile>>
     Ok(Some(profile))
 }
 
+/// Open the lockfile at `path` and deserialise it, or return an empty lockfile if it doesn't exist
+pub fn read_lockfile(path: impl AsRef<Path>) -> Result<lock::Lockfile> {
+    let file = match fs::read_to_string(path) {
+        Ok(file) => file,
+        Err(e) if matches!(e.kind(), std::io::ErrorKind::NotFound) => {
+            return Ok(lock::Lockfile::default())
+        }
+        Err(e) => return Err(e),
+    };
+
+    let lockfile: lock::Lockfile = toml::from_str(&file).map_err(invalid_data_to_io)?;
+
+    Ok(lockfile)
+}
+
 /// Serialise `config` and write it to the config file at `path`
 pub fn write_config(path: impl AsRef<Path>, config: &structs::Config) -> Result<()> {
     let contents = toml::to_string(config).map_err(invalid_data_to_io)?;
@@ -58,6 +74,13 @@ pub fn write_profile(path: impl AsRef<Path>, profile: &structs::Profile) -> Resu
     Ok(())
 }
 
+/// Serialise `lockfile` and write it to the lockfile at `path`
+pub fn write_lockfile(path: impl AsRef<Path>, lockfile: &lock::Lockfile) -> Result<()> {
+    let contents = toml::to_string(lockfile).map_err(invalid_data_to_io)?;
+    fs::write(path, contents)?;
+    Ok(())
+}
+
 pub fn migrate_legacy_config(
     old_config_path: impl AsRef<Path>,
 ) -> std::result::Result<(), MigrateError> {
@@ -77,9 +100,13 @@ pub fn migrate_legacy_config(
 
         let profile = structs::Profile {
             filters: legacy::migrate_filters(filters)?,
+            side: structs::Side::Both,
+            loader_version: None,
             imports: Vec::new(),
             options: OptionsOverrides::default(),
             overrides: None,
+            exclude_dependencies: Vec::new(),
+            mirrors: BTreeMap::new(),
             mods: {
                 let mut mods = HashMap::new();
                 for mod_ in legacy_mods {
@@ -114,9 +141,13 @@ pub fn migrate_legacy_config(
 
         let profile = structs::Profile {
             filters: structs::Filters::empty(),
+            side: structs::Side::Both,
+            loader_version: None,
             imports: Vec::new(),
             options: OptionsOverrides::default(),
             overrides: None,
+            exclude_dependencies: Vec::new(),
+            mirrors: BTreeMap::new(),
             mods: HashMap::new(),
             shaders: HashMap::new(),
             modpacks: HashMap::from([(legacy_modpack.name.clone(), source)]),
@@ -128,6 +159,8 @@ pub fn migrate_legacy_config(
             config: structs::ProfileItemConfig {
                 name: legacy_modpack.name,
                 minecraft_dir,
+                side: None,
+                mirrors: BTreeMap::new(),
             },
         })
     }
@@ -135,6 +168,8 @@ pub fn migrate_legacy_config(
     let config = structs::Config {
         active_profile: config.active_profile,
         profiles,
+        concurrency: structs::Concurrency::default(),
+        loader_servers: structs::LoaderServers::default(),
     };
 
     let mut out_config = old_config_path.as_ref().to_path_buf();
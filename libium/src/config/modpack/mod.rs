@@ -1,6 +1,10 @@
-use std::io::{Read, Seek};
+use std::{
+    fs::{self, File},
+    io::{self, Read, Seek, Write},
+    path::Path,
+};
 
-use zip::{result::ZipResult, ZipArchive};
+use zip::{result::ZipResult, write::SimpleFileOptions, ZipArchive, ZipWriter};
 
 pub use zip_extensions::zip_extract;
 
@@ -20,3 +24,50 @@ pub fn read_file_from_zip(input: impl Read + Seek, file_name: &str) -> ZipResult
     };
     ret
 }
+
+/// The contents of a file written to a modpack
+pub enum ZipEntry<'a> {
+    Contents(&'a [u8]),
+    /// A file or a directory, directories are written recursively
+    Path(&'a Path),
+}
+
+/// Write a zip file to `output` containing each of the `entries` at its path
+pub fn write_zip<'a>(
+    output: impl Write + Seek,
+    entries: impl IntoIterator<Item = (&'a Path, ZipEntry<'a>)>,
+) -> ZipResult<()> {
+    let mut zip = ZipWriter::new(output);
+    for (name, entry) in entries {
+        match entry {
+            ZipEntry::Contents(contents) => {
+                zip.start_file(zip_name(name), SimpleFileOptions::default())?;
+                zip.write_all(contents)?;
+            }
+            ZipEntry::Path(path) => write_path(&mut zip, name, path)?,
+        }
+    }
+    zip.finish()?;
+    Ok(())
+}
+
+fn write_path(zip: &mut ZipWriter<impl Write + Seek>, name: &Path, path: &Path) -> ZipResult<()> {
+    if path.is_dir() {
+        for entry in fs::read_dir(path)? {
+            let entry = entry?;
+            write_path(zip, &name.join(entry.file_name()), &entry.path())?;
+        }
+    } else {
+        zip.start_file(zip_name(name), SimpleFileOptions::default())?;
+        io::copy(&mut File::open(path)?, zip)?;
+    }
+    Ok(())
+}
+
+/// Zip files always separate paths with forward slashes
+fn zip_name(path: &Path) -> String {
+    path.components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
@@ -1,5 +1,6 @@
 use std::{collections::HashMap, path::PathBuf};
 
+use crate::config::structs::Side;
 use ferinth::structures::{project::ProjectSupportRange, version::Hash, Int};
 use serde::{Deserialize, Serialize};
 use url::Url;
@@ -51,8 +52,26 @@ pub struct ModpackFile {
 #[derive(Deserialize, Serialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct ModpackFileEnvironment {
-    client: ProjectSupportRange,
-    server: ProjectSupportRange,
+    pub client: ProjectSupportRange,
+    pub server: ProjectSupportRange,
+}
+
+impl ModpackFileEnvironment {
+    /// The environment of a file that is exclusively for `side`, `None` if it is for both sides
+    pub fn of(side: Side) -> Option<Self> {
+        let (client, server) = match side {
+            Side::Client => (
+                ProjectSupportRange::Required,
+                ProjectSupportRange::Unsupported,
+            ),
+            Side::Server => (
+                ProjectSupportRange::Unsupported,
+                ProjectSupportRange::Required,
+            ),
+            Side::Both => return None,
+        };
+        Some(Self { client, server })
+    }
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
@@ -10,7 +10,7 @@ use thiserror::Error;
 
 /// Struct for processing the `.minecraft/options.txt` file.
 /// Use the [Options::read] and [Options::write] methods for serialization and deserialization.
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct Options {
     fields: HashMap<String, String>,
 }
@@ -72,18 +72,35 @@ impl Options {
         iter.sort();
 
         for (key, value) in iter {
-            // Remove string quotes since that breaks the options file for some reason.
-            let value = if value.starts_with('"') && value.ends_with('"') {
-                &value[1..value.len() - 1]
-            } else {
-                value
-            };
-
-            writeln!(write, "{key}:{value}")?;
+            writeln!(write, "{key}:{}", unquote(value))?;
         }
         Ok(())
     }
 
+    /// Get the fields that are different in `other`, sorted by key.
+    ///
+    /// Returns the key, the value in `self` and the value in `other`, `None` if the field is missing.
+    pub fn diff<'a>(&'a self, other: &'a Self) -> Vec<(&'a str, Option<&'a str>, Option<&'a str>)> {
+        let mut diff = vec![];
+
+        for (key, value) in &other.fields {
+            let old = self.fields.get(key).map(|value| unquote(value));
+            let new = unquote(value);
+            if old != Some(new) {
+                diff.push((key.as_str(), old, Some(new)));
+            }
+        }
+
+        for (key, value) in &self.fields {
+            if !other.fields.contains_key(key) {
+                diff.push((key.as_str(), Some(unquote(value)), None));
+            }
+        }
+
+        diff.sort_by_key(|(key, _, _)| *key);
+        diff
+    }
+
     /// Read the `options.txt` file from a reader.
     pub fn read<R: io::BufRead>(
         reader: R,
@@ -110,6 +127,15 @@ impl Options {
     }
 }
 
+/// Remove string quotes since that breaks the options file for some reason.
+fn unquote(value: &str) -> &str {
+    if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+        &value[1..value.len() - 1]
+    } else {
+        value
+    }
+}
+
 /// Overrides defined in the profile that can be applied to `options.txt`. See [Options::apply].
 #[derive(Serialize, Deserialize, Default, Debug, Clone)]
 #[serde(rename_all = "kebab-case")]
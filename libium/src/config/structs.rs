@@ -1,16 +1,17 @@
 use crate::add;
 
 use derive_more::derive::Display;
-use ferinth::structures::project::ProjectType;
+use ferinth::structures::project::{ProjectSupportRange, ProjectType};
 use semver::Prerelease;
 use serde::{de::Visitor, Deserialize, Serialize};
 use std::{
-    collections::{hash_map::Entry, HashMap},
+    collections::{hash_map::Entry, BTreeMap, HashMap},
     env::current_dir,
     fmt,
     fs::File,
     io,
     marker::PhantomData,
+    num::NonZeroUsize,
     ops::{Deref, DerefMut},
     path::{Path, PathBuf},
     str::FromStr,
@@ -30,6 +31,130 @@ pub struct Config {
     #[serde(skip_serializing_if = "Vec::is_empty")]
     #[serde(default)]
     pub profiles: Vec<ProfileItem>,
+
+    /// How many tasks for each host can run at the same time
+    #[serde(default, skip_serializing_if = "Concurrency::is_default")]
+    pub concurrency: Concurrency,
+
+    /// The servers mod loaders are installed from
+    #[serde(default, skip_serializing_if = "LoaderServers::is_default")]
+    pub loader_servers: LoaderServers,
+}
+
+/// The base URLs of the servers mod loaders are installed from, which can be replaced with mirrors
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct LoaderServers {
+    /// The Fabric metadata server
+    pub fabric_meta: String,
+    /// The Quilt metadata server
+    pub quilt_meta: String,
+    /// The server with the Forge versions for each Minecraft version
+    pub forge_files: String,
+    /// The maven repository with the Forge installers
+    pub forge_maven: String,
+    /// The maven repository with the `NeoForge` versions and installers
+    pub neoforge_maven: String,
+}
+
+impl Default for LoaderServers {
+    fn default() -> Self {
+        Self {
+            fabric_meta: "https://meta.fabricmc.net/v2".to_owned(),
+            quilt_meta: "https://meta.quiltmc.org/v3".to_owned(),
+            forge_files: "https://files.minecraftforge.net".to_owned(),
+            forge_maven: "https://maven.minecraftforge.net".to_owned(),
+            neoforge_maven: "https://maven.neoforged.net".to_owned(),
+        }
+    }
+}
+
+impl LoaderServers {
+    fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// The limits on how many requests and downloads for each host can run at the same time
+///
+/// A limit of 0 would never let anything run, so it is rejected when reading the config.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct Concurrency {
+    pub modrinth: NonZeroUsize,
+    pub curseforge: NonZeroUsize,
+    /// GitHub's secondary rate limits are tripped by many simultaneous requests
+    pub github: NonZeroUsize,
+    /// Any other host, e.g. the URL of a file
+    pub other: NonZeroUsize,
+}
+
+impl Default for Concurrency {
+    fn default() -> Self {
+        let limit = |limit| NonZeroUsize::new(limit).expect("The default limits are not 0");
+        Self {
+            modrinth: limit(20),
+            curseforge: limit(20),
+            github: limit(4),
+            other: limit(8),
+        }
+    }
+}
+
+impl Concurrency {
+    fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Get the limit for `host`
+    pub fn limit(&self, host: Host) -> NonZeroUsize {
+        match host {
+            Host::Modrinth => self.modrinth,
+            Host::Curseforge => self.curseforge,
+            Host::Github => self.github,
+            Host::Other => self.other,
+        }
+    }
+}
+
+/// The hosts that have separate concurrency limits
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Host {
+    Modrinth,
+    #[display("CurseForge")]
+    Curseforge,
+    #[display("GitHub")]
+    Github,
+    #[display("other hosts")]
+    Other,
+}
+
+impl Host {
+    /// Get the host `url` is served by, including the CDNs of each platform
+    pub fn of_url(url: &Url) -> Self {
+        let domain = url.host_str().unwrap_or_default();
+        let is = |suffix: &str| domain == suffix || domain.ends_with(&format!(".{suffix}"));
+        if is("modrinth.com") {
+            Self::Modrinth
+        } else if is("curseforge.com") || is("forgecdn.net") {
+            Self::Curseforge
+        } else if is("github.com") || is("githubusercontent.com") {
+            Self::Github
+        } else {
+            Self::Other
+        }
+    }
+
+    /// Get the host that resolving `source` makes requests to
+    pub fn of_source(source: &Source) -> Self {
+        match source.ids().next() {
+            Some(SourceId::Modrinth(_) | SourceId::PinnedModrinth(..)) => Self::Modrinth,
+            Some(SourceId::Curseforge(_) | SourceId::PinnedCurseforge(..)) => Self::Curseforge,
+            Some(SourceId::Github(..) | SourceId::PinnedGithub(..)) => Self::Github,
+            Some(SourceId::Url(url)) => Self::of_url(url),
+            Some(SourceId::File(_)) | None => Self::Other,
+        }
+    }
 }
 
 #[derive(Deserialize, Serialize, Debug)]
@@ -48,6 +173,13 @@ pub struct ProfileItemConfig {
     pub name: String,
     /// The `.minecraft` directory to download mod files to
     pub minecraft_dir: PathBuf,
+    /// The side to install files for, overriding the profile's side
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub side: Option<Side>,
+    /// URL prefixes mapped to the prefixes of mirrors to download from first,
+    /// in addition to and overriding the profile's mirrors
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub mirrors: BTreeMap<String, String>,
 }
 
 /// The path to the profile `.toml` file or the profile data itself.
@@ -145,6 +277,8 @@ impl ProfileItem {
             config: ProfileItemConfig {
                 name,
                 minecraft_dir,
+                side: None,
+                mirrors: BTreeMap::new(),
             },
         }
     }
@@ -176,12 +310,27 @@ const fn is_zero(n: &usize) -> bool {
 pub struct Profile {
     #[serde(flatten)]
     pub filters: Filters,
+    /// The side to install files for, files unsupported on it are skipped
+    #[serde(default, skip_serializing_if = "Side::is_both")]
+    pub side: Side,
+    /// The version of the mod loader to use, e.g. `=0.16.5` or `0.16`
+    ///
+    /// Mods are checked against it when it pins a single version.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub loader_version: Option<Version>,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub imports: Vec<ProfileImport>,
     #[serde(default, skip_serializing_if = "OptionsOverrides::is_empty")]
     pub options: OptionsOverrides,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub overrides: Option<Overrides>,
+    /// Dependencies that shouldn't be downloaded automatically, e.g because they are supplied by overrides
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude_dependencies: Vec<ExcludedDependency>,
+    /// URL prefixes mapped to the prefixes of mirrors to download from first,
+    /// e.g. `"https://cdn.modrinth.com/" = "https://mirror.example.com/modrinth/"`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub mirrors: BTreeMap<String, String>,
     #[serde(default, skip_serializing_if = "HashMap::is_empty")]
     pub mods: HashMap<String, Source>,
     #[serde(default, skip_serializing_if = "HashMap::is_empty")]
@@ -192,6 +341,24 @@ pub struct Profile {
     pub resourcepacks: HashMap<String, Source>,
 }
 
+/// A dependency excluded from being downloaded automatically
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum ExcludedDependency {
+    Id(SourceId),
+    /// A Modrinth project slug or ID, or a CurseForge project ID
+    Slug(String),
+}
+
+impl fmt::Display for ExcludedDependency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExcludedDependency::Id(id) => write!(f, "{id}"),
+            ExcludedDependency::Slug(slug) => write!(f, "{slug}"),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(untagged)]
 pub enum ProfileImport {
@@ -244,20 +411,29 @@ impl Profile {
 impl Profile {
     /// A simple contructor that automatically deals with converting to filters
     pub fn new(versions: Option<Vec<Version>>, mod_loader: ModLoader) -> Self {
-        Self {
-            filters: Filters {
-                versions,
-                mod_loaders: match mod_loader {
-                    ModLoader::Fabric | ModLoader::Quilt => {
-                        Some(vec![ModLoader::Fabric, ModLoader::Quilt])
-                    }
-                    mod_loader => Some(vec![mod_loader]),
-                },
-                ..Filters::empty()
+        Self::with_filters(Filters {
+            versions,
+            mod_loaders: match mod_loader {
+                ModLoader::Fabric | ModLoader::Quilt => {
+                    Some(vec![ModLoader::Fabric, ModLoader::Quilt])
+                }
+                mod_loader => Some(vec![mod_loader]),
             },
+            ..Filters::empty()
+        })
+    }
+
+    /// Create an empty profile checking compatibility with `filters`
+    pub fn with_filters(filters: Filters) -> Self {
+        Self {
+            filters,
+            side: Side::Both,
+            loader_version: None,
             imports: Vec::new(),
             options: OptionsOverrides::default(),
             overrides: None,
+            exclude_dependencies: Vec::new(),
+            mirrors: BTreeMap::new(),
             mods: HashMap::new(),
             shaders: HashMap::new(),
             modpacks: HashMap::new(),
@@ -285,12 +461,14 @@ impl Profile {
 
     pub fn push(&mut self, kind: SourceKind, id: String, source: Source) -> Result<(), add::Error> {
         let map = self.map_mut(kind);
+        // Different versions of a project are still the same project
+        let project = |id: &SourceId| id.unpinned().unwrap_or_else(|| id.clone());
 
         for source_id in source.ids() {
             let has_duplicates = map
-                .iter()
-                .flat_map(|(_, source)| source.ids())
-                .any(|mod_id| mod_id == source_id);
+                .values()
+                .flat_map(|source| source.ids())
+                .any(|mod_id| project(mod_id) == project(source_id));
             if has_duplicates {
                 return Err(add::Error::AlreadyAdded);
             }
@@ -325,20 +503,20 @@ impl Profile {
     pub fn ids(&self) -> impl Iterator<Item = (SourceKind, &SourceId)> {
         let mod_ids = self
             .mods
-            .iter()
-            .flat_map(|(_, source)| source.ids().map(|id| (SourceKind::Mods, id)));
+            .values()
+            .flat_map(|source| source.ids().map(|id| (SourceKind::Mods, id)));
         let resourcepack_ids = self
             .resourcepacks
-            .iter()
-            .flat_map(|(_, source)| source.ids().map(|id| (SourceKind::Resourcepacks, id)));
+            .values()
+            .flat_map(|source| source.ids().map(|id| (SourceKind::Resourcepacks, id)));
         let shaderpack_ids = self
             .shaders
-            .iter()
-            .flat_map(|(_, source)| source.ids().map(|id| (SourceKind::Shaders, id)));
+            .values()
+            .flat_map(|source| source.ids().map(|id| (SourceKind::Shaders, id)));
         let modpack_ids = self
             .modpacks
-            .iter()
-            .flat_map(|(_, source)| source.ids().map(|id| (SourceKind::Modpacks, id)));
+            .values()
+            .flat_map(|source| source.ids().map(|id| (SourceKind::Modpacks, id)));
         mod_ids
             .chain(resourcepack_ids)
             .chain(shaderpack_ids)
@@ -460,6 +638,20 @@ pub enum SourceId {
     PinnedGithub((String, String), i32),
 }
 
+impl SourceId {
+    /// Get the identifier of the project this is a pinned version of, `None` if it isn't pinned
+    pub fn unpinned(&self) -> Option<SourceId> {
+        match self {
+            SourceId::PinnedCurseforge(id, _) => Some(SourceId::Curseforge(*id)),
+            SourceId::PinnedModrinth(id, _) => Some(SourceId::Modrinth(id.clone())),
+            SourceId::PinnedGithub((owner, repo), _) => {
+                Some(SourceId::Github(owner.clone(), repo.clone()))
+            }
+            _ => None,
+        }
+    }
+}
+
 impl fmt::Display for SourceId {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -493,6 +685,14 @@ impl<'de> Deserialize<'de> for SourceId {
     }
 }
 
+impl FromStr for SourceId {
+    type Err = serde::de::value::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        SourceTagVisitor.visit_str(s)
+    }
+}
+
 struct SourceTagVisitor;
 
 impl<'de> Visitor<'de> for SourceTagVisitor {
@@ -608,7 +808,8 @@ impl<'a> Iterator for SourceIdsIter<'a> {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
 pub enum SourceKind {
     Mods,
     Resourcepacks,
@@ -654,7 +855,8 @@ pub fn check_exists<R: io::Read + io::Seek>(
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
 pub enum SourceKindWithModpack {
     Mods,
     Resourcepacks,
@@ -746,6 +948,11 @@ pub struct Filters {
     #[serde(default, with = "MaybeListOrSingle")]
     #[serde(alias = "hash")]
     pub hashes: Option<Vec<String>>,
+    /// Whether to also download the optional dependencies of the source
+    ///
+    /// Only applies to the profile's own sources, the optional dependencies of dependencies are never downloaded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub optional_dependencies: Option<bool>,
 }
 
 #[derive(Deserialize)]
@@ -807,6 +1014,7 @@ impl Filters {
             description: concat_opts(self.description, other.description),
             install_overrides: other.install_overrides.or(self.install_overrides),
             hashes: concat_opts(self.hashes, other.hashes),
+            optional_dependencies: other.optional_dependencies.or(self.optional_dependencies),
         }
     }
 
@@ -869,6 +1077,7 @@ impl Filters {
             && self.description.is_none()
             && self.install_overrides.is_none()
             && self.hashes.is_none()
+            && self.optional_dependencies.is_none()
     }
 }
 
@@ -1016,6 +1225,60 @@ impl Version {
             .any(|comp| matches!(comp.op, semver::Op::Exact) && comp.minor.is_some())
     }
 
+    /// The version this requirement pins, if it is a single strict requirement
+    ///
+    /// A missing patch is left out, e.g `=1.21` is Minecraft 1.21.
+    pub fn exact(&self) -> Option<String> {
+        let [comp] = self.0.comparators.as_slice() else {
+            return None;
+        };
+        if !matches!(comp.op, semver::Op::Exact) {
+            return None;
+        }
+        let mut version = format!("{}.{}", comp.major, comp.minor?);
+        if let Some(patch) = comp.patch {
+            version.push_str(&format!(".{patch}"));
+        }
+        if !comp.pre.is_empty() {
+            version.push_str(&format!("-{}", comp.pre));
+        }
+        Some(version)
+    }
+
+    /// The highest version this requirement allows, or `None` if it has no upper bound
+    ///
+    /// Parts that can be anything, e.g. the patch of `0.16.x`, are the largest possible number.
+    pub fn upper_bound(&self) -> Option<String> {
+        use semver::Op;
+
+        self.0
+            .comparators
+            .iter()
+            .filter_map(|comp| {
+                let (major, minor, patch) = (comp.major, comp.minor, comp.patch);
+                let any = u64::MAX;
+                match comp.op {
+                    Op::Exact | Op::LessEq | Op::Wildcard => {
+                        Some((major, minor.unwrap_or(any), patch.unwrap_or(any)))
+                    }
+                    Op::Tilde => Some((major, minor.unwrap_or(any), any)),
+                    Op::Caret => match (major, minor, patch) {
+                        (0, Some(0), Some(patch)) => Some((0, 0, patch)),
+                        (0, Some(minor), _) => Some((0, minor, any)),
+                        _ => Some((major, any, any)),
+                    },
+                    Op::Less => match (minor.unwrap_or(0), patch.unwrap_or(0)) {
+                        (minor, 1..) => Some((major, minor, patch.unwrap_or(0) - 1)),
+                        (1.., 0) => Some((major, minor.unwrap_or(0) - 1, any)),
+                        (0, 0) => major.checked_sub(1).map(|major| (major, any, any)),
+                    },
+                    _ => None,
+                }
+            })
+            .min()
+            .map(|(major, minor, patch)| format!("{major}.{minor}.{patch}"))
+    }
+
     pub fn into_req(self) -> semver::VersionReq {
         self.0
     }
@@ -1067,6 +1330,41 @@ impl FromStr for ModLoader {
     }
 }
 
+/// The side of the game files are installed for
+#[derive(
+    Deserialize, Serialize, Debug, Display, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum Side {
+    Client,
+    Server,
+    #[default]
+    Both,
+}
+
+impl Side {
+    pub fn is_both(&self) -> bool {
+        *self == Self::Both
+    }
+
+    /// Whether a file for the `other` side should be installed on this side
+    pub fn includes(self, other: Self) -> bool {
+        self == Self::Both || other == Self::Both || self == other
+    }
+
+    /// Get the side a project is exclusively for from its support on each side
+    ///
+    /// Projects unsupported on both sides, or whose support is unknown, are treated as supporting both.
+    pub fn from_support(client: &ProjectSupportRange, server: &ProjectSupportRange) -> Self {
+        match (client, server) {
+            (ProjectSupportRange::Unsupported, ProjectSupportRange::Unsupported) => Self::Both,
+            (ProjectSupportRange::Unsupported, _) => Self::Server,
+            (_, ProjectSupportRange::Unsupported) => Self::Client,
+            _ => Self::Both,
+        }
+    }
+}
+
 #[derive(Deserialize, Serialize, Debug, Display, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
 pub enum ReleaseChannel {
     Release,
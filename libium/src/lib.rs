@@ -1,33 +1,75 @@
 pub mod add;
+pub mod cache;
 pub mod config;
 pub mod iter_ext;
+pub mod loader;
+pub mod rate_limit;
 pub mod scan;
+pub mod search;
 pub mod upgrade;
 pub mod version_ext;
 
 pub use add::add;
 pub use scan::scan;
 
+use config::structs::Host;
 use directories::{BaseDirs, ProjectDirs};
+use hyper_util::rt::TokioExecutor;
 use std::{
     fs, io,
     path::{Path, PathBuf},
-    sync::LazyLock,
+    sync::{Arc, LazyLock},
 };
+use tower::retry::RetryLayer;
+use tower_http::follow_redirect::FollowRedirectLayer;
 
+/// The GitHub API client, built like octocrab's default client with the rate limit headers recorded
 pub static GITHUB_API: LazyLock<octocrab::Octocrab> = LazyLock::new(|| {
-    let mut github = octocrab::OctocrabBuilder::new();
-    if let Ok(token) = std::env::var("GITHUB_TOKEN") {
-        github = github.personal_token(token);
-    }
-    github.build().expect("Could not build GitHub client")
+    use octocrab::service::middleware::{
+        auth_header::AuthHeaderLayer, base_uri::BaseUriLayer, extra_headers::ExtraHeadersLayer,
+        retry::RetryConfig,
+    };
+
+    let connector = hyper_rustls::HttpsConnectorBuilder::new()
+        .with_native_roots()
+        .expect("Could not load the native root certificates")
+        .https_or_http()
+        .enable_http1()
+        .build();
+    let client = hyper_util::client::legacy::Client::builder(TokioExecutor::new()).build(connector);
+    let auth_header = std::env::var("GITHUB_TOKEN").ok().map(|token| {
+        format!("Bearer {token}")
+            .parse()
+            .expect("Invalid GitHub token")
+    });
+    let base_uri = http::Uri::from_static("https://api.github.com");
+    let upload_uri = http::Uri::from_static("https://uploads.github.com");
+
+    octocrab::OctocrabBuilder::new_empty()
+        .with_service(client)
+        .with_layer(&rate_limit::RecordLayer(Host::Github))
+        .with_layer(&RetryLayer::new(RetryConfig::Simple(3)))
+        .with_layer(&FollowRedirectLayer::new())
+        .with_layer(&ExtraHeadersLayer::new(Arc::new(vec![(
+            http::header::USER_AGENT,
+            http::HeaderValue::from_static("octocrab"),
+        )])))
+        .with_layer(&BaseUriLayer::new(base_uri.clone()))
+        .with_layer(&AuthHeaderLayer::new(auth_header, base_uri, upload_uri))
+        .with_auth(octocrab::AuthState::None)
+        .build()
+        .expect("Could not build GitHub client")
 });
 
-pub static CURSEFORGE_API: LazyLock<furse::Furse> = LazyLock::new(|| {
-    furse::Furse::new(&std::env::var("CURSEFORGE_API_KEY").unwrap_or(String::from(
+/// Get the CurseForge API key from the `CURSEFORGE_API_KEY` environment variable, or use ferium's own
+fn curseforge_api_key() -> String {
+    std::env::var("CURSEFORGE_API_KEY").unwrap_or(String::from(
         "$2a$10$sI.yRk4h4R49XYF94IIijOrO4i3W3dAFZ4ssOlNE10GYrDhc2j8K.",
-    )))
-});
+    ))
+}
+
+pub static CURSEFORGE_API: LazyLock<furse::Furse> =
+    LazyLock::new(|| furse::Furse::new(&curseforge_api_key()));
 
 pub static MODRINTH_API: LazyLock<ferinth::Ferinth> = LazyLock::new(|| {
     ferinth::Ferinth::new(
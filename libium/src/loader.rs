@@ -0,0 +1,302 @@
+use crate::config::{
+    modpack::read_file_from_zip,
+    structs::{LoaderServers, ModLoader, Version},
+};
+use serde::Deserialize;
+use std::{collections::HashMap, fs::File, io::BufReader, path::Path};
+
+#[derive(Debug, thiserror::Error)]
+#[error(transparent)]
+pub enum Error {
+    ReqwestError(#[from] reqwest::Error),
+    IOError(#[from] std::io::Error),
+    ZipError(#[from] zip::result::ZipError),
+    #[error("There is no compatible {0} version for Minecraft {1}")]
+    NoVersion(ModLoader, String),
+}
+type Result<T> = std::result::Result<T, Error>;
+
+/// Get the base URL of a server without a trailing slash
+fn endpoint(url: &str) -> &str {
+    url.trim_end_matches('/')
+}
+
+/// Get the base URL of the Fabric or Quilt metadata server
+fn meta_endpoint(servers: &LoaderServers, loader: ModLoader) -> &str {
+    if loader == ModLoader::Fabric {
+        endpoint(&servers.fabric_meta)
+    } else {
+        endpoint(&servers.quilt_meta)
+    }
+}
+
+#[derive(Deserialize)]
+struct LoaderEntry {
+    loader: LoaderVersion,
+}
+
+#[derive(Deserialize)]
+struct LoaderVersion {
+    version: String,
+    /// Quilt doesn't mark its versions as stable
+    #[serde(default = "stable_default")]
+    stable: bool,
+}
+
+const fn stable_default() -> bool {
+    true
+}
+
+#[derive(Deserialize)]
+struct ForgePromotions {
+    promos: HashMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct MavenVersions {
+    versions: Vec<String>,
+}
+
+/// Get the latest version of `loader` for Minecraft `game_version` that meets the `requirement` from the `servers`
+///
+/// Stable and recommended versions are preferred over the latest one.
+pub async fn latest_version(
+    servers: &LoaderServers,
+    loader: ModLoader,
+    game_version: &str,
+    requirement: Option<&Version>,
+) -> Result<String> {
+    let meets = |version: &str| requirement.iter().all(|req| req.matches(version));
+    let client = reqwest::Client::new();
+    let version = match loader {
+        ModLoader::Fabric | ModLoader::Quilt => {
+            let entries: Vec<LoaderEntry> = client
+                .get(format!(
+                    "{}/versions/loader/{game_version}",
+                    meta_endpoint(servers, loader)
+                ))
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            // The versions are sorted from newest to oldest
+            let entries = entries
+                .iter()
+                .filter(|entry| meets(&entry.loader.version))
+                .collect::<Vec<_>>();
+            let stable = entries
+                .iter()
+                .find(|entry| entry.loader.stable)
+                .or(entries.first());
+            stable.map(|entry| entry.loader.version.clone())
+        }
+        ModLoader::Forge => {
+            let mut promotions: ForgePromotions = client
+                .get(format!(
+                    "{}/net/minecraftforge/forge/promotions_slim.json",
+                    endpoint(&servers.forge_files)
+                ))
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            ["recommended", "latest"]
+                .into_iter()
+                .filter_map(|promo| promotions.promos.remove(&format!("{game_version}-{promo}")))
+                .find(|version| meets(version))
+        }
+        ModLoader::NeoForge => {
+            // NeoForge versions start with the minor and patch version of Minecraft, e.g. 21.1.x for 1.21.1
+            let mut parts = game_version.split('.').skip(1);
+            let prefix = format!(
+                "{}.{}.",
+                parts.next().unwrap_or_default(),
+                parts.next().unwrap_or("0")
+            );
+            let versions: MavenVersions = client
+                .get(format!(
+                    "{}/api/maven/versions/releases/net/neoforged/neoforge",
+                    endpoint(&servers.neoforge_maven)
+                ))
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            // The versions are sorted from oldest to newest
+            versions.versions.into_iter().rev().find(|version| {
+                version.starts_with(&prefix) && !version.contains("beta") && meets(version)
+            })
+        }
+    };
+    version.ok_or_else(|| Error::NoVersion(loader, game_version.to_owned()))
+}
+
+/// Get the name of the directory in `.minecraft/versions` that `loader_version` of `loader` is installed to
+pub fn version_id(loader: ModLoader, game_version: &str, loader_version: &str) -> String {
+    match loader {
+        ModLoader::Fabric => format!("fabric-loader-{loader_version}-{game_version}"),
+        ModLoader::Quilt => format!("quilt-loader-{loader_version}-{game_version}"),
+        ModLoader::Forge => format!("{game_version}-forge-{loader_version}"),
+        ModLoader::NeoForge => format!("neoforge-{loader_version}"),
+    }
+}
+
+/// Get the launcher version JSON of `loader_version` of Fabric or Quilt for Minecraft `game_version` from the `servers`
+///
+/// Forge and NeoForge don't provide one, their installer has to be run instead.
+pub async fn version_json(
+    servers: &LoaderServers,
+    loader: ModLoader,
+    game_version: &str,
+    loader_version: &str,
+) -> Result<Option<String>> {
+    if !matches!(loader, ModLoader::Fabric | ModLoader::Quilt) {
+        return Ok(None);
+    }
+
+    Ok(Some(
+        reqwest::get(format!(
+            "{}/versions/loader/{game_version}/{loader_version}/profile/json",
+            meta_endpoint(servers, loader)
+        ))
+        .await?
+        .error_for_status()?
+        .text()
+        .await?,
+    ))
+}
+
+/// Get the URL of the installer jar of `loader_version` of Forge or NeoForge for Minecraft `game_version` on the `servers`
+///
+/// Fabric and Quilt don't need to be installed using an installer.
+pub fn installer_url(
+    servers: &LoaderServers,
+    loader: ModLoader,
+    game_version: &str,
+    loader_version: &str,
+) -> Option<String> {
+    match loader {
+        ModLoader::Fabric | ModLoader::Quilt => None,
+        ModLoader::Forge => {
+            let version = format!("{game_version}-{loader_version}");
+            Some(format!(
+                "{}/net/minecraftforge/forge/{version}/forge-{version}-installer.jar",
+                endpoint(&servers.forge_maven)
+            ))
+        }
+        ModLoader::NeoForge => Some(format!(
+            "{}/releases/net/neoforged/neoforge/{loader_version}/neoforge-{loader_version}-installer.jar",
+            endpoint(&servers.neoforge_maven)
+        )),
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Predicates {
+    One(String),
+    /// Any of the predicates has to be met
+    Any(Vec<String>),
+}
+
+impl Predicates {
+    fn into_vec(self) -> Vec<String> {
+        match self {
+            Predicates::One(predicate) => vec![predicate],
+            Predicates::Any(predicates) => predicates,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct FabricModJson {
+    #[serde(default)]
+    depends: HashMap<String, Predicates>,
+}
+
+#[derive(Deserialize)]
+struct QuiltModJson {
+    quilt_loader: QuiltLoader,
+}
+
+#[derive(Deserialize)]
+struct QuiltLoader {
+    /// Either the ID of a mod or a [`QuiltDependency`]
+    #[serde(default)]
+    depends: Vec<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct QuiltDependency {
+    id: String,
+    versions: Option<Predicates>,
+}
+
+/// Convert a Fabric version predicate, e.g. `>=0.15 <0.17` or `0.16.x`, to a requirement
+///
+/// Unlike semver, a bare version only matches that exact version.
+fn parse_predicate(predicate: &str) -> Option<Version> {
+    predicate
+        .split_whitespace()
+        .map(|term| {
+            if term.starts_with(|c: char| c.is_ascii_digit()) && !term.contains(['x', 'X', '*']) {
+                format!("={term}")
+            } else {
+                term.to_owned()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+        .parse()
+        .ok()
+}
+
+/// Check that the mod jar at `path` can be loaded by `loader_version` of `loader`
+///
+/// The loader requirement in the jar's `fabric.mod.json` or `quilt.mod.json` is checked,
+/// other mod loaders and requirements that can't be parsed are assumed to be met.
+/// Returns the requirement if it isn't met.
+pub fn unmet_requirement(
+    path: &Path,
+    loader: ModLoader,
+    loader_version: &str,
+) -> Result<Option<String>> {
+    let read = |name| read_file_from_zip(BufReader::new(File::open(path)?), name);
+    let predicates = match loader {
+        ModLoader::Fabric => read("fabric.mod.json")?
+            .and_then(|json| serde_json::from_str::<FabricModJson>(&json).ok())
+            .and_then(|mod_json| {
+                mod_json
+                    .depends
+                    .into_iter()
+                    .find(|(id, _)| id == "fabricloader")
+            })
+            .map(|(_, predicates)| predicates.into_vec()),
+        ModLoader::Quilt => read("quilt.mod.json")?
+            .and_then(|json| serde_json::from_str::<QuiltModJson>(&json).ok())
+            .and_then(|mod_json| {
+                mod_json
+                    .quilt_loader
+                    .depends
+                    .into_iter()
+                    .filter_map(|dep| serde_json::from_value::<QuiltDependency>(dep).ok())
+                    .find(|dep| dep.id == "quilt_loader")
+                    .and_then(|dep| dep.versions)
+            })
+            .map(Predicates::into_vec),
+        ModLoader::Forge | ModLoader::NeoForge => None,
+    };
+
+    let Some(predicates) = predicates else {
+        return Ok(None);
+    };
+    let met = predicates.iter().any(|predicate| {
+        parse_predicate(predicate)
+            .iter()
+            .all(|req| req.matches(loader_version))
+    });
+    Ok((!met).then(|| predicates.join(" or ")))
+}
@@ -0,0 +1,403 @@
+use crate::config::structs::Host;
+use http::{HeaderMap, Response};
+use reqwest::StatusCode;
+use std::{
+    collections::HashMap,
+    future::Future,
+    pin::Pin,
+    sync::{LazyLock, Mutex, PoisonError},
+    task::{Context, Poll},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tower::{Layer, Service};
+
+/// How many times a rate limited request is tried again
+const MAX_RETRIES: u32 = 5;
+/// How long to wait when the API doesn't say when its rate limit resets
+const DEFAULT_WAIT: Duration = Duration::from_secs(60);
+
+/// When requests to each rate limited host can be sent again
+static PAUSED_UNTIL: LazyLock<Mutex<HashMap<Host, Instant>>> = LazyLock::new(Default::default);
+/// How many more requests can be sent to each host before its rate limit resets
+static BUDGETS: LazyLock<Mutex<HashMap<Host, Budget>>> = LazyLock::new(Default::default);
+
+#[derive(Debug, Clone, Copy)]
+struct Budget {
+    remaining: u64,
+    resets_at: Instant,
+}
+
+/// The rate limit of `host` if its responses can't be read, as the number of requests per period
+///
+/// Modrinth's `X-Ratelimit-Remaining` and `X-Ratelimit-Reset` headers can't be read with a [`RecordLayer`]
+/// like GitHub's, since `ferinth::Ferinth` builds its own private `reqwest::Client`,
+/// which can neither be replaced nor take middleware, and only returns the reset once the limit is exceeded.
+/// So Modrinth's documented limit of 300 requests per minute is counted instead.
+fn known_limit(host: Host) -> Option<(u64, Duration)> {
+    match host {
+        Host::Modrinth => Some((300, Duration::from_secs(60))),
+        _ => None,
+    }
+}
+
+/// An error from an API client, which may be caused by exceeding the API's rate limit
+pub trait ApiError {
+    /// Whether the request was rejected because the rate limit was exceeded
+    fn is_rate_limited(&self) -> bool;
+
+    /// How long until the rate limit resets, if the error says so
+    fn resets_in(&self) -> Option<Duration> {
+        None
+    }
+}
+
+impl ApiError for ferinth::Error {
+    fn is_rate_limited(&self) -> bool {
+        matches!(self, ferinth::Error::RateLimitExceeded(_))
+    }
+
+    /// Read from Modrinth's `X-Ratelimit-Reset` header
+    fn resets_in(&self) -> Option<Duration> {
+        match self {
+            ferinth::Error::RateLimitExceeded(seconds) => {
+                Some(Duration::from_secs(*seconds as u64))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl ApiError for furse::Error {
+    fn is_rate_limited(&self) -> bool {
+        matches!(self, furse::Error::ReqwestError(err) if err.status() == Some(StatusCode::TOO_MANY_REQUESTS))
+    }
+}
+
+impl ApiError for octocrab::Error {
+    fn is_rate_limited(&self) -> bool {
+        // GitHub rejects requests over its primary and secondary rate limits with either status
+        matches!(self, octocrab::Error::GitHub { source, .. }
+            if source.status_code == StatusCode::TOO_MANY_REQUESTS
+                || source.status_code == StatusCode::FORBIDDEN
+                    && source.message.to_lowercase().contains("rate limit"))
+    }
+}
+
+/// Send a request to `host` by calling `request`, waiting until the rate limit resets
+/// and trying again whenever it is exceeded
+///
+/// Other requests to `host` wait too, rather than exceeding the rate limit again.
+pub async fn send<T, E, F>(host: Host, request: impl Fn() -> F) -> Result<T, E>
+where
+    F: Future<Output = Result<T, E>>,
+    E: ApiError,
+{
+    let mut attempt = 0;
+    loop {
+        wait_for(host).await;
+        match request().await {
+            Err(err) if err.is_rate_limited() && attempt < MAX_RETRIES => {
+                let wait = err
+                    .resets_in()
+                    .or_else(|| resets_in(host))
+                    .unwrap_or(DEFAULT_WAIT);
+                pause(host, wait);
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// How long until the rate limit of `host` resets, if its responses said so
+fn resets_in(host: Host) -> Option<Duration> {
+    BUDGETS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get(&host)
+        .map(|budget| budget.resets_at.saturating_duration_since(Instant::now()))
+        .filter(|wait| !wait.is_zero())
+}
+
+/// Record the rate limit headers of a response from `host`
+///
+/// `X-Ratelimit-Remaining` and `X-Ratelimit-Reset` set how many more requests can be sent before waiting,
+/// and `Retry-After` pauses requests, as sent when a secondary rate limit is exceeded.
+pub fn record(host: Host, headers: &HeaderMap) {
+    let header = |name| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<u64>().ok())
+    };
+
+    if let Some(retry_after) = header("retry-after") {
+        pause(host, Duration::from_secs(retry_after));
+    }
+    let (Some(remaining), Some(reset)) =
+        (header("x-ratelimit-remaining"), header("x-ratelimit-reset"))
+    else {
+        return;
+    };
+    // GitHub sends when the rate limit resets, Modrinth how long until it resets
+    let resets_in = match host {
+        Host::Github => {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default();
+            Duration::from_secs(reset).saturating_sub(now)
+        }
+        _ => Duration::from_secs(reset),
+    };
+    BUDGETS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .insert(
+            host,
+            Budget {
+                remaining,
+                resets_at: Instant::now() + resets_in,
+            },
+        );
+}
+
+/// Stop sending requests to `host` for `wait`
+fn pause(host: Host, wait: Duration) {
+    let until = Instant::now() + wait;
+    let mut paused = PAUSED_UNTIL.lock().unwrap_or_else(PoisonError::into_inner);
+    let paused_until = paused.entry(host).or_insert(until);
+    *paused_until = (*paused_until).max(until);
+}
+
+/// Wait until a request can be sent to `host`, without exceeding its rate limit
+async fn wait_for(host: Host) {
+    loop {
+        let until = PAUSED_UNTIL
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&host)
+            .copied();
+        if let Some(until) = until {
+            tokio::time::sleep(until.saturating_duration_since(Instant::now())).await;
+        }
+        match take(host) {
+            Some(wait) => pause(host, wait),
+            None => return,
+        }
+    }
+}
+
+/// Take a request from the budget of `host`
+///
+/// Returns how long until the rate limit resets if the budget is used up.
+fn take(host: Host) -> Option<Duration> {
+    let now = Instant::now();
+    let mut budgets = BUDGETS.lock().unwrap_or_else(PoisonError::into_inner);
+    if !budgets
+        .get(&host)
+        .is_some_and(|budget| budget.resets_at > now)
+    {
+        let (limit, period) = known_limit(host)?;
+        budgets.insert(
+            host,
+            Budget {
+                remaining: limit,
+                resets_at: now + period,
+            },
+        );
+    }
+    let budget = budgets.get_mut(&host)?;
+    if budget.remaining == 0 {
+        Some(budget.resets_at - now)
+    } else {
+        budget.remaining -= 1;
+        None
+    }
+}
+
+/// Get the host whose rate limit will take the longest to reset, and how long that will take
+///
+/// Returns `None` if no requests are waiting for a rate limit to reset.
+pub fn waiting() -> Option<(Host, Duration)> {
+    let now = Instant::now();
+    PAUSED_UNTIL
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .iter()
+        .map(|(host, until)| (*host, until.saturating_duration_since(now)))
+        .filter(|(_, wait)| !wait.is_zero())
+        .max_by_key(|(_, wait)| *wait)
+}
+
+/// A layer that records the rate limit headers of the responses from `Host`
+///
+/// Used to build the GitHub API client, whose errors don't include the headers.
+#[derive(Debug, Clone, Copy)]
+pub struct RecordLayer(pub Host);
+
+impl<S> Layer<S> for RecordLayer {
+    type Service = RecordService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RecordService {
+            inner,
+            host: self.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RecordService<S> {
+    inner: S,
+    host: Host,
+}
+
+impl<S, Request, B> Service<Request> for RecordService<S>
+where
+    S: Service<Request, Response = Response<B>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        let host = self.host;
+        let response = self.inner.call(request);
+        Box::pin(async move {
+            let response = response.await?;
+            record(host, response.headers());
+            Ok(response)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::HeaderValue;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    /// Held by the tests one at a time, since they share the rate limits of every host
+    static SERIAL: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+    /// Forgets the pauses and budget of the host when dropped,
+    /// so that other tests using the host aren't rate limited
+    struct Reset {
+        host: Host,
+        _serial: tokio::sync::MutexGuard<'static, ()>,
+    }
+
+    impl Reset {
+        async fn new(host: Host) -> Self {
+            Self {
+                host,
+                _serial: SERIAL.lock().await,
+            }
+        }
+    }
+
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            PAUSED_UNTIL
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .remove(&self.host);
+            BUDGETS
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .remove(&self.host);
+        }
+    }
+
+    #[derive(Debug)]
+    struct FakeError {
+        resets_in: Option<Duration>,
+    }
+
+    impl ApiError for FakeError {
+        fn is_rate_limited(&self) -> bool {
+            true
+        }
+
+        fn resets_in(&self) -> Option<Duration> {
+            self.resets_in
+        }
+    }
+
+    #[tokio::test]
+    async fn send_waits_for_reset() {
+        let _reset = Reset::new(Host::Other).await;
+        let attempts = AtomicU32::new(0);
+        let start = Instant::now();
+        let result = send(Host::Other, || async {
+            if attempts.fetch_add(1, Ordering::SeqCst) == 0 {
+                Err(FakeError {
+                    resets_in: Some(Duration::from_millis(200)),
+                })
+            } else {
+                Ok(())
+            }
+        })
+        .await;
+        assert!(result.is_ok());
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+        assert!(start.elapsed() >= Duration::from_millis(200));
+    }
+
+    #[tokio::test]
+    async fn send_gives_up() {
+        let _reset = Reset::new(Host::Curseforge).await;
+        let attempts = AtomicU32::new(0);
+        let result = send(Host::Curseforge, || async {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            Err::<(), _>(FakeError {
+                resets_in: Some(Duration::from_millis(1)),
+            })
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), MAX_RETRIES + 1);
+    }
+
+    #[tokio::test]
+    async fn pause_shows_waiting() {
+        let _reset = Reset::new(Host::Modrinth).await;
+        pause(Host::Modrinth, Duration::from_secs(600));
+        // A shorter pause doesn't shorten the wait
+        pause(Host::Modrinth, Duration::from_secs(1));
+        let (host, wait) = waiting().unwrap();
+        assert_eq!(host, Host::Modrinth);
+        assert!(wait > Duration::from_secs(590));
+    }
+
+    #[tokio::test]
+    async fn budget_from_headers() {
+        let _reset = Reset::new(Host::Github).await;
+        let reset = SystemTime::now().duration_since(UNIX_EPOCH).unwrap() + Duration::from_secs(30);
+        let mut headers = HeaderMap::new();
+        headers.insert("x-ratelimit-remaining", HeaderValue::from_static("1"));
+        headers.insert("x-ratelimit-reset", reset.as_secs().into());
+        record(Host::Github, &headers);
+
+        // The last request is sent, then the next one has to wait until the reset
+        assert_eq!(take(Host::Github), None);
+        let wait = take(Host::Github).unwrap();
+        assert!(wait > Duration::from_secs(25) && wait <= Duration::from_secs(30));
+        assert!(resets_in(Host::Github).is_some_and(|wait| wait > Duration::from_secs(25)));
+    }
+
+    #[tokio::test]
+    async fn known_limit_budget() {
+        let _reset = Reset::new(Host::Modrinth).await;
+        let (limit, _) = known_limit(Host::Modrinth).unwrap();
+        for _ in 0..limit {
+            assert_eq!(take(Host::Modrinth), None);
+        }
+        assert!(take(Host::Modrinth).is_some());
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{CURSEFORGE_API, MODRINTH_API};
+use crate::{config::structs::Host, rate_limit, CURSEFORGE_API, MODRINTH_API};
 use futures_util::{try_join, TryFutureExt};
 use sha1::{Digest, Sha1};
 use std::{
@@ -16,13 +16,14 @@ pub enum Error {
 }
 type Result<T> = std::result::Result<T, Error>;
 
-/// Scans `dir_path` and return the filename, Modrinth project ID, and CurseForge mod ID for each JAR file
+/// Scans `dir_path` and return the filename, Modrinth project and version IDs,
+/// and CurseForge mod and file IDs for each JAR file
 ///
 /// Calls `hashing_complete` after reading and hashing files is done.
 pub async fn scan(
     dir_path: impl AsRef<Path>,
     hashing_complete: impl Fn(),
-) -> Result<Vec<(String, Option<String>, Option<i32>)>> {
+) -> Result<Vec<(String, Option<(String, String)>, Option<(i32, i32)>)>> {
     let mut filenames = HashMap::new();
     let mut mr_hashes = vec![];
     let mut cf_hashes = vec![];
@@ -54,22 +55,29 @@ pub async fn scan(
 
     // Fetch the mods using the file hashes.
     let (mr_results, cf_results) = try_join!(
-        MODRINTH_API
-            .get_versions_from_hashes(mr_hashes.clone())
-            .map_err(Error::from),
-        CURSEFORGE_API
-            .get_fingerprint_matches(cf_hashes.clone())
-            .map_err(Error::from),
+        rate_limit::send(Host::Modrinth, || {
+            MODRINTH_API.get_versions_from_hashes(mr_hashes.clone())
+        })
+        .map_err(Error::from),
+        rate_limit::send(Host::Curseforge, || {
+            CURSEFORGE_API.get_fingerprint_matches(cf_hashes.clone())
+        })
+        .map_err(Error::from),
     )?;
 
     // Elide explicit type parameters when https://github.com/rust-lang/rust/issues/90879 is resolved.
-    let mut mr_results =
-        HashMap::<_, _>::from_iter(mr_results.into_iter().map(|(k, v)| (k, v.project_id)));
-    let mut cf_results = HashMap::<_, _>::from_iter(
-        cf_results
-            .exact_fingerprints
+    let mut mr_results = HashMap::<_, _>::from_iter(
+        mr_results
             .into_iter()
-            .zip(cf_results.exact_matches.into_iter().map(|m| m.id)),
+            .map(|(k, v)| (k, (v.project_id, v.id))),
+    );
+    let mut cf_results = HashMap::<_, _>::from_iter(
+        cf_results.exact_fingerprints.into_iter().zip(
+            cf_results
+                .exact_matches
+                .into_iter()
+                .map(|m| (m.id, m.file.id)),
+        ),
     );
 
     Ok(mr_hashes
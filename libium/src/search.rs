@@ -0,0 +1,393 @@
+use crate::{
+    cache::{Cache, NotCached, PROJECT_TTL},
+    config::structs::{Filters, Host, ModLoader, Side, SourceId},
+    curseforge_api_key, rate_limit, MODRINTH_API,
+};
+use ferinth::structures::{
+    project::ProjectType,
+    search::{Facet, Sort},
+};
+use furse::structures::{common_structs::ModLoaderType, mod_structs::Mod};
+use serde::Deserialize;
+use std::{future::Future, sync::LazyLock};
+
+const CURSEFORGE_SEARCH: &str = "https://api.curseforge.com/v1/mods/search";
+/// The ID of Minecraft on CurseForge
+const CURSEFORGE_MINECRAFT: i32 = 432;
+/// The ID of the mods class on CurseForge
+const CURSEFORGE_MODS: i32 = 6;
+/// Sort CurseForge search results by popularity
+const CURSEFORGE_POPULARITY: i32 = 2;
+/// The most results CurseForge returns in a page
+const CURSEFORGE_PAGE_SIZE: usize = 50;
+/// The most results Modrinth returns in a page
+const MODRINTH_PAGE_SIZE: usize = 100;
+/// How many pages of results are searched at most to find enough compatible ones
+const MAX_PAGES: usize = 5;
+
+/// CurseForge's search isn't in furse, so it's requested directly
+static CURSEFORGE_CLIENT: LazyLock<reqwest::Client> = LazyLock::new(reqwest::Client::new);
+
+#[derive(Debug, thiserror::Error)]
+#[error(transparent)]
+pub enum Error {
+    ModrinthError(#[from] ferinth::Error),
+    CurseForgeError(#[from] furse::Error),
+    NotCached(#[from] NotCached),
+}
+type Result<T> = std::result::Result<T, Error>;
+
+/// A project found by searching
+#[derive(Debug, Clone)]
+pub struct SearchResult {
+    /// The source to add the project with
+    pub id: SourceId,
+    pub slug: String,
+    pub title: String,
+    pub description: String,
+    pub downloads: usize,
+    /// The sides the project supports, CurseForge doesn't say
+    pub side: Option<Side>,
+    pub categories: Vec<String>,
+}
+
+/// Search Modrinth for up to `limit` mods matching `query` that are compatible with `filters`
+///
+/// The game versions and mod loaders of `filters` are used, sorted by relevance.
+pub async fn modrinth(
+    cache: &Cache,
+    query: &str,
+    filters: &Filters,
+    limit: usize,
+) -> Result<Vec<SearchResult>> {
+    if cache.offline {
+        return Err(NotCached(format!("The Modrinth search for {query}")).into());
+    }
+
+    let facets = modrinth_facets(filters);
+    let page_size = limit.clamp(1, MODRINTH_PAGE_SIZE);
+    let hits = collect_pages(
+        limit,
+        |offset| {
+            let facets = facets.clone();
+            async move {
+                let response = rate_limit::send(Host::Modrinth, || {
+                    MODRINTH_API.search_paged(
+                        query,
+                        &Sort::Relevance,
+                        page_size,
+                        offset,
+                        facets.clone(),
+                    )
+                })
+                .await?;
+                let more = offset + response.hits.len() < response.total_hits;
+                Ok((response.hits, more))
+            }
+        },
+        |hit| {
+            hit.game_versions
+                .iter()
+                .any(|version| filters.game_version_matches(version))
+        },
+    )
+    .await?;
+
+    Ok(hits
+        .into_iter()
+        .map(|hit| {
+            let side = Side::from_support(&hit.client_side, &hit.server_side);
+            SearchResult {
+                id: SourceId::Modrinth(hit.project_id),
+                slug: hit.slug,
+                title: hit.title,
+                description: hit.description,
+                downloads: hit.downloads,
+                side: Some(side),
+                // The mod loaders are already known to match
+                categories: hit
+                    .display_categories
+                    .into_iter()
+                    .filter(|category| category.parse::<ModLoader>().is_err())
+                    .collect(),
+            }
+        })
+        .collect())
+}
+
+/// The facets to search Modrinth with, facets in the same list are combined with OR and the lists with AND
+///
+/// Game versions are only used if they're all exact, otherwise they're checked once the results are returned.
+fn modrinth_facets(filters: &Filters) -> Vec<Vec<Facet>> {
+    let mut facets = vec![vec![Facet::ProjectType(ProjectType::Mod)]];
+    if let Some(mod_loaders) = filters.mod_loaders.as_ref().filter(|l| !l.is_empty()) {
+        facets.push(
+            mod_loaders
+                .iter()
+                .map(|loader| Facet::Categories(loader.to_string().to_lowercase()))
+                .collect(),
+        );
+    }
+    if let Some(versions) = filters
+        .versions
+        .as_ref()
+        .and_then(|versions| {
+            versions
+                .iter()
+                .map(|version| version.exact().map(Facet::Versions))
+                .collect::<Option<Vec<_>>>()
+        })
+        .filter(|versions| !versions.is_empty())
+    {
+        facets.push(versions);
+    }
+    facets
+}
+
+/// Collect up to `limit` results that are `compatible` from the pages of results returned by `fetch_page`
+///
+/// `fetch_page` is called with the number of results to skip,
+/// and returns the results of the page and whether there are more pages.
+/// Pages are fetched until there are enough compatible results, up to [`MAX_PAGES`].
+async fn collect_pages<T, F>(
+    limit: usize,
+    mut fetch_page: impl FnMut(usize) -> F,
+    compatible: impl Fn(&T) -> bool,
+) -> Result<Vec<T>>
+where
+    F: Future<Output = Result<(Vec<T>, bool)>>,
+{
+    let mut results = vec![];
+    let mut offset = 0;
+    for _ in 0..MAX_PAGES {
+        let (page, more) = fetch_page(offset).await?;
+        let empty = page.is_empty();
+        offset += page.len();
+        results.extend(page.into_iter().filter(|result| compatible(result)));
+        if results.len() >= limit || !more || empty {
+            break;
+        }
+    }
+    results.truncate(limit);
+    Ok(results)
+}
+
+#[derive(Deserialize)]
+struct CurseForgeSearch {
+    data: Vec<Mod>,
+}
+
+/// Search CurseForge for up to `limit` mods matching `query` that are compatible with `filters`
+///
+/// The game versions and mod loaders of `filters` are used, sorted by popularity.
+pub async fn curseforge(
+    cache: &Cache,
+    query: &str,
+    filters: &Filters,
+    limit: usize,
+) -> Result<Vec<SearchResult>> {
+    if cache.offline {
+        return Err(NotCached(format!("The CurseForge search for {query}")).into());
+    }
+
+    let page_size = limit.clamp(1, CURSEFORGE_PAGE_SIZE);
+    let mut params = vec![
+        ("gameId", CURSEFORGE_MINECRAFT.to_string()),
+        ("classId", CURSEFORGE_MODS.to_string()),
+        ("searchFilter", query.to_owned()),
+        ("sortField", CURSEFORGE_POPULARITY.to_string()),
+        ("sortOrder", "desc".to_owned()),
+        ("pageSize", page_size.to_string()),
+    ];
+    // CurseForge can only filter by a single game version and mod loader,
+    // the rest are checked once the results are returned
+    if let Some(version) = filters
+        .versions
+        .as_deref()
+        .and_then(|versions| match versions {
+            [version] => version.exact(),
+            _ => None,
+        })
+    {
+        params.push(("gameVersion", version));
+    }
+    if let Some([mod_loader]) = filters.mod_loaders.as_deref() {
+        params.push((
+            "modLoaderType",
+            (to_cf_loader(*mod_loader) as u8).to_string(),
+        ));
+    }
+
+    let mods = collect_pages(
+        limit,
+        |offset| {
+            let params = &params;
+            async move {
+                let mods = curseforge_page(params, offset).await?;
+                let more = mods.len() == page_size;
+                Ok((mods, more))
+            }
+        },
+        |project: &Mod| {
+            project.latest_files_indexes.iter().any(|index| {
+                filters.game_version_matches(&index.game_version)
+                    && match &index.mod_loader {
+                        None | Some(ModLoaderType::Any) => true,
+                        Some(loader) => from_cf_loader(loader)
+                            .is_some_and(|loader| filters.mod_loader_matches(&loader)),
+                    }
+            })
+        },
+    )
+    .await?;
+
+    Ok(mods
+        .into_iter()
+        .map(|project| SearchResult {
+            id: SourceId::Curseforge(project.id),
+            slug: project.slug,
+            title: project.name,
+            description: project.summary,
+            downloads: project.download_count,
+            side: None,
+            categories: project
+                .categories
+                .into_iter()
+                .map(|category| category.name)
+                .collect(),
+        })
+        .collect())
+}
+
+/// Get the ID of the CurseForge project with `slug`, of `class_id` if it is known,
+/// or `None` if there isn't one
+///
+/// Slugs are only unique within a class, so projects of other classes can have the same slug.
+pub async fn curseforge_slug(
+    cache: &Cache,
+    class_id: Option<i32>,
+    slug: &str,
+) -> Result<Option<i32>> {
+    let class = class_id.map_or_else(|| "any".to_owned(), |class_id| class_id.to_string());
+    cache
+        .fetch(&["curseforge", "slug", &class, slug], PROJECT_TTL, async {
+            let mut params = vec![
+                ("gameId", CURSEFORGE_MINECRAFT.to_string()),
+                ("slug", slug.to_owned()),
+            ];
+            if let Some(class_id) = class_id {
+                params.push(("classId", class_id.to_string()));
+            }
+            Ok::<_, Error>(
+                curseforge_page(&params, 0)
+                    .await?
+                    .into_iter()
+                    .find(|project| project.slug == slug)
+                    .map(|project| project.id),
+            )
+        })
+        .await
+}
+
+/// Get the page of CurseForge's search results for `params` starting `offset` results in
+async fn curseforge_page(params: &[(&str, String)], offset: usize) -> Result<Vec<Mod>> {
+    Ok(rate_limit::send(Host::Curseforge, || async {
+        Ok::<_, furse::Error>(
+            CURSEFORGE_CLIENT
+                .get(CURSEFORGE_SEARCH)
+                .query(params)
+                .query(&[("index", offset)])
+                .header("x-api-key", curseforge_api_key())
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<CurseForgeSearch>()
+                .await?
+                .data,
+        )
+    })
+    .await?)
+}
+
+fn to_cf_loader(mod_loader: ModLoader) -> ModLoaderType {
+    match mod_loader {
+        ModLoader::Quilt => ModLoaderType::Quilt,
+        ModLoader::Fabric => ModLoaderType::Fabric,
+        ModLoader::Forge => ModLoaderType::Forge,
+        ModLoader::NeoForge => ModLoaderType::NeoForge,
+    }
+}
+
+/// Returns `None` for mod loaders ferium doesn't support
+fn from_cf_loader(mod_loader: &ModLoaderType) -> Option<ModLoader> {
+    match mod_loader {
+        ModLoaderType::Quilt => Some(ModLoader::Quilt),
+        ModLoaderType::Fabric => Some(ModLoader::Fabric),
+        ModLoaderType::Forge => Some(ModLoader::Forge),
+        ModLoaderType::NeoForge => Some(ModLoader::NeoForge),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inexact_versions_not_faceted() {
+        let filters = Filters {
+            versions: Some(vec!["1.21.x".parse().unwrap()]),
+            ..Filters::empty()
+        };
+        assert_eq!(
+            modrinth_facets(&filters),
+            [[Facet::ProjectType(ProjectType::Mod)]]
+        );
+
+        let filters = Filters {
+            versions: Some(vec!["=1.21.1".parse().unwrap()]),
+            ..Filters::empty()
+        };
+        assert_eq!(
+            modrinth_facets(&filters),
+            [
+                [Facet::ProjectType(ProjectType::Mod)],
+                [Facet::Versions("1.21.1".to_owned())]
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn pages_until_limit() {
+        // Only even numbers are compatible, and there are 25 results in pages of 10
+        let mut offsets = vec![];
+        let results = collect_pages(
+            7,
+            |offset| {
+                offsets.push(offset);
+                async move {
+                    let page = (offset..(offset + 10).min(25)).collect::<Vec<_>>();
+                    Ok((page, offset + 10 < 25))
+                }
+            },
+            |number| number % 2 == 0,
+        )
+        .await
+        .unwrap();
+        assert_eq!(results, [0, 2, 4, 6, 8, 10, 12]);
+        assert_eq!(offsets, [0, 10]);
+
+        // The last page is reached before finding enough compatible results
+        let results = collect_pages(
+            20,
+            |offset| async move {
+                let page = (offset..(offset + 10).min(25)).collect::<Vec<_>>();
+                Ok((page, offset + 10 < 25))
+            },
+            |number| number % 2 == 0,
+        )
+        .await
+        .unwrap();
+        assert_eq!(results.len(), 13);
+    }
+}
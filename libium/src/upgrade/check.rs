@@ -1,5 +1,9 @@
 use super::Metadata;
-use crate::{config::structs::Filters, iter_ext::IterExt, MODRINTH_API};
+use crate::{
+    config::structs::{Filters, Host},
+    iter_ext::IterExt,
+    rate_limit, MODRINTH_API,
+};
 use ferinth::structures::tag::GameVersionType;
 use std::{collections::HashSet, sync::OnceLock};
 
@@ -24,7 +28,8 @@ pub async fn get_version_groups() -> Result<&'static Vec<Vec<String>>> {
     if let Some(v) = VERSION_GROUPS.get() {
         Ok(v)
     } else {
-        let versions = MODRINTH_API.list_game_versions().await?;
+        let versions =
+            rate_limit::send(Host::Modrinth, || MODRINTH_API.list_game_versions()).await?;
         let mut v = vec![vec![]];
         for version in versions {
             if version.version_type == GameVersionType::Release {
@@ -1,12 +1,14 @@
 pub mod check;
 pub mod mod_downloadable;
+pub mod resolve;
 
 use crate::{
+    cache::{self, Cache},
     config::{
         modpack::modrinth,
         structs::{
-            ModLoader, ProfileImport, ProfileImportSource, ReleaseChannel, SourceId, SourceKind,
-            SourceKindWithModpack,
+            Host, ModLoader, ProfileImport, ProfileImportSource, ReleaseChannel, Side, SourceId,
+            SourceKind, SourceKindWithModpack,
         },
     },
     get_tmp_dir,
@@ -14,7 +16,7 @@ use crate::{
     version_ext::VersionExt,
 };
 use ferinth::structures::{
-    project::ProjectType,
+    project::Project as MRProject,
     version::{
         DependencyType as MRDependencyType, Hash as MRHash, Version as MRVersion, VersionType,
     },
@@ -25,13 +27,17 @@ use furse::structures::file_structs::{
 };
 use md5::Digest;
 use octocrab::models::repos::{Asset as GHAsset, Release as GHRelease};
-use reqwest::{Client, Url};
+use reqwest::{header::RANGE, Client, StatusCode, Url};
+use serde::Serialize;
 use std::{
+    collections::BTreeMap,
     ffi::OsStr,
     fs::{self, create_dir_all, rename, File, OpenOptions},
     io::{self, BufWriter, SeekFrom, Write},
     path::{Path, PathBuf},
     str::FromStr,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
 };
 
 #[derive(Debug, thiserror::Error)]
@@ -44,9 +50,78 @@ pub enum Error {
     UnexpectedFileHash(String, String),
     #[error("expected one of the specified user hash {0:?} but got {1}")]
     UnexpectedUserHash(Vec<String>, String),
+    #[error("there are no URLs to download the file from")]
+    NoUrls,
+    NotCached(#[from] cache::NotCached),
 }
 type Result<T> = std::result::Result<T, Error>;
 
+impl Error {
+    /// Whether the error may go away by trying again, e.g. a dropped connection or a server error
+    pub fn is_retryable(&self) -> bool {
+        let Error::ReqwestError(err) = self else {
+            return false;
+        };
+        match err.status() {
+            Some(status) => status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS,
+            // A connection dropped while reading the body is reported as a decoding error
+            None => {
+                err.is_connect()
+                    || err.is_timeout()
+                    || err.is_request()
+                    || err.is_body()
+                    || err.is_decode()
+            }
+        }
+    }
+}
+
+/// How long to wait before retrying a failed download for the first time
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+/// The longest time to wait before retrying a failed download
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// How files are downloaded
+#[derive(Debug, Clone, Default)]
+pub struct DownloadOptions {
+    /// How many times a download is retried after a network error
+    pub retries: u32,
+    /// The limit on the total speed of the downloads sharing it
+    pub bandwidth: Option<Arc<BandwidthLimit>>,
+    /// The cache downloaded files are linked from and stored in
+    pub cache: Cache,
+}
+
+/// A limit on the total speed of the downloads that share it
+#[derive(Debug)]
+pub struct BandwidthLimit {
+    bytes_per_second: u64,
+    /// When the data downloaded so far is allowed to have finished downloading
+    until: Mutex<Instant>,
+}
+
+impl BandwidthLimit {
+    pub fn new(bytes_per_second: u64) -> Self {
+        Self {
+            bytes_per_second: bytes_per_second.max(1),
+            until: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Wait until `bytes` more bytes can be downloaded without exceeding the limit
+    async fn take(&self, bytes: usize) {
+        let wait = {
+            let mut until = self.until.lock().unwrap_or_else(PoisonError::into_inner);
+            let now = Instant::now();
+            // Time spent not downloading can't be used to go faster later
+            *until = (*until).max(now)
+                + Duration::from_secs_f64(bytes as f64 / self.bytes_per_second as f64);
+            *until - now
+        };
+        tokio::time::sleep(wait).await;
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Metadata {
     /// The title of the GitHub Release, Modrinth Version, or CurseForge File
@@ -62,8 +137,11 @@ pub struct Metadata {
 }
 
 /// Downloadable data from a source on the internet.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "kebab-case")]
 pub struct DownloadData {
+    /// The pinned identifier of the resolved file, `None` if the file isn't from a platform
+    pub id: Option<SourceId>,
     pub src: DownloadSource,
     /// The path of the downloaded file relative to the output directory
     ///
@@ -73,10 +151,14 @@ pub struct DownloadData {
     pub length: u64,
     /// The dependencies this file has
     pub dependencies: Vec<SourceId>,
+    /// The optional dependencies this file has, e.g integrations with other mods
+    pub optional_dependencies: Vec<SourceId>,
     /// Other mods this file is incompatible with
     pub conflicts: Vec<SourceId>,
     /// The kind of source file, `None` if the kind is unknown.
     pub kind: Option<SourceKindWithModpack>,
+    /// The side of the game the file is exclusively for, `Both` if it isn't known
+    pub side: Side,
     /// The expected hash of the file.
     /// The hash is provided by the source (e.g Github)
     /// and is recalculated and compared when downloading.
@@ -87,13 +169,33 @@ pub struct DownloadData {
     pub user_hash: Vec<String>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum Hash {
     Curseforge(Vec<CFHash>),
     Modrinth(MRHash),
 }
 
 impl Hash {
+    /// The SHA-1 hash in base16 format, if the platform provides one
+    pub fn sha1(&self) -> Option<&str> {
+        match self {
+            Hash::Curseforge(hashes) => hashes
+                .iter()
+                .find(|hash| hash.algo == CFHashAlgo::Sha1)
+                .map(|hash| hash.value.as_str()),
+            Hash::Modrinth(hash) => Some(&hash.sha1),
+        }
+    }
+
+    /// The SHA-512 hash in base16 format, if the platform provides one
+    pub fn sha512(&self) -> Option<&str> {
+        match self {
+            Hash::Curseforge(_) => None,
+            Hash::Modrinth(hash) => Some(&hash.sha512),
+        }
+    }
+
     /// Compare the hash to a reader object.
     /// If the reader is a file, there is no guarantee where the file cursor will end up.
     fn compare<R>(&self, reader: &mut R) -> Result<()>
@@ -139,9 +241,11 @@ impl Hash {
 }
 
 /// The source of some downloadable data.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum DownloadSource {
-    Url(Url),
+    /// The URLs to download the file from, tried in order
+    Urls(Vec<Url>),
     Contents(String),
     Path(PathBuf),
 }
@@ -176,10 +280,10 @@ pub fn try_from_cf_file(
             game_versions: file.game_versions,
         },
         DownloadData {
-            src: DownloadSource::Url(
-                file.download_url
-                    .ok_or(DistributionDeniedError(file.mod_id, file.id))?,
-            ),
+            id: Some(SourceId::PinnedCurseforge(file.mod_id, file.id)),
+            src: DownloadSource::Urls(vec![file
+                .download_url
+                .ok_or(DistributionDeniedError(file.mod_id, file.id))?]),
             output: kind.directory(inferred_kind).join(file.file_name.as_str()),
             length: file.file_length as u64,
             dependencies: file
@@ -193,6 +297,17 @@ pub fn try_from_cf_file(
                     }
                 })
                 .collect_vec(),
+            optional_dependencies: file
+                .dependencies
+                .iter()
+                .filter_map(|d| {
+                    if d.relation_type == CFFileRelationType::OptionalDependency {
+                        Some(SourceId::Curseforge(d.mod_id))
+                    } else {
+                        None
+                    }
+                })
+                .collect_vec(),
             conflicts: file
                 .dependencies
                 .iter()
@@ -205,6 +320,7 @@ pub fn try_from_cf_file(
                 })
                 .collect_vec(),
             kind: inferred_kind,
+            side: Side::Both,
             hash: Some(Hash::Curseforge(file.hashes)),
             user_hash: vec![],
         },
@@ -214,9 +330,9 @@ pub fn try_from_cf_file(
 pub fn from_mr_version(
     kind: SourceKind,
     version: MRVersion,
-    project_type: Option<ProjectType>,
+    project: &MRProject,
 ) -> (Metadata, DownloadData) {
-    let inferred_kind = project_type.and_then(SourceKindWithModpack::from_mr_project_type);
+    let inferred_kind = SourceKindWithModpack::from_mr_project_type(project.project_type.clone());
 
     (
         Metadata {
@@ -237,7 +353,11 @@ pub fn from_mr_version(
             game_versions: version.game_versions.clone(),
         },
         DownloadData {
-            src: DownloadSource::Url(version.get_version_file().url.clone()),
+            id: Some(SourceId::PinnedModrinth(
+                version.project_id.clone(),
+                version.id.clone(),
+            )),
+            src: DownloadSource::Urls(vec![version.get_version_file().url.clone()]),
             output: kind
                 .directory(inferred_kind)
                 .join(version.get_version_file().filename.as_str()),
@@ -260,6 +380,24 @@ pub fn from_mr_version(
                     }
                 })
                 .collect_vec(),
+            optional_dependencies: version
+                .dependencies
+                .clone()
+                .into_iter()
+                .filter_map(|d| {
+                    if d.dependency_type == MRDependencyType::Optional {
+                        match (d.project_id, d.version_id) {
+                            (Some(proj_id), Some(ver_id)) => {
+                                Some(SourceId::PinnedModrinth(proj_id, ver_id))
+                            }
+                            (Some(proj_id), None) => Some(SourceId::Modrinth(proj_id)),
+                            _ => None,
+                        }
+                    } else {
+                        None
+                    }
+                })
+                .collect_vec(),
             hash: Some(Hash::Modrinth(version.get_version_file().hashes.clone())),
             conflicts: version
                 .dependencies
@@ -279,6 +417,7 @@ pub fn from_mr_version(
                 })
                 .collect_vec(),
             kind: inferred_kind,
+            side: Side::from_support(&project.client_side, &project.server_side),
             user_hash: vec![],
         },
     )
@@ -286,6 +425,7 @@ pub fn from_mr_version(
 
 pub fn from_gh_releases(
     kind: SourceKind,
+    (owner, repo): (&str, &str),
     releases: impl IntoIterator<Item = GHRelease>,
 ) -> Vec<(Metadata, DownloadData)> {
     releases
@@ -319,23 +459,32 @@ pub fn from_gh_releases(
                             .collect_vec(),
                         filename: asset.name.clone(),
                     },
-                    from_gh_asset(kind, asset),
+                    from_gh_asset(kind, (owner, repo), asset),
                 )
             })
         })
         .collect_vec()
 }
 
-pub fn from_gh_asset(kind: SourceKind, asset: GHAsset) -> DownloadData {
+pub fn from_gh_asset(
+    kind: SourceKind,
+    (owner, repo): (&str, &str),
+    asset: GHAsset,
+) -> DownloadData {
     let inferred_kind = None;
 
     DownloadData {
-        src: DownloadSource::Url(asset.browser_download_url),
+        id: i32::try_from(asset.id.0)
+            .ok()
+            .map(|pin| SourceId::PinnedGithub((owner.to_owned(), repo.to_owned()), pin)),
+        src: DownloadSource::Urls(vec![asset.browser_download_url]),
         output: kind.directory(inferred_kind).join(asset.name),
         length: asset.size as u64,
         dependencies: Vec::new(),
+        optional_dependencies: Vec::new(),
         conflicts: Vec::new(),
         kind: inferred_kind,
+        side: Side::Both,
         hash: None,
         user_hash: vec![],
     }
@@ -370,29 +519,41 @@ pub fn from_file(
             loaders: vec![],
         },
         DownloadData {
+            id: None,
             src: DownloadSource::Path(path),
             output,
             length,
             dependencies: vec![],
+            optional_dependencies: vec![],
             conflicts: vec![],
             kind: inferred_kind,
+            side: Side::Both,
             hash: None,
             user_hash: vec![],
         },
     ))
 }
 
-pub async fn from_url(kind: SourceKind, url: &Url) -> Result<(Metadata, DownloadData)> {
+pub async fn from_url(
+    cache: &Cache,
+    kind: SourceKind,
+    url: &Url,
+) -> Result<(Metadata, DownloadData)> {
     let path = url.path();
     let (_, filename) = path.split_once('/').unwrap_or(("", path));
     let (title, _) = filename.split_once('.').unwrap_or((filename, ""));
     let inferred_kind = None;
     let output = kind.directory(inferred_kind).join(filename);
 
-    let length = reqwest::get(url.clone())
-        .await?
-        .content_length()
-        .unwrap_or(0);
+    // The length is only used to show progress, so it's unknown offline
+    let length = if cache.offline {
+        0
+    } else {
+        reqwest::get(url.clone())
+            .await?
+            .content_length()
+            .unwrap_or(0)
+    };
 
     Ok((
         Metadata {
@@ -404,12 +565,15 @@ pub async fn from_url(kind: SourceKind, url: &Url) -> Result<(Metadata, Download
             loaders: vec![],
         },
         DownloadData {
-            src: DownloadSource::Url(url.clone()),
+            id: None,
+            src: DownloadSource::Urls(vec![url.clone()]),
             output,
             length,
             dependencies: vec![],
+            optional_dependencies: vec![],
             conflicts: vec![],
             kind: inferred_kind,
+            side: Side::Both,
             hash: None,
             user_hash: vec![],
         },
@@ -418,17 +582,17 @@ pub async fn from_url(kind: SourceKind, url: &Url) -> Result<(Metadata, Download
 
 pub fn from_modpack_file(file: modrinth::ModpackFile) -> DownloadData {
     DownloadData {
-        src: DownloadSource::Url(
-            file.downloads
-                .first()
-                .expect("Download URLs not provided")
-                .clone(),
-        ),
+        id: None,
+        src: DownloadSource::Urls(file.downloads),
         output: file.path,
         length: file.file_size as u64,
         dependencies: Vec::new(),
+        optional_dependencies: Vec::new(),
         conflicts: Vec::new(),
         kind: None,
+        side: file.env.map_or(Side::Both, |env| {
+            Side::from_support(&env.client, &env.server)
+        }),
         hash: Some(Hash::Modrinth(file.hashes)),
         user_hash: vec![],
     }
@@ -439,27 +603,96 @@ impl DownloadData {
     ///
     /// The `update` closure is called with the chunk length whenever a chunk is downloaded and written.
     ///
+    /// A download that fails because of the network is retried up to `options.retries` times,
+    /// waiting twice as long before each retry, and resumes from what was already downloaded.
+    /// A `.part` file left over from an earlier download is resumed too.
+    /// Downloading waits whenever it would exceed `options.bandwidth`.
+    ///
     /// Returns the total size of the file and the filename.
     pub async fn download(
         self,
         client: Client,
         output_dir: impl AsRef<Path>,
+        options: &DownloadOptions,
         update: impl Fn(usize) + Send,
     ) -> Result<(usize, String)> {
         let (size, filename) = (self.length as usize, self.filename());
         let out_file_path = output_dir.as_ref().join(&self.output);
 
-        match self.src {
-            DownloadSource::Url(url) => {
-                let mut temp_file = TempFile::new(&out_file_path, size)?;
+        match &self.src {
+            DownloadSource::Urls(urls) => {
+                let cached = options
+                    .cache
+                    .file_paths(self.hash.as_ref(), &self.user_hash);
+                if let Some(cached_path) = cache::find_file(&cached) {
+                    // Link the cached file into place, so that profiles share a single copy on disk
+                    let part_path = out_file_path.with_extension("part");
+                    if let Some(up_dir) = out_file_path.parent() {
+                        create_dir_all(up_dir)?;
+                    }
+                    if part_path.exists() {
+                        fs::remove_file(&part_path)?;
+                    }
+                    cache::link_file(cached_path, &part_path)?;
+                    let temp_file = TempFile::new(&out_file_path, size)?;
 
-                let mut response = client.get(url).send().await?;
-                while let Some(chunk) = response.chunk().await? {
-                    temp_file.file.write_all(&chunk)?;
-                    update(chunk.len());
+                    match temp_file.finalize(self.hash.clone(), self.user_hash.clone()) {
+                        Ok(result) => {
+                            update(size);
+                            return Ok(result);
+                        }
+                        Err(err) => {
+                            // Writing to the linked file would change the cached file too
+                            fs::remove_file(part_path)?;
+                            if !matches!(
+                                err,
+                                Error::UnexpectedFileHash(..) | Error::UnexpectedUserHash(..)
+                            ) {
+                                return Err(err);
+                            }
+                            // The cached file is corrupted, so download it again
+                            fs::remove_file(cached_path)?;
+                        }
+                    }
+                }
+                if options.cache.offline {
+                    return Err(cache::NotCached(filename).into());
                 }
 
-                temp_file.finalize(self.hash, self.user_hash)
+                // Try each URL in turn, resuming what the previous one downloaded
+                let mut reported = 0;
+                let mut result = Err(Error::NoUrls);
+                for url in urls {
+                    result = self
+                        .download_from(
+                            &client,
+                            url,
+                            &out_file_path,
+                            options,
+                            &mut reported,
+                            &update,
+                        )
+                        .await;
+                    if result.is_ok() {
+                        break;
+                    }
+                }
+                let result = result?;
+
+                let has_user_hash = !self.user_hash.is_empty();
+
+                // User hashes may be prefixes, so the file is stored under its full hash
+                let cached = if has_user_hash && self.hash.as_ref().and_then(Hash::sha512).is_none()
+                {
+                    options
+                        .cache
+                        .file_paths(self.hash.as_ref(), &[calculate_sha512(&out_file_path)?])
+                } else {
+                    options.cache.file_paths(self.hash.as_ref(), &[])
+                };
+                // The cache is only an optimisation, so failing to write it isn't an error
+                let _ = cache::store_file(&out_file_path, &cached);
+                Ok(result)
             }
             DownloadSource::Contents(data) => {
                 let mut temp_file = TempFile::new(&out_file_path, size)?;
@@ -491,6 +724,82 @@ impl DownloadData {
         }
     }
 
+    /// Download the file from `url` to `out_file_path`, retrying up to `options.retries` times if the network fails
+    ///
+    /// `reported` is the length `update` has been called with so far.
+    async fn download_from(
+        &self,
+        client: &Client,
+        url: &Url,
+        out_file_path: &Path,
+        options: &DownloadOptions,
+        reported: &mut u64,
+        update: &impl Fn(usize),
+    ) -> Result<(usize, String)> {
+        let mut attempt = 0;
+        loop {
+            let mut temp_file = TempFile::new(out_file_path, self.length as usize)?;
+            let resumed = temp_file.len()? > 0;
+            match temp_file
+                .fetch(client, url, options.bandwidth.as_deref(), reported, update)
+                .await
+            {
+                Ok(()) => match temp_file.finalize(self.hash.clone(), self.user_hash.clone()) {
+                    // The `.part` file was left over from another version of the file
+                    Err(Error::UnexpectedFileHash(..) | Error::UnexpectedUserHash(..))
+                        if resumed =>
+                    {
+                        fs::remove_file(out_file_path.with_extension("part"))?;
+                        continue;
+                    }
+                    result => return result,
+                },
+                Err(err) if err.is_retryable() && attempt < options.retries => {}
+                Err(err) => return Err(err),
+            }
+            tokio::time::sleep(
+                RETRY_DELAY
+                    .saturating_mul(2_u32.saturating_pow(attempt))
+                    .min(MAX_RETRY_DELAY),
+            )
+            .await;
+            attempt += 1;
+        }
+    }
+
+    /// Try the URLs rewritten using `mirrors` first, keeping the original URLs as fallbacks
+    ///
+    /// `mirrors` maps URL prefixes to the prefixes of mirrors, the longest matching prefix is used.
+    pub fn use_mirrors(&mut self, mirrors: &BTreeMap<String, String>) {
+        let DownloadSource::Urls(urls) = &mut self.src else {
+            return;
+        };
+        let mut mirrored = urls
+            .iter()
+            .filter_map(|url| {
+                let (prefix, mirror) = mirrors
+                    .iter()
+                    .filter(|(prefix, _)| url.as_str().starts_with(prefix.as_str()))
+                    .max_by_key(|(prefix, _)| prefix.len())?;
+                Url::parse(&format!("{mirror}{}", &url.as_str()[prefix.len()..])).ok()
+            })
+            .collect_vec();
+        for url in urls.drain(..) {
+            if !mirrored.contains(&url) {
+                mirrored.push(url);
+            }
+        }
+        *urls = mirrored;
+    }
+
+    /// Get the host the file is downloaded from first
+    pub fn host(&self) -> Host {
+        match &self.src {
+            DownloadSource::Urls(urls) => urls.first().map_or(Host::Other, Host::of_url),
+            DownloadSource::Contents(_) | DownloadSource::Path(_) => Host::Other,
+        }
+    }
+
     pub fn filename(&self) -> String {
         self.output
             .file_name()
@@ -529,6 +838,75 @@ impl TempFile {
         })
     }
 
+    /// Get the length of what has been written to the `.part` file so far
+    fn len(&mut self) -> Result<u64> {
+        self.file.flush()?;
+        Ok(self.file.get_ref().metadata()?.len())
+    }
+
+    /// Download the rest of the file from `url`, resuming from the end of the `.part` file
+    /// and waiting whenever the `bandwidth` limit would be exceeded
+    ///
+    /// `update` is called with the length of data that exceeds the `reported` length,
+    /// which is then increased to the length of the file.
+    async fn fetch(
+        &mut self,
+        client: &Client,
+        url: &Url,
+        bandwidth: Option<&BandwidthLimit>,
+        reported: &mut u64,
+        update: impl Fn(usize),
+    ) -> Result<()> {
+        let mut report = |length: u64| {
+            if length > *reported {
+                update((length - *reported) as usize);
+                *reported = length;
+            }
+        };
+
+        let mut start = self.len()?;
+        let request = |start: u64| {
+            let request = client.get(url.clone());
+            if start == 0 {
+                request
+            } else {
+                request.header(RANGE, format!("bytes={start}-"))
+            }
+        };
+        let mut response = request(start).send().await?;
+        // The `.part` file is longer than the file, so download it again
+        if response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+            start = 0;
+            response = request(start).send().await?;
+        }
+        let mut response = response.error_for_status()?;
+        // The server may ignore the range and send the whole file
+        if start != 0 && response.status() != StatusCode::PARTIAL_CONTENT {
+            start = 0;
+        }
+        if start == 0 {
+            self.file.get_ref().set_len(0)?;
+        }
+        report(start);
+
+        let mut length = start;
+        let result = async {
+            while let Some(chunk) = response.chunk().await? {
+                self.file.write_all(&chunk)?;
+                length += chunk.len() as u64;
+                report(length);
+                if let Some(bandwidth) = bandwidth {
+                    bandwidth.take(chunk.len()).await;
+                }
+            }
+            Ok(())
+        }
+        .await;
+        // Keep what was downloaded so it can be resumed
+        self.file.flush()?;
+        result
+    }
+
     /// Compare hashes and remove the `.part` extension.
     pub fn finalize(
         mut self,
@@ -568,6 +946,12 @@ pub fn calculate_sha512(path: &Path) -> std::result::Result<String, io::Error> {
     Ok(base16ct::lower::encode_string(&hasher.finalize()))
 }
 
+pub fn calculate_sha1(path: &Path) -> std::result::Result<String, io::Error> {
+    let mut hasher = sha1::Sha1::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(base16ct::lower::encode_string(&hasher.finalize()))
+}
+
 impl ProfileImport {
     pub async fn download(&self, src_path: &Path) -> Result<PathBuf> {
         match self {
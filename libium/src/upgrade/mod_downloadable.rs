@@ -2,11 +2,13 @@ use futures_util::future::{join, join_all};
 
 use super::{
     check, from_file, from_gh_releases, from_mr_version, from_url, try_from_cf_file,
-    DistributionDeniedError, DownloadData,
+    DistributionDeniedError, DownloadData, Metadata,
 };
 use crate::{
-    config::structs::{Filters, Source, SourceId, SourceKind},
+    cache::{self, Cache, PINNED_TTL, PROJECT_TTL},
+    config::structs::{Filters, Host, ReleaseChannel, Source, SourceId, SourceKind},
     iter_ext::IterExt as _,
+    rate_limit,
     upgrade::from_gh_asset,
     CURSEFORGE_API, GITHUB_API, MODRINTH_API,
 };
@@ -31,19 +33,21 @@ pub enum Error {
     CantUseFileSource,
     #[error(transparent)]
     Io(#[from] crate::upgrade::Error),
+    NotCached(#[from] cache::NotCached),
 }
 type Result<T> = std::result::Result<T, Error>;
 
 impl Source {
     pub async fn fetch_download_file(
         &self,
+        cache: &Cache,
         src_path: Option<&Path>,
         kind: SourceKind,
         filters: Vec<&Filters>,
-    ) -> Result<DownloadData> {
+    ) -> Result<(Metadata, DownloadData)> {
         let mut download_files = vec![];
         let _ = self.each_sources(filters, |filters, id| {
-            download_files.push(id.fetch_download_file(src_path, kind, filters));
+            download_files.push(id.fetch_download_file(cache, src_path, kind, filters));
         });
 
         for file in join_all(download_files).await {
@@ -61,15 +65,25 @@ impl Source {
 impl SourceId {
     pub async fn fetch_download_file(
         &self,
+        cache: &Cache,
         src_path: Option<&Path>,
         kind: SourceKind,
         filters: Vec<&Filters>,
-    ) -> Result<DownloadData> {
+    ) -> Result<(Metadata, DownloadData)> {
         let mut download_files = match self {
             SourceId::Curseforge(id) => {
-                let (files, mod_) = join(
-                    CURSEFORGE_API.get_mod_files(*id),
-                    CURSEFORGE_API.get_mod(*id),
+                let key = id.to_string();
+                let (files, mod_): (Result<_>, Result<_>) = join(
+                    cache.fetch(
+                        &["curseforge", "files", &key],
+                        cache.versions_ttl(),
+                        rate_limit::send(Host::Curseforge, || CURSEFORGE_API.get_mod_files(*id)),
+                    ),
+                    cache.fetch(
+                        &["curseforge", "mod", &key],
+                        PROJECT_TTL,
+                        rate_limit::send(Host::Curseforge, || CURSEFORGE_API.get_mod(*id)),
+                    ),
                 )
                 .await;
                 let (mut files, mod_) = (files?, mod_?);
@@ -81,57 +95,111 @@ impl SourceId {
                     .collect::<Result<Vec<_>>>()?
             }
             SourceId::Modrinth(id) => {
-                let project = MODRINTH_API.get_project(id).await?;
+                let (project, versions): (Result<_>, Result<_>) = join(
+                    cache.fetch(
+                        &["modrinth", "project", id],
+                        PROJECT_TTL,
+                        rate_limit::send(Host::Modrinth, || MODRINTH_API.get_project(id)),
+                    ),
+                    cache.fetch(
+                        &["modrinth", "versions", id],
+                        cache.versions_ttl(),
+                        rate_limit::send(Host::Modrinth, || MODRINTH_API.list_versions(id)),
+                    ),
+                )
+                .await;
+                let project = project?;
 
-                MODRINTH_API
-                    .list_versions(id)
-                    .await?
+                versions?
                     .into_iter()
-                    .map(|version| {
-                        from_mr_version(kind, version, Some(project.project_type.clone()))
-                    })
+                    .map(|version| from_mr_version(kind, version, &project))
                     .collect_vec()
             }
-            SourceId::Github(owner, repo) => GITHUB_API
-                .repos(owner, repo)
-                .releases()
-                .list()
-                .send()
+            SourceId::Github(owner, repo) => cache
+                .fetch::<_, _, Error>(
+                    &["github", "releases", owner, repo],
+                    cache.versions_ttl(),
+                    rate_limit::send(Host::Github, || async {
+                        GITHUB_API
+                            .repos(owner, repo)
+                            .releases()
+                            .list()
+                            .send()
+                            .await
+                            .map(|page| page.items)
+                    }),
+                )
                 .await
-                .map(|r| from_gh_releases(kind, r.items))?,
+                .map(|releases| from_gh_releases(kind, (owner, repo), releases))?,
             SourceId::File(path) => match src_path {
                 Some(src_path) => vec![from_file(kind, src_path, path)?],
                 None => return Err(Error::CantUseFileSource),
             },
-            SourceId::Url(url) => vec![from_url(kind, url).await?],
+            SourceId::Url(url) => vec![from_url(cache, kind, url).await?],
             SourceId::PinnedCurseforge(mod_id, pin) => {
-                let (mod_file, mod_) = join(
-                    CURSEFORGE_API.get_mod_file(*mod_id, *pin),
-                    CURSEFORGE_API.get_mod(*mod_id),
+                let key = mod_id.to_string();
+                let (mod_file, mod_): (Result<_>, Result<_>) = join(
+                    cache.fetch(
+                        &["curseforge", "file", &key, &pin.to_string()],
+                        PINNED_TTL,
+                        rate_limit::send(Host::Curseforge, || {
+                            CURSEFORGE_API.get_mod_file(*mod_id, *pin)
+                        }),
+                    ),
+                    cache.fetch(
+                        &["curseforge", "mod", &key],
+                        PROJECT_TTL,
+                        rate_limit::send(Host::Curseforge, || CURSEFORGE_API.get_mod(*mod_id)),
+                    ),
                 )
                 .await;
                 let (mod_file, mod_) = (mod_file?, mod_?);
 
-                let cf = try_from_cf_file(kind, mod_file, mod_.class_id)?;
-                return Ok(cf.1);
+                return Ok(try_from_cf_file(kind, mod_file, mod_.class_id)?);
             }
             SourceId::PinnedModrinth(id, pin) => {
-                let (mr_version, mr_project) =
-                    join(MODRINTH_API.get_version(pin), MODRINTH_API.get_project(id)).await;
+                let (mr_version, mr_project): (Result<_>, Result<_>) = join(
+                    cache.fetch(
+                        &["modrinth", "version", pin],
+                        PINNED_TTL,
+                        rate_limit::send(Host::Modrinth, || MODRINTH_API.get_version(pin)),
+                    ),
+                    cache.fetch(
+                        &["modrinth", "project", id],
+                        PROJECT_TTL,
+                        rate_limit::send(Host::Modrinth, || MODRINTH_API.get_project(id)),
+                    ),
+                )
+                .await;
                 let (mr_version, mr_project) = (mr_version?, mr_project?);
 
-                let mr = from_mr_version(kind, mr_version, Some(mr_project.project_type));
-                return Ok(mr.1);
+                return Ok(from_mr_version(kind, mr_version, &mr_project));
             }
             SourceId::PinnedGithub((owner, repo), pin) => {
-                return Ok(from_gh_asset(
-                    kind,
-                    GITHUB_API
-                        .repos(owner, repo)
-                        .release_assets()
-                        .get(*pin as u64)
-                        .await?,
-                ))
+                let asset = cache
+                    .fetch::<_, _, Error>(
+                        &["github", "asset", owner, repo, &pin.to_string()],
+                        PINNED_TTL,
+                        rate_limit::send(Host::Github, || async {
+                            GITHUB_API
+                                .repos(owner, repo)
+                                .release_assets()
+                                .get(*pin as u64)
+                                .await
+                        }),
+                    )
+                    .await?;
+
+                // Assets don't carry release metadata, so use the asset itself
+                let metadata = Metadata {
+                    title: asset.name.clone(),
+                    description: String::new(),
+                    filename: asset.name.clone(),
+                    channel: ReleaseChannel::Release,
+                    game_versions: vec![],
+                    loaders: vec![],
+                };
+                return Ok((metadata, from_gh_asset(kind, (owner, repo), asset)));
             }
         };
 
@@ -155,6 +223,6 @@ impl SourceId {
 
         let index =
             super::check::select_latest(download_files.iter().map(|(m, _)| m), filters).await?;
-        Ok(download_files.into_iter().nth(index).unwrap().1)
+        Ok(download_files.into_iter().nth(index).unwrap())
     }
 }
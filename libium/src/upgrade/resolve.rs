@@ -0,0 +1,413 @@
+use super::{mod_downloadable, DownloadData};
+use crate::{
+    cache::{Cache, PROJECT_TTL},
+    config::structs::{ExcludedDependency, Host, SourceId},
+    rate_limit, MODRINTH_API,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+/// The project a source or file belongs to, regardless of the version it is pinned to
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ProjectKey {
+    Curseforge(i32),
+    Modrinth(String),
+    Github(String, String),
+}
+
+impl ProjectKey {
+    /// Get the project of `id`, `None` for files and URLs since they don't belong to a project
+    ///
+    /// Modrinth slugs aren't resolved, so they only match the files of their project once resolved.
+    pub fn of(id: &SourceId) -> Option<Self> {
+        match id {
+            SourceId::Curseforge(id) | SourceId::PinnedCurseforge(id, _) => {
+                Some(Self::Curseforge(*id))
+            }
+            SourceId::Modrinth(id) | SourceId::PinnedModrinth(id, _) => {
+                Some(Self::Modrinth(id.clone()))
+            }
+            // GitHub repository names are case insensitive
+            SourceId::Github(owner, repo) | SourceId::PinnedGithub((owner, repo), _) => {
+                Some(Self::Github(owner.to_lowercase(), repo.to_lowercase()))
+            }
+            SourceId::File(_) | SourceId::Url(_) => None,
+        }
+    }
+}
+
+impl ExcludedDependency {
+    /// Get the projects excluded without looking up slugs
+    pub fn literal_projects(&self) -> Vec<ProjectKey> {
+        match self {
+            ExcludedDependency::Id(id) => ProjectKey::of(id).into_iter().collect(),
+            ExcludedDependency::Slug(slug) => {
+                let mut projects = vec![ProjectKey::Modrinth(slug.clone())];
+                if let Ok(id) = slug.parse() {
+                    projects.push(ProjectKey::Curseforge(id));
+                }
+                projects
+            }
+        }
+    }
+
+    /// Get the projects excluded, looking up the project ID of a Modrinth slug through `cache`
+    pub async fn projects(
+        &self,
+        cache: &Cache,
+    ) -> Result<Vec<ProjectKey>, mod_downloadable::Error> {
+        let mut projects = self.literal_projects();
+        if let ExcludedDependency::Slug(slug) = self {
+            if slug.parse::<i32>().is_err() {
+                let project = cache
+                    .fetch::<_, _, mod_downloadable::Error>(
+                        &["modrinth", "project", slug],
+                        PROJECT_TTL,
+                        rate_limit::send(Host::Modrinth, || MODRINTH_API.get_project(slug)),
+                    )
+                    .await?;
+                projects.push(ProjectKey::Modrinth(project.id));
+            }
+        }
+        Ok(projects)
+    }
+}
+
+/// Get the projects excluded by `exclusions`, looking up Modrinth slugs through `cache`
+///
+/// `on_error` is called for slugs that couldn't be looked up, only their literal projects are excluded then.
+pub async fn excluded_projects(
+    cache: &Cache,
+    exclusions: &[ExcludedDependency],
+    mut on_error: impl FnMut(&ExcludedDependency, mod_downloadable::Error),
+) -> HashSet<ProjectKey> {
+    let mut projects = HashSet::new();
+    for exclusion in exclusions {
+        match exclusion.projects(cache).await {
+            Ok(excluded) => projects.extend(excluded),
+            Err(err) => {
+                on_error(exclusion, err);
+                projects.extend(exclusion.literal_projects());
+            }
+        }
+    }
+    projects
+}
+
+/// A file resolved from a source
+#[derive(Debug, Clone)]
+pub struct Node {
+    /// The name of the source the file was resolved from
+    pub name: String,
+    /// The name of the profile's source that pulled this file in, `None` if it is the profile's source
+    pub required_by: Option<String>,
+    pub file: DownloadData,
+}
+
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.name, self.file.filename())?;
+        if let Some(required_by) = &self.required_by {
+            write!(f, ", required by {required_by}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Two resolved files that declare each other incompatible
+#[derive(Debug, Clone)]
+pub struct Conflict {
+    /// The file that declared the conflict
+    pub file: Node,
+    pub conflicts_with: Node,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub struct ConflictError(pub Vec<Conflict>);
+
+impl fmt::Display for ConflictError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "The following files are incompatible with each other:")?;
+        for conflict in &self.0 {
+            write!(
+                f,
+                "\n• {} is incompatible with {}",
+                conflict.file, conflict.conflicts_with
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The files resolved from a profile's sources and the dependencies they pulled in
+#[derive(Debug, Default)]
+pub struct DependencyGraph {
+    nodes: Vec<Node>,
+    /// The projects that shouldn't be added as dependencies
+    excluded: HashSet<ProjectKey>,
+}
+
+/// The result of resolving a dependency graph
+#[derive(Debug)]
+pub struct Resolved {
+    /// The files to install, with every project appearing once
+    pub nodes: Vec<Node>,
+    /// The names of the profile's sources that resolved to the same project as another of its sources,
+    /// along with the name of the source that was kept
+    pub duplicates: Vec<(String, String)>,
+}
+
+impl DependencyGraph {
+    pub fn push(&mut self, node: Node) {
+        self.nodes.push(node);
+    }
+
+    pub fn extend(&mut self, nodes: impl IntoIterator<Item = Node>) {
+        self.nodes.extend(nodes);
+    }
+
+    /// Exclude `projects` from being added as dependencies
+    pub fn exclude(&mut self, projects: impl IntoIterator<Item = ProjectKey>) {
+        self.excluded.extend(projects);
+    }
+
+    pub fn excluded(&self) -> &HashSet<ProjectKey> {
+        &self.excluded
+    }
+
+    /// Unify the files belonging to the same project, and check that none of the remaining files conflict
+    ///
+    /// Files are the same project if they have the same project ID or the same SHA-1 hash,
+    /// which also unifies a project reached through both Modrinth and CurseForge.
+    /// The profile's own sources are preferred over dependencies, then the first by name.
+    pub fn resolve(mut self) -> Result<Resolved, ConflictError> {
+        self.nodes.sort_by(|a, b| {
+            a.required_by
+                .is_some()
+                .cmp(&b.required_by.is_some())
+                .then_with(|| a.name.cmp(&b.name))
+        });
+
+        let mut nodes: Vec<Node> = Vec::new();
+        let mut duplicates = Vec::new();
+        let mut projects = HashMap::new();
+        let mut hashes = HashMap::new();
+
+        for node in self.nodes {
+            let project = node.file.id.as_ref().and_then(ProjectKey::of);
+            let sha1 = node.file.hash.as_ref().and_then(|hash| hash.sha1());
+
+            let existing = project
+                .as_ref()
+                .and_then(|project| projects.get(project))
+                .or_else(|| sha1.and_then(|sha1| hashes.get(sha1)))
+                .copied();
+            if let Some(index) = existing {
+                let kept: &Node = &nodes[index];
+                if node.required_by.is_none() {
+                    duplicates.push((node.name, kept.name.clone()));
+                }
+                continue;
+            }
+
+            if let Some(project) = project {
+                projects.insert(project, nodes.len());
+            }
+            if let Some(sha1) = sha1 {
+                hashes.insert(sha1.to_owned(), nodes.len());
+            }
+            nodes.push(node);
+        }
+
+        let mut conflicts: Vec<Conflict> = Vec::new();
+        for (index, node) in nodes.iter().enumerate() {
+            for conflict in &node.file.conflicts {
+                let Some(&other) = ProjectKey::of(conflict).and_then(|key| projects.get(&key))
+                else {
+                    continue;
+                };
+                let other_node = &nodes[other];
+
+                // A pinned conflict only applies to that exact version
+                let pinned = matches!(
+                    conflict,
+                    SourceId::PinnedCurseforge(..)
+                        | SourceId::PinnedModrinth(..)
+                        | SourceId::PinnedGithub(..)
+                );
+                if other == index || (pinned && other_node.file.id.as_ref() != Some(conflict)) {
+                    continue;
+                }
+
+                // Both files may declare the conflict, only report it once
+                if conflicts
+                    .iter()
+                    .any(|c| c.file.name == other_node.name && c.conflicts_with.name == node.name)
+                {
+                    continue;
+                }
+
+                conflicts.push(Conflict {
+                    file: node.clone(),
+                    conflicts_with: other_node.clone(),
+                });
+            }
+        }
+
+        if conflicts.is_empty() {
+            Ok(Resolved { nodes, duplicates })
+        } else {
+            Err(ConflictError(conflicts))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::structs::Side,
+        upgrade::{DownloadSource, Hash},
+    };
+    use ferinth::structures::version::Hash as MRHash;
+    use std::path::PathBuf;
+
+    fn node(name: &str, required_by: Option<&str>, id: SourceId, sha1: &str) -> Node {
+        Node {
+            name: name.to_owned(),
+            required_by: required_by.map(ToOwned::to_owned),
+            file: DownloadData {
+                id: Some(id),
+                src: DownloadSource::Urls(vec![]),
+                output: PathBuf::from(format!("{name}.jar")),
+                length: 0,
+                dependencies: vec![],
+                optional_dependencies: vec![],
+                conflicts: vec![],
+                kind: None,
+                side: Side::Both,
+                hash: Some(Hash::Modrinth(MRHash {
+                    sha512: String::new(),
+                    sha1: sha1.to_owned(),
+                    others: HashMap::new(),
+                })),
+                user_hash: vec![],
+            },
+        }
+    }
+
+    fn names(resolved: &Resolved) -> Vec<&str> {
+        resolved
+            .nodes
+            .iter()
+            .map(|node| node.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn unify_by_project() {
+        let mut graph = DependencyGraph::default();
+        graph.push(node(
+            "Dependency of Iris: AANobbMI",
+            Some("Iris"),
+            SourceId::PinnedModrinth("AANobbMI".to_owned(), "old".to_owned()),
+            "a",
+        ));
+        graph.push(node(
+            "Sodium",
+            None,
+            SourceId::PinnedModrinth("AANobbMI".to_owned(), "new".to_owned()),
+            "b",
+        ));
+        let resolved = graph.resolve().unwrap();
+        // The profile's own source is kept over the dependency
+        assert_eq!(names(&resolved), ["Sodium"]);
+        assert!(resolved.duplicates.is_empty());
+    }
+
+    #[test]
+    fn unify_by_sha1() {
+        let mut graph = DependencyGraph::default();
+        graph.push(node(
+            "Sodium",
+            None,
+            SourceId::PinnedModrinth("AANobbMI".to_owned(), "version".to_owned()),
+            "same",
+        ));
+        graph.push(node(
+            "Sodium CF",
+            None,
+            SourceId::PinnedCurseforge(394_468, 1),
+            "same",
+        ));
+        let resolved = graph.resolve().unwrap();
+        assert_eq!(names(&resolved), ["Sodium"]);
+        // Duplicates among the profile's own sources are reported
+        assert_eq!(
+            resolved.duplicates,
+            [("Sodium CF".to_owned(), "Sodium".to_owned())]
+        );
+    }
+
+    #[test]
+    fn pinned_conflict() {
+        let sodium = |version: &str| {
+            node(
+                "Sodium",
+                None,
+                SourceId::PinnedModrinth("AANobbMI".to_owned(), version.to_owned()),
+                "a",
+            )
+        };
+        let iris = || {
+            let mut iris = node(
+                "Iris",
+                None,
+                SourceId::PinnedModrinth("YL57xq9U".to_owned(), "version".to_owned()),
+                "b",
+            );
+            iris.file.conflicts = vec![SourceId::PinnedModrinth(
+                "AANobbMI".to_owned(),
+                "bad".to_owned(),
+            )];
+            iris
+        };
+
+        // A pinned conflict only applies to the version it pins
+        let mut graph = DependencyGraph::default();
+        graph.extend([sodium("good"), iris()]);
+        assert!(graph.resolve().is_ok());
+
+        let mut graph = DependencyGraph::default();
+        graph.extend([sodium("bad"), iris()]);
+        let ConflictError(conflicts) = graph.resolve().unwrap_err();
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].file.name, "Iris");
+        assert_eq!(conflicts[0].conflicts_with.name, "Sodium");
+    }
+
+    #[test]
+    fn conflict_reported_once() {
+        let mut sodium = node(
+            "Sodium",
+            None,
+            SourceId::PinnedModrinth("AANobbMI".to_owned(), "version".to_owned()),
+            "a",
+        );
+        sodium.file.conflicts = vec![SourceId::Modrinth("YL57xq9U".to_owned())];
+        let mut iris = node(
+            "Iris",
+            None,
+            SourceId::PinnedModrinth("YL57xq9U".to_owned(), "version".to_owned()),
+            "b",
+        );
+        iris.file.conflicts = vec![SourceId::Modrinth("AANobbMI".to_owned())];
+
+        let mut graph = DependencyGraph::default();
+        graph.extend([sodium, iris]);
+        let ConflictError(conflicts) = graph.resolve().unwrap_err();
+        assert_eq!(conflicts.len(), 1);
+    }
+}
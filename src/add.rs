@@ -1,8 +1,47 @@
+use crate::Context;
+use anyhow::Result;
 use colored::Colorize as _;
 use libium::{add::Error, iter_ext::IterExt as _};
+use serde::Serialize;
 use std::collections::HashMap;
 
-pub fn display_successes_failures(successes: &[String], failures: Vec<(String, Error)>) -> bool {
+#[derive(Serialize)]
+struct AddOutput<'a> {
+    added: &'a [String],
+    failed: Vec<AddFailure>,
+}
+
+#[derive(Serialize)]
+struct AddFailure {
+    id: String,
+    error: String,
+}
+
+/// Print which sources were added and why the others failed
+///
+/// Returns whether adding any of them failed, other than because they were already added.
+pub fn display_successes_failures(
+    ctx: &Context,
+    successes: &[String],
+    failures: Vec<(String, Error)>,
+) -> Result<bool> {
+    let exit_error = failures
+        .iter()
+        .any(|(_, err)| !matches!(err, Error::AlreadyAdded));
+    if ctx.json {
+        ctx.output(&AddOutput {
+            added: successes,
+            failed: failures
+                .into_iter()
+                .map(|(id, err)| AddFailure {
+                    id,
+                    error: err.to_string(),
+                })
+                .collect(),
+        })?;
+        return Ok(exit_error);
+    }
+
     if !successes.is_empty() {
         println!(
             "{} {}",
@@ -13,13 +52,12 @@ pub fn display_successes_failures(successes: &[String], failures: Vec<(String, E
     // No need to print the ID again if there is only one
     } else if failures.len() == 1 {
         let err = &failures[0].1;
-        return if matches!(err, libium::add::Error::AlreadyAdded) {
+        if matches!(err, Error::AlreadyAdded) {
             println!("{}", err.to_string().yellow());
-            false
         } else {
             println!("{}", err.to_string().red());
-            true
-        };
+        }
+        return Ok(exit_error);
     }
 
     let mut grouped_errors = HashMap::new();
@@ -38,20 +76,18 @@ pub fn display_successes_failures(successes: &[String], failures: Vec<(String, E
         .unwrap_or(0)
         .clamp(0, 50);
 
-    let mut exit_error = false;
     for (err, ids) in grouped_errors {
         println!(
             "{:pad_len$}: {}",
             // Change already added into a warning
-            if err == libium::add::Error::AlreadyAdded.to_string() {
+            if err == Error::AlreadyAdded.to_string() {
                 err.yellow()
             } else {
-                exit_error = true;
                 err.red()
             },
             ids.iter().map(|s| s.italic()).display(", ")
         );
     }
 
-    exit_error
+    Ok(exit_error)
 }
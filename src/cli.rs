@@ -1,9 +1,12 @@
 #![deny(missing_docs)]
 
-use crate::{subcommands::profile, DEFAULT_PARALLEL_TASKS};
+use crate::{
+    subcommands::{export, profile},
+    DEFAULT_PARALLEL_TASKS, DEFAULT_RETRIES,
+};
 use clap::{Args, Parser, Subcommand, ValueEnum, ValueHint};
 use clap_complete::Shell;
-use libium::config::structs::{Filters, ModLoader, Regex, ReleaseChannel, Version};
+use libium::config::structs::{Filters, ModLoader, Regex, ReleaseChannel, SourceKind, Version};
 use std::path::PathBuf;
 
 #[derive(Clone, Debug, Parser)]
@@ -17,7 +20,17 @@ pub struct Ferium {
     pub threads: Option<usize>,
     /// Specify the maximum number of simultaneous parallel tasks.
     #[clap(long, short = 'p', default_value_t = DEFAULT_PARALLEL_TASKS)]
+    #[clap(value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
     pub parallel_tasks: usize,
+    /// Specify how many times a download is retried after a network error.
+    /// The wait before each retry doubles, and the download resumes where it stopped.
+    #[clap(long, default_value_t = DEFAULT_RETRIES)]
+    pub retries: u32,
+    /// Limit the total download speed, in bytes per second, e.g. `5MB` or `500KiB`.
+    /// How many requests and downloads for each host run at the same time is limited
+    /// by the `concurrency` table of the config.
+    #[clap(long, visible_alias = "bw")]
+    pub max_bandwidth: Option<size::Size>,
     /// Set a GitHub personal access token for increasing the GitHub API rate limit.
     #[clap(long, visible_alias = "gh", env = "GITHUB_TOKEN")]
     pub github_token: Option<String>,
@@ -30,6 +43,19 @@ pub struct Ferium {
     #[clap(long, short, visible_aliases = ["config", "conf"])]
     #[clap(value_hint(ValueHint::FilePath))]
     pub config_file: Option<PathBuf>,
+    /// The format to print results in.
+    /// With `json`, the results are printed to stdout as a single JSON document once the subcommand is done,
+    /// and progress is printed to stderr.
+    #[clap(long, global = true, value_enum, default_value_t)]
+    pub output: OutputFormat,
+    /// The directory downloaded files and API responses are cached in.
+    #[clap(long, global = true)]
+    #[clap(value_hint(ValueHint::DirPath))]
+    pub cache_dir: Option<PathBuf>,
+    /// Don't use the network, only use downloaded files and API responses from the cache.
+    /// Upgrading a profile whose files are all locked and were downloaded before works offline.
+    #[clap(long, global = true)]
+    pub offline: bool,
 }
 
 #[derive(Clone, Debug, Subcommand)]
@@ -60,6 +86,18 @@ pub enum SubCommands {
         #[command(flatten)]
         filters: FilterArguments,
     },
+    /// Search Modrinth and Curseforge for mods compatible with the profile, and select which to add
+    Search {
+        /// What to search for
+        #[clap(required = true)]
+        query: Vec<String>,
+        /// Only search this platform, both are searched by default
+        #[clap(long, short)]
+        platform: Option<Platform>,
+        /// The maximum number of results to show from each platform
+        #[clap(long, short, default_value_t = 10)]
+        limit: usize,
+    },
     /// Scan the profile's output directory (or the specified directory) for mods and add them to the profile
     Scan {
         /// The platform you prefer mods to be added from.
@@ -85,6 +123,9 @@ pub enum SubCommands {
         /// Temporarily ignore game version and mod loader checks and add the mods anyway
         #[clap(long, short, visible_alias = "override")]
         force: bool,
+        /// Pin the mods to the exact files found, instead of their latest compatible versions
+        #[clap(long, visible_alias = "lock")]
+        pin: bool,
     },
     /// Print shell auto completions for the specified shell
     Complete {
@@ -104,6 +145,12 @@ pub enum SubCommands {
         /// Complements the verbose flag.
         #[clap(long, short, visible_alias = "md")]
         markdown: bool,
+        /// Show the dependencies each mod pulls in, recursively
+        #[clap(long, conflicts_with_all = ["verbose", "markdown"])]
+        tree: bool,
+        /// List the optional dependencies of the mods that aren't in the profile
+        #[clap(long, conflicts_with_all = ["verbose", "markdown", "tree"])]
+        optional: bool,
     },
     /// Create, configure, delete, switch, or list profiles
     Profile {
@@ -124,7 +171,36 @@ pub enum SubCommands {
     Upgrade {
         #[command(flatten)]
         filters: FilterArguments,
+        /// Resolve the latest compatible versions instead of installing the files in the lockfile.
+        /// Optionally, provide the names of the sources to update, otherwise all sources are updated.
+        #[clap(long, short = 'u', num_args = 0.., value_name = "NAMES")]
+        update: Option<Vec<String>>,
+        /// Only print the files that would be downloaded or moved and the options that would change,
+        /// without changing the minecraft directory or the lockfile
+        #[clap(long)]
+        dry_run: bool,
+        /// Install the profile's mod loader into the minecraft directory too.
+        /// The loader version recorded in the profile is installed, otherwise the latest stable version is installed and recorded.
+        /// Use `--update` to install the latest stable version again.
+        #[clap(long)]
+        install_loader: bool,
+    },
+    /// Resolve the latest compatible version of some sources,
+    /// the rest stay on the files recorded in the lockfile
+    Update {
+        /// The case-insensitive names or IDs of the sources to update.
+        /// If none are provided, every source of the selected kinds is updated.
+        names: Vec<String>,
+        /// Only update sources of these kinds
+        #[clap(long, short)]
+        kind: Vec<SourceKind>,
+        #[command(flatten)]
+        filters: FilterArguments,
     },
+    /// Browse and manage the profile in a full-screen terminal UI,
+    /// with shortcuts to search, add, remove, pin, and upgrade sources
+    Tui,
+    Export(export::Args),
     /// Migrate a ferium config to ogj-ferium, be warned this may not work
     Migrate {
         #[clap(long, short)]
@@ -190,6 +266,8 @@ pub struct FilterArguments {
     #[clap(long, short = 'r')]
     #[clap(alias = "hash")]
     pub hashes: Option<Vec<String>>,
+    #[clap(long)]
+    pub optional_dependencies: Option<bool>,
 }
 
 impl From<FilterArguments> for Filters {
@@ -203,10 +281,31 @@ impl From<FilterArguments> for Filters {
             description: value.description,
             install_overrides: value.install_overrides,
             hashes: value.hashes,
+            optional_dependencies: value.optional_dependencies,
         }
     }
 }
 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Human readable text
+    #[default]
+    Text,
+    /// Machine readable JSON
+    Json,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    /// A Modrinth modpack (`.mrpack`)
+    #[default]
+    #[clap(alias = "modrinth", alias = "mr")]
+    Mrpack,
+    /// A Curseforge modpack (`.zip`)
+    #[clap(alias = "cf")]
+    Curseforge,
+}
+
 #[derive(Clone, Copy, Debug, Default, ValueEnum)]
 pub enum Platform {
     #[default]
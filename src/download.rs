@@ -1,13 +1,15 @@
-use crate::{default_semaphore, warn, SEMAPHORE, STYLE_BYTE, TICK};
+use crate::{warn, Context, CROSS, STYLE_BYTE, TICK};
 use anyhow::{anyhow, bail, Error, Result};
 use colored::Colorize as _;
 use fs_extra::file::{move_file, CopyOptions as FileCopyOptions};
 use indicatif::ProgressBar;
 use libium::{
+    config::structs::Side,
     iter_ext::IterExt as _,
     upgrade::{DownloadData, DownloadSource},
 };
 use parking_lot::Mutex;
+use serde::Serialize;
 use std::{
     fs::{self, create_dir_all, read_dir, remove_file},
     path::{Path, PathBuf},
@@ -16,16 +18,27 @@ use std::{
 };
 use tokio::task::JoinSet;
 
-/// Check the given `directory`
+/// The changes [`clean`] makes to a directory
+#[derive(Debug, Default)]
+pub struct CleanPlan {
+    /// Files that are already downloaded
+    pub up_to_date: Vec<String>,
+    /// Files that will be moved to `directory`/.old
+    pub to_move: Vec<PathBuf>,
+    /// Files that will be deleted, i.e. `.part` files of files that aren't downloaded anymore
+    pub to_delete: Vec<PathBuf>,
+}
+
+/// Check the given `directory` without changing it
 ///
-/// - If there are files there that are not in `to_download` or `to_install`, they will be moved to `directory`/.old
-/// - If a file in `to_download` or `to_install` is already there, it will be removed from the respective vector
-/// - If the file is a `.part` file or if the move failed, the file will be deleted
-pub async fn clean(
+/// - Files that are already there are removed from `to_download` and marked as up to date
+/// - Files that are not in `to_download` are marked to be moved to `directory`/.old if `move_old` is set
+/// - `.part` files are marked to be deleted, unless they can be resumed when downloading a file in `to_download`
+pub fn plan_clean(
     directory: &Path,
     to_download: &mut Vec<DownloadData>,
     move_old: bool,
-) -> Result<()> {
+) -> Result<CleanPlan> {
     let dupes = find_dupes_by_key(to_download, DownloadData::filename);
     if !dupes.is_empty() {
         warn!(
@@ -37,13 +50,7 @@ pub async fn clean(
                 .display(", ")
         );
     }
-    if move_old {
-        let old_dir = directory.join(".old");
-        if old_dir.exists() {
-            fs::remove_dir_all(&old_dir)?;
-        }
-        create_dir_all(old_dir)?;
-    }
+    let mut plan = CleanPlan::default();
     for file in read_dir(directory)? {
         let file = file?;
         // If it's a file
@@ -58,26 +65,88 @@ pub async fn clean(
             {
                 // Don't download it
                 to_download.swap_remove(index);
-            // Or else, move the file to `directory`/.old
-            // If the file is a `.part` file or if the move failed, delete the file
-            } else if filename.ends_with("part")
-                || (move_old
-                    && move_file(
-                        file.path(),
-                        directory.join(".old").join(filename),
-                        &FileCopyOptions::new(),
-                    )
-                    .is_err())
-            {
-                remove_file(file.path())?;
+                plan.up_to_date.push(filename.to_owned());
+            } else if filename.ends_with("part") {
+                let resumable = to_download.iter().any(|thing| {
+                    thing
+                        .output
+                        .with_extension("part")
+                        .file_name()
+                        .is_some_and(|part| part == filename)
+                });
+                if !resumable {
+                    plan.to_delete.push(file.path());
+                }
+            } else if move_old {
+                plan.to_move.push(file.path());
             }
         }
     }
+    Ok(plan)
+}
+
+/// Check the given `directory`
+///
+/// - If there are files there that are not in `to_download` or `to_install`, they will be moved to `directory`/.old
+/// - If a file in `to_download` or `to_install` is already there, it will be removed from the respective vector
+/// - If the file is a `.part` file that can't be resumed or if the move failed, the file will be deleted
+pub async fn clean(
+    directory: &Path,
+    to_download: &mut Vec<DownloadData>,
+    move_old: bool,
+) -> Result<()> {
+    let plan = plan_clean(directory, to_download, move_old)?;
+    let old_dir = directory.join(".old");
+    if move_old {
+        if old_dir.exists() {
+            fs::remove_dir_all(&old_dir)?;
+        }
+        create_dir_all(&old_dir)?;
+    }
+    for path in plan.to_delete {
+        remove_file(path)?;
+    }
+    for path in plan.to_move {
+        // If the move failed, delete the file
+        let Some(filename) = path.file_name() else {
+            continue;
+        };
+        if move_file(&path, old_dir.join(filename), &FileCopyOptions::new()).is_err() {
+            remove_file(path)?;
+        }
+    }
+    Ok(())
+}
+
+/// A file that could not be downloaded
+#[derive(Debug, Serialize)]
+pub struct DownloadError {
+    pub filename: String,
+    pub error: String,
+}
+
+/// Download and install the files in `to_download` to `minecraft_dir`, failing if any of them couldn't be downloaded
+pub async fn download(
+    ctx: &Context,
+    minecraft_dir: PathBuf,
+    to_download: Vec<DownloadData>,
+) -> Result<()> {
+    let failed = try_download(ctx, minecraft_dir, to_download).await?;
+    if let Some(DownloadError { filename, error }) = failed.into_iter().next() {
+        bail!("Could not download {filename}: {error}");
+    }
     Ok(())
 }
 
-/// Download and install the files in `to_download` and `to_install` to the paths set in `profile`
-pub async fn download(minecraft_dir: PathBuf, to_download: Vec<DownloadData>) -> Result<()> {
+/// Download and install the files in `to_download` to `minecraft_dir`
+///
+/// The downloads share a single bandwidth limit.
+/// The files that couldn't be downloaded are returned, the others are still downloaded.
+pub async fn try_download(
+    ctx: &Context,
+    minecraft_dir: PathBuf,
+    to_download: Vec<DownloadData>,
+) -> Result<Vec<DownloadError>> {
     let progress_bar = Arc::new(Mutex::new(
         ProgressBar::new(
             to_download
@@ -97,36 +166,52 @@ pub async fn download(minecraft_dir: PathBuf, to_download: Vec<DownloadData>) ->
         let progress_bar = Arc::clone(&progress_bar);
         let client = client.clone();
         let minecraft_dir = minecraft_dir.clone();
+        let ctx = ctx.clone();
 
         tasks.spawn(async move {
-            let _permit = SEMAPHORE.get_or_init(default_semaphore).acquire().await?;
-
-            let (length, filename) = downloadable
-                .download(client, minecraft_dir, |additional| {
-                    progress_bar.lock().inc(additional as u64);
-                })
-                .await?;
-            progress_bar.lock().println(format!(
-                "{} Downloaded  {:>7}  {}",
-                &*TICK,
-                size::Size::from_bytes(length)
-                    .format()
-                    .with_base(size::Base::Base10)
-                    .to_string(),
-                filename.dimmed(),
-            ));
-            Ok::<(), Error>(())
+            let filename = downloadable.filename();
+            let result = async {
+                let _permits = ctx.acquire_permits(downloadable.host()).await?;
+                downloadable
+                    .download(client, minecraft_dir, &ctx.download, |additional| {
+                        progress_bar.lock().inc(additional as u64);
+                    })
+                    .await
+                    .map_err(Error::from)
+            }
+            .await;
+            match result {
+                Ok((length, filename)) => {
+                    progress_bar.lock().println(format!(
+                        "{} Downloaded  {:>7}  {}",
+                        *TICK,
+                        size::Size::from_bytes(length)
+                            .format()
+                            .with_base(size::Base::Base10)
+                            .to_string(),
+                        filename.dimmed(),
+                    ));
+                    None
+                }
+                Err(err) => {
+                    progress_bar
+                        .lock()
+                        .println(format!("{CROSS} {filename}  {err}").red().to_string());
+                    Some(DownloadError {
+                        filename,
+                        error: err.to_string(),
+                    })
+                }
+            }
         });
     }
-    for res in tasks.join_all().await {
-        res?;
-    }
+    let failed = tasks.join_all().await.into_iter().flatten().collect();
     Arc::try_unwrap(progress_bar)
         .map_err(|_| anyhow!("Failed to run threads to completion"))?
         .into_inner()
         .finish_and_clear();
 
-    Ok(())
+    Ok(failed)
 }
 
 /// Construct a `to_install` vector from the `directory`
@@ -141,12 +226,15 @@ pub fn read_overrides(to_install: &mut Vec<DownloadData>, directory: &Path) -> R
             };
 
             to_install.push(DownloadData {
+                id: None,
                 src: DownloadSource::Path(entry.path()),
                 output: output.to_path_buf(),
                 length: entry.metadata()?.len(),
                 dependencies: vec![],
+                optional_dependencies: vec![],
                 conflicts: vec![],
                 kind: None,
+                side: Side::Both,
                 hash: None,
                 user_hash: vec![],
             });
@@ -5,3 +5,16 @@ macro_rules! warn {
         eprintln!("{}{}", "Warning! ".bright_yellow(), format!($($arg)*).bright_yellow())
     }};
 }
+
+/// Print a human readable status message,
+/// to stderr if the [`Context`](crate::Context) `$ctx` prints JSON so that stdout only contains the JSON document.
+#[macro_export]
+macro_rules! status {
+    ($ctx:expr, $($arg:tt)*) => {{
+        if $ctx.json {
+            eprintln!($($arg)*)
+        } else {
+            println!($($arg)*)
+        }
+    }};
+}
@@ -1,4 +1,3 @@
-#![cfg_attr(test, feature(assert_matches))]
 #![deny(
     clippy::all,
     clippy::perf,
@@ -28,44 +27,137 @@ mod tests;
 
 use anyhow::{anyhow, bail, ensure, Context as _, Result};
 use clap::{CommandFactory, Parser};
-use cli::{Ferium, ProfileSubCommands, SubCommands};
+use cli::{Ferium, OutputFormat, ProfileSubCommands, SubCommands};
 use colored::{ColoredString, Colorize};
 use indicatif::ProgressStyle;
 use inquire::Select;
 use libium::{
+    cache::Cache,
     config::{
         self, read_config,
         structs::{
-            Config, Filters, Profile, ProfileItem, ProfileItemConfig, ProfileSource,
-            ProfileSourceMut, SourceId, SourceKind,
+            Concurrency, Config, Filters, Host, LoaderServers, Profile, ProfileItem,
+            ProfileItemConfig, ProfileSource, ProfileSourceMut, SourceId, SourceKind,
         },
     },
     iter_ext::IterExt as _,
+    upgrade::{BandwidthLimit, DownloadOptions},
 };
+use parking_lot::Mutex;
+use serde::Serialize;
 use std::{
     env::{set_var, var_os},
     path::PathBuf,
     process::ExitCode,
-    sync::{LazyLock, OnceLock},
+    sync::{Arc, LazyLock},
 };
 use subcommands::profile;
-use tokio::sync::Semaphore;
+use tokio::sync::{Semaphore, SemaphorePermit};
 
 const CROSS: &str = "×";
 static TICK: LazyLock<ColoredString> = LazyLock::new(|| "✓".green());
 
+/// The JSON document printed to stdout once the subcommand is done, if it prints one
+pub type JsonDocument = Arc<Mutex<Option<serde_json::Value>>>;
+
+pub const DEFAULT_RETRIES: u32 = 3;
 pub const DEFAULT_PARALLEL_TASKS: usize = 50;
-pub static SEMAPHORE: OnceLock<Semaphore> = OnceLock::new();
-#[must_use]
-pub const fn default_semaphore() -> Semaphore {
-    Semaphore::const_new(DEFAULT_PARALLEL_TASKS)
+
+/// The settings of a single invocation, from the CLI options and the config
+#[derive(Clone)]
+pub struct Context {
+    /// Whether results are printed as JSON, set from `--output`
+    pub json: bool,
+    document: JsonDocument,
+    /// The options to download files with, the files downloaded with them share the bandwidth limit
+    pub download: DownloadOptions,
+    /// The servers mod loaders are installed from, set from the config
+    pub loader_servers: LoaderServers,
+    /// The limits on simultaneous tasks
+    semaphores: Arc<Semaphores>,
+}
+
+impl Context {
+    fn new(cli_app: &Ferium, config: &Config, document: JsonDocument) -> Self {
+        let max_bandwidth = cli_app
+            .max_bandwidth
+            .and_then(|max_bandwidth| max_bandwidth.bytes().try_into().ok())
+            .filter(|&max_bandwidth| max_bandwidth != 0);
+        Self {
+            json: cli_app.output == OutputFormat::Json,
+            document,
+            download: DownloadOptions {
+                retries: cli_app.retries,
+                cache: Cache {
+                    dir: cli_app
+                        .cache_dir
+                        .clone()
+                        .unwrap_or_else(|| libium::PROJECT_DIRS.cache_dir().to_owned()),
+                    offline: cli_app.offline,
+                    refresh: false,
+                },
+                bandwidth: max_bandwidth
+                    .map(|max_bandwidth| Arc::new(BandwidthLimit::new(max_bandwidth))),
+            },
+            loader_servers: config.loader_servers.clone(),
+            semaphores: Arc::new(Semaphores::new(cli_app.parallel_tasks, config.concurrency)),
+        }
+    }
+
+    /// Set the JSON document printed once the subcommand is done, replacing the previous one
+    fn output(&self, document: &impl Serialize) -> Result<()> {
+        *self.document.lock() = Some(serde_json::to_value(document)?);
+        Ok(())
+    }
+
+    /// Wait until a task for `host` can run without exceeding the limit for `host` or `--parallel-tasks`
+    ///
+    /// The task can run until the returned permits are dropped.
+    async fn acquire_permits(
+        &self,
+        host: Host,
+    ) -> Result<(SemaphorePermit<'_>, SemaphorePermit<'_>)> {
+        let host_permit = self.semaphores.host(host).acquire().await?;
+        let permit = self.semaphores.all.acquire().await?;
+        Ok((host_permit, permit))
+    }
+}
+
+/// The limits on simultaneous tasks, from `--parallel-tasks` and the `concurrency` table of the config
+struct Semaphores {
+    all: Semaphore,
+    modrinth: Semaphore,
+    curseforge: Semaphore,
+    github: Semaphore,
+    other: Semaphore,
+}
+
+impl Semaphores {
+    fn new(parallel_tasks: usize, concurrency: Concurrency) -> Self {
+        Self {
+            all: Semaphore::new(parallel_tasks),
+            modrinth: Semaphore::new(concurrency.modrinth.get()),
+            curseforge: Semaphore::new(concurrency.curseforge.get()),
+            github: Semaphore::new(concurrency.github.get()),
+            other: Semaphore::new(concurrency.other.get()),
+        }
+    }
+
+    fn host(&self, host: Host) -> &Semaphore {
+        match host {
+            Host::Modrinth => &self.modrinth,
+            Host::Curseforge => &self.curseforge,
+            Host::Github => &self.github,
+            Host::Other => &self.other,
+        }
+    }
 }
 
 /// Indicatif themes
 #[expect(clippy::expect_used)]
 pub static STYLE_NO: LazyLock<ProgressStyle> = LazyLock::new(|| {
     ProgressStyle::default_bar()
-        .template("{spinner} {elapsed} [{wide_bar:.cyan/blue}] {pos:.cyan}/{len:.blue}")
+        .template("{spinner} {elapsed} [{wide_bar:.cyan/blue}] {pos:.cyan}/{len:.blue} {msg}")
         .expect("Progress bar template parse failure")
         .progress_chars("#>-")
 });
@@ -124,7 +216,17 @@ fn main() -> ExitCode {
     }
 }
 
-async fn actual_main(mut cli_app: Ferium) -> Result<()> {
+/// Run the subcommand, then print the JSON document it produced even if it failed
+async fn actual_main(cli_app: Ferium) -> Result<()> {
+    let document = JsonDocument::default();
+    let result = run(cli_app, &document).await;
+    if let Some(document) = document.lock().take() {
+        println!("{document}");
+    }
+    result
+}
+
+async fn run(mut cli_app: Ferium, document: &JsonDocument) -> Result<()> {
     // The complete command should not require a config.
     // See [#139](https://github.com/gorilla-devs/ferium/issues/139) for why this might be a problem.
     if let SubCommands::Complete { shell } = cli_app.subcommand {
@@ -143,19 +245,17 @@ async fn actual_main(mut cli_app: Ferium) -> Result<()> {
         };
     }
 
-    if let Some(token) = cli_app.github_token {
+    if let Some(token) = &cli_app.github_token {
         if !token.is_empty() {
             set_var("GITHUB_TOKEN", token);
         }
     }
-    if let Some(key) = cli_app.curseforge_api_key {
+    if let Some(key) = &cli_app.curseforge_api_key {
         if !key.is_empty() {
             set_var("CURSEFORGE_API_KEY", key);
         }
     }
 
-    let _ = SEMAPHORE.set(Semaphore::new(cli_app.parallel_tasks));
-
     let old_default_config_path = libium::BASE_DIRS
         .home_dir()
         .join(".config")
@@ -163,6 +263,7 @@ async fn actual_main(mut cli_app: Ferium) -> Result<()> {
         .join("ogj-config.toml");
     let config_path = &cli_app
         .config_file
+        .clone()
         .or_else(|| var_os("OGJ_FERIUM_CONFIG_FILE").map(Into::into))
         .unwrap_or({
             #[cfg(target_os = "macos")]
@@ -183,6 +284,7 @@ async fn actual_main(mut cli_app: Ferium) -> Result<()> {
 
     let mut config = config::read_config(config_path)?;
     handle_invalid_paths(config_path, &mut config).await?;
+    let ctx = Context::new(&cli_app, &config, Arc::clone(document));
 
     let mut did_add_fail = false;
 
@@ -198,6 +300,7 @@ async fn actual_main(mut cli_app: Ferium) -> Result<()> {
             resourcepacks_dir,
             shaderpacks_dir,
             force,
+            pin,
         } => {
             let (item, mut profile) = get_active_profile(&mut config)?;
 
@@ -230,26 +333,10 @@ async fn actual_main(mut cli_app: Ferium) -> Result<()> {
             spinner.set_message("Adding sources");
 
             let mut send_ids = Vec::new();
-            for id in ids {
-                use libium::config::structs::SourceId;
-                match id {
-                    (filename, None, None) => {
-                        println!("{} {}", "Unknown file:".yellow(), filename.dimmed());
-                    }
-                    (_, Some(mr_id), None) => {
-                        send_ids.push(SourceId::Modrinth(mr_id));
-                    }
-                    (_, None, Some(cf_id)) => {
-                        send_ids.push(SourceId::Curseforge(cf_id));
-                    }
-                    (_, Some(mr_id), Some(cf_id)) => match platform {
-                        cli::Platform::Modrinth => {
-                            send_ids.push(SourceId::Modrinth(mr_id));
-                        }
-                        cli::Platform::Curseforge => {
-                            send_ids.push(SourceId::Curseforge(cf_id));
-                        }
-                    },
+            for (filename, modrinth, curseforge) in ids {
+                match scanned_source(platform, pin, modrinth, curseforge) {
+                    Some(id) => send_ids.push(id),
+                    None => status!(ctx, "{} {}", "Unknown file:".yellow(), filename.dimmed()),
                 }
             }
 
@@ -257,7 +344,7 @@ async fn actual_main(mut cli_app: Ferium) -> Result<()> {
                 libium::add(&mut profile, send_ids, !force, Filters::empty()).await?;
             spinner.finish_and_clear();
 
-            did_add_fail = add::display_successes_failures(&successes, failures);
+            did_add_fail = add::display_successes_failures(&ctx, &successes, failures)?;
 
             profile.write()?;
         }
@@ -282,39 +369,70 @@ async fn actual_main(mut cli_app: Ferium) -> Result<()> {
             );
 
             let identifiers = if let Some(pin) = pin {
-                let id = libium::add::parse_id(identifiers[0].clone());
+                let id = libium::add::parse_id(&ctx.download.cache, identifiers[0].clone()).await?;
+                // A URL may already name a version, which is replaced by `pin`
                 vec![match id {
-                    SourceId::Curseforge(project_id) => SourceId::PinnedCurseforge(
+                    SourceId::Curseforge(project_id)
+                    | SourceId::PinnedCurseforge(project_id, _) => SourceId::PinnedCurseforge(
                         project_id,
                         pin.parse().context("Invalid file ID for CurseForge file")?,
                     ),
-                    SourceId::Modrinth(project_id) => SourceId::PinnedModrinth(project_id, pin),
-                    SourceId::Github(owner, repo) => SourceId::PinnedGithub(
-                        (owner, repo),
-                        pin.parse().context("Invalid asset ID for GitHub")?,
-                    ),
-                    _ => unreachable!(),
+                    SourceId::Modrinth(project_id) | SourceId::PinnedModrinth(project_id, _) => {
+                        SourceId::PinnedModrinth(project_id, pin)
+                    }
+                    SourceId::Github(owner, repo) | SourceId::PinnedGithub((owner, repo), _) => {
+                        SourceId::PinnedGithub(
+                            (owner, repo),
+                            pin.parse().context("Invalid asset ID for GitHub")?,
+                        )
+                    }
+                    SourceId::File(_) | SourceId::Url(_) => {
+                        bail!("Only CurseForge, Modrinth, and GitHub projects can be pinned")
+                    }
                 }]
             } else {
-                identifiers
-                    .into_iter()
-                    .map(libium::add::parse_id)
-                    .collect_vec()
+                let mut ids = Vec::with_capacity(identifiers.len());
+                for identifier in identifiers {
+                    ids.push(libium::add::parse_id(&ctx.download.cache, identifier).await?);
+                }
+                ids
             };
 
             let (successes, failures) =
                 libium::add(&mut profile, identifiers, !force, filters).await?;
 
-            did_add_fail = add::display_successes_failures(&successes, failures);
+            did_add_fail = add::display_successes_failures(&ctx, &successes, failures)?;
 
             profile.write()?;
         }
-        SubCommands::List { verbose, markdown } => {
+        SubCommands::Search {
+            query,
+            platform,
+            limit,
+        } => {
+            let (_item, mut profile) = get_active_profile(&mut config)?;
+            did_add_fail =
+                subcommands::search(&ctx, &mut profile, &query.join(" "), platform, limit).await?;
+            profile.write()?;
+        }
+        SubCommands::List {
+            verbose,
+            markdown,
+            tree,
+            optional,
+        } => {
+            let path = get_active_profile_path(&mut config)?;
             let (item, mut profile) = get_active_profile(&mut config)?;
             check_empty_profile(&profile)?;
 
-            if verbose {
-                subcommands::list::verbose(&mut profile, markdown).await?;
+            if optional {
+                subcommands::list::optional(&ctx, path.as_deref(), item, &profile).await?;
+            } else if tree {
+                subcommands::list::tree(&ctx, path.as_deref(), item, &profile).await?;
+            } else if verbose {
+                subcommands::list::verbose(&ctx, &mut profile, markdown).await?;
+            } else if ctx.json {
+                ctx.output(&subcommands::list::json(item, &profile))?;
             } else {
                 println!(
                     "{} {} on {} {}\n",
@@ -363,7 +481,7 @@ async fn actual_main(mut cli_app: Ferium) -> Result<()> {
                     subcommands::profile::configure(&mut config, args).await?;
                 }
                 ProfileSubCommands::Create(args) => {
-                    subcommands::profile::create(&mut config, args).await?;
+                    subcommands::profile::create(&ctx, &mut config, args).await?;
                 }
                 ProfileSubCommands::Delete {
                     profile_name,
@@ -373,13 +491,30 @@ async fn actual_main(mut cli_app: Ferium) -> Result<()> {
                 }
                 ProfileSubCommands::Info => {
                     let (item, profile) = get_active_profile(&mut config)?;
-                    subcommands::profile::info(item, &profile, true);
+                    if ctx.json {
+                        ctx.output(&subcommands::profile::info_json(item, &profile, true))?;
+                    } else {
+                        subcommands::profile::info(item, &profile, true);
+                    }
                 }
 
                 ProfileSubCommands::List => {
-                    for (i, (item, profile)) in try_iter_profiles(&mut config.profiles).enumerate()
-                    {
-                        subcommands::profile::info(item, &profile, i == config.active_profile);
+                    let profiles = try_iter_profiles(&mut config.profiles).enumerate();
+                    if ctx.json {
+                        let infos = profiles
+                            .map(|(i, (item, profile))| {
+                                subcommands::profile::info_json(
+                                    item,
+                                    &profile,
+                                    i == config.active_profile,
+                                )
+                            })
+                            .collect_vec();
+                        ctx.output(&infos)?;
+                    } else {
+                        for (i, (item, profile)) in profiles {
+                            subcommands::profile::info(item, &profile, i == config.active_profile);
+                        }
                     }
                 }
 
@@ -397,7 +532,8 @@ async fn actual_main(mut cli_app: Ferium) -> Result<()> {
                 }
             }
             if default_flag {
-                println!(
+                status!(
+                    ctx,
                     "{} ogj-ferium profile help {}",
                     "Use".yellow(),
                     "for more information about this subcommand".yellow()
@@ -407,14 +543,70 @@ async fn actual_main(mut cli_app: Ferium) -> Result<()> {
         SubCommands::Remove { mod_names } => {
             let (_item, mut profile) = get_active_profile(&mut config)?;
             check_empty_profile(&profile)?;
-            subcommands::remove(&mut profile, mod_names)?;
+            subcommands::remove(&ctx, &mut profile, mod_names)?;
             profile.write()?;
         }
-        SubCommands::Upgrade { filters } => {
-            let path = get_active_working_dir(&mut config)?;
+        SubCommands::Upgrade {
+            filters,
+            update,
+            dry_run,
+            install_loader,
+        } => {
+            let path = get_active_profile_path(&mut config)?;
+            let (item, mut profile) = get_active_profile(&mut config)?;
+            check_empty_profile(&profile)?;
+            if install_loader {
+                let update_all = update.as_ref().is_some_and(Vec::is_empty);
+                subcommands::install_loader(&ctx, item, &mut profile, update_all, dry_run).await?;
+            }
+            let result = subcommands::upgrade(
+                &ctx,
+                path.as_deref(),
+                item,
+                &profile,
+                filters.into(),
+                update.into(),
+                dry_run,
+            )
+            .await;
+            // Record the installed loader version even if some sources failed
+            if install_loader && !dry_run {
+                profile.write()?;
+            }
+            result?;
+        }
+        SubCommands::Export(args) => {
+            let path = get_active_profile_path(&mut config)?;
+            let (item, profile) = get_active_profile(&mut config)?;
+            check_empty_profile(&profile)?;
+            subcommands::export(&ctx, path.as_deref(), item, &profile, args).await?;
+        }
+        SubCommands::Update {
+            names,
+            kind,
+            filters,
+        } => {
+            let path = get_active_profile_path(&mut config)?;
             let (item, profile) = get_active_profile(&mut config)?;
             check_empty_profile(&profile)?;
-            subcommands::upgrade(path.as_deref(), item, &profile, filters.into()).await?;
+            subcommands::update(
+                &ctx,
+                path.as_deref(),
+                item,
+                &profile,
+                names,
+                kind,
+                filters.into(),
+            )
+            .await?;
+        }
+        SubCommands::Tui => {
+            let path = get_active_profile_path(&mut config)?;
+            let (item, mut profile) = get_active_profile(&mut config)?;
+            // Write the changes made before an error too
+            let result = subcommands::tui(&ctx, path.as_deref(), item, &mut profile).await;
+            profile.write()?;
+            result?;
         }
         SubCommands::Migrate {
             config: old_config_path,
@@ -426,7 +618,7 @@ async fn actual_main(mut cli_app: Ferium) -> Result<()> {
         SubCommands::Configure(args) => {
             subcommands::profile::configure(&mut config, args).await?;
         }
-        SubCommands::Create(args) => subcommands::profile::create(&mut config, args).await?,
+        SubCommands::Create(args) => subcommands::profile::create(&ctx, &mut config, args).await?,
         SubCommands::Switch(args) => {
             subcommands::profile::switch(&mut config, args)?;
         }
@@ -452,7 +644,9 @@ async fn actual_main(mut cli_app: Ferium) -> Result<()> {
 }
 
 /// Get the active profile with error handling
-fn get_active_profile(config: &mut Config) -> Result<(&mut ProfileItemConfig, ProfileSourceMut)> {
+fn get_active_profile(
+    config: &mut Config,
+) -> Result<(&mut ProfileItemConfig, ProfileSourceMut<'_>)> {
     let index = get_active_profile_index(config)?;
     let ProfileItem { profile, config } = &mut config.profiles[index];
 
@@ -471,14 +665,11 @@ fn get_active_profile(config: &mut Config) -> Result<(&mut ProfileItemConfig, Pr
     Ok((config, profile))
 }
 
-fn get_active_working_dir(config: &mut Config) -> Result<Option<PathBuf>> {
+/// Get the path to the active profile's file, or `None` if it is embedded
+fn get_active_profile_path(config: &mut Config) -> Result<Option<PathBuf>> {
     let index = get_active_profile_index(config)?;
     match &config.profiles[index].profile {
-        ProfileSource::Path(path) => Ok(Some(
-            path.parent()
-                .context("Internal error: Path should have a parent but it does not")?
-                .to_path_buf(),
-        )),
+        ProfileSource::Path(path) => Ok(Some(path.clone())),
         ProfileSource::Embedded(_) => Ok(None),
     }
 }
@@ -540,6 +731,36 @@ fn try_iter_profiles<'a>(
     })
 }
 
+/// Get the source to add for a file found by `scan` on Modrinth and `CurseForge`,
+/// preferring `platform` if it is on both
+///
+/// The source is pinned to the version of the file if `pin` is set.
+fn scanned_source(
+    platform: cli::Platform,
+    pin: bool,
+    modrinth: Option<(String, String)>,
+    curseforge: Option<(i32, i32)>,
+) -> Option<SourceId> {
+    let modrinth = modrinth.map(|(project, version)| {
+        if pin {
+            SourceId::PinnedModrinth(project, version)
+        } else {
+            SourceId::Modrinth(project)
+        }
+    });
+    let curseforge = curseforge.map(|(project, file)| {
+        if pin {
+            SourceId::PinnedCurseforge(project, file)
+        } else {
+            SourceId::Curseforge(project)
+        }
+    });
+    match platform {
+        cli::Platform::Modrinth => modrinth.or(curseforge),
+        cli::Platform::Curseforge => curseforge.or(modrinth),
+    }
+}
+
 /// Check if `profile` is empty, and if so return an error
 fn check_empty_profile(profile: &Profile) -> Result<()> {
     ensure!(
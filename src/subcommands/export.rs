@@ -0,0 +1,468 @@
+use super::upgrade::{resolve, with_item_mirrors, Locking, Resolution, Update};
+use crate::{cli::ExportFormat, download::download, status, warn, Context, TICK};
+use anyhow::{bail, Context as _, Error, Result};
+use colored::Colorize as _;
+use ferinth::structures::version::Hash as MRHash;
+use libium::{
+    config::{
+        modpack::{
+            curseforge::{self, Manifest, ManifestType, ModpackModLoader},
+            modrinth::{self, DependencyID, Game, Metadata},
+            write_zip, ZipEntry,
+        },
+        options::{Options, OptionsOverrides},
+        structs::{Filters, ModLoader, Profile, ProfileItemConfig, Side, SourceId, Version},
+    },
+    get_tmp_dir,
+    iter_ext::IterExt as _,
+    loader,
+    upgrade::{calculate_sha1, calculate_sha512, DownloadData, DownloadSource, Hash},
+};
+use reqwest::Url;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::{self, File},
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+use tokio::task::JoinSet;
+
+/// The hosts Modrinth accepts downloads from in the modpacks it hosts
+const MRPACK_HOSTS: [&str; 4] = [
+    "cdn.modrinth.com",
+    "github.com",
+    "raw.githubusercontent.com",
+    "gitlab.com",
+];
+
+/// Export the active profile as a modpack
+#[derive(clap::Args, Clone, Debug)]
+pub struct Args {
+    /// The format of the modpack
+    #[clap(long, short, value_enum, default_value_t)]
+    pub format: ExportFormat,
+    /// The path to write the modpack to.
+    /// Defaults to the profile's name in the current directory.
+    #[clap(value_hint(clap::ValueHint::FilePath))]
+    pub path: Option<PathBuf>,
+    /// The version of the modpack
+    #[clap(long, default_value = "1.0.0")]
+    pub pack_version: String,
+    /// The version of the mod loader the modpack is played with.
+    /// Defaults to the version pinned by the profile's `loader-version`,
+    /// or the latest stable version for the profile's game version meeting it.
+    #[clap(long)]
+    pub loader_version: Option<String>,
+    /// The author of the modpack, only recorded in Curseforge modpacks
+    #[clap(long)]
+    pub author: Option<String>,
+}
+
+/// What a modpack is and what it is played with
+struct PackInfo {
+    name: String,
+    version: String,
+    author: String,
+    game_version: String,
+    /// The mod loader and its version
+    loader: Option<(ModLoader, String)>,
+}
+
+/// Export the profile as a modpack
+///
+/// The profile is resolved using its lockfile, like an upgrade would,
+/// but neither the minecraft directory nor the lockfile are changed.
+pub async fn export(
+    ctx: &Context,
+    // The path to the profile file or `None` if it is embedded
+    profile_path: Option<&Path>,
+    profile_item: &ProfileItemConfig,
+    profile: &Profile,
+    Args {
+        format,
+        path,
+        pack_version,
+        loader_version,
+        author,
+    }: Args,
+) -> Result<()> {
+    status!(ctx, "{}", "Exporting Profile".bold());
+
+    let mut locking = Locking::read(profile_path, Update::Locked)?;
+    // Files for either side are included, the modpack records which side they are for
+    let profile = &with_item_mirrors(profile, profile_item);
+    let mut resolution = resolve(
+        ctx,
+        profile_path,
+        profile,
+        Side::Both,
+        Filters::empty(),
+        &mut locking,
+    )
+    .await?;
+
+    // CurseForge modpacks can still list files that can't be downloaded by third parties
+    let mut denied = vec![];
+    if format == ExportFormat::Curseforge {
+        resolution
+            .errors
+            .retain(|error| match &error.distribution_denied {
+                Some(id) => {
+                    denied.push((error.name.clone(), id.clone()));
+                    false
+                }
+                None => true,
+            });
+    }
+    if !resolution.errors.is_empty() {
+        bail!("\nCould not get the latest compatible version of some sources, the modpack would be incomplete")
+    }
+
+    let (game_version, loader) = pack_target(&profile.filters)?;
+    let loader_version =
+        loader_version.or_else(|| profile.loader_version.as_ref().and_then(Version::exact));
+    let loader = match (loader, loader_version) {
+        (Some(loader), Some(version)) => Some((loader, version)),
+        (Some(loader), None) => {
+            let version = loader::latest_version(
+                &ctx.loader_servers,
+                loader,
+                &game_version,
+                profile.loader_version.as_ref(),
+            )
+            .await
+            .with_context(|| {
+                format!("Could not find a {loader} version, provide one using `--loader-version`")
+            })?;
+            Some((loader, version))
+        }
+        (None, _) => None,
+    };
+
+    let pack = PackInfo {
+        name: profile_item.name.clone(),
+        version: pack_version,
+        author: author.unwrap_or_default(),
+        game_version,
+        loader,
+    };
+    let output = path.unwrap_or_else(|| {
+        PathBuf::from(format!(
+            "{}.{}",
+            pack.name,
+            match format {
+                ExportFormat::Mrpack => "mrpack",
+                ExportFormat::Curseforge => "zip",
+            }
+        ))
+    });
+
+    match format {
+        ExportFormat::Mrpack => {
+            write_mrpack(ctx, &output, pack, resolution, &profile.mirrors).await?;
+        }
+        ExportFormat::Curseforge => {
+            write_curseforge(ctx, &output, pack, resolution, denied, &profile.mirrors).await?;
+        }
+    }
+
+    status!(ctx, "\n{} Exported to {}", TICK.clone(), output.display());
+    Ok(())
+}
+
+/// Get the game version and mod loader the profile's `filters` target
+fn pack_target(filters: &Filters) -> Result<(String, Option<ModLoader>)> {
+    let Some(game_version) = filters.versions.iter().flatten().find_map(Version::exact) else {
+        bail!("Only profiles that require a single game version, e.g. `=1.21.1`, can be exported")
+    };
+
+    let loader = match filters.mod_loaders.as_deref() {
+        Some([loader, rest @ ..]) => {
+            if !rest.is_empty() {
+                warn!("modpacks only have one mod loader, {loader} will be used");
+            }
+            Some(*loader)
+        }
+        _ => None,
+    };
+
+    Ok((game_version, loader))
+}
+
+/// Split the resolved `files` into the ones downloaded from URLs and the ones packed into the overrides
+fn split_files(files: Vec<DownloadData>) -> (Vec<(Vec<Url>, DownloadData)>, Vec<DownloadData>) {
+    let mut downloads = vec![];
+    let mut overrides = vec![];
+    for file in files {
+        match &file.src {
+            DownloadSource::Urls(urls) => downloads.push((urls.clone(), file)),
+            DownloadSource::Path(_) | DownloadSource::Contents(_) => overrides.push(file),
+        }
+    }
+    (downloads, overrides)
+}
+
+/// Write the modpack zip to `output` with its `index` file,
+/// and the `overrides` and `options` packed into the `overrides` folder
+fn write_pack(
+    output: &Path,
+    index_name: &str,
+    index: &str,
+    overrides: &[DownloadData],
+    options: OptionsOverrides,
+) -> Result<()> {
+    let mut options_txt = vec![];
+    if !options.is_empty() {
+        let mut opts = Options::default();
+        opts.apply(options, |err| warn!("apply: {err}"));
+        opts.write(&mut options_txt, |err| warn!("write: {err}"))?;
+    }
+
+    let override_paths = overrides
+        .iter()
+        .map(|file| Path::new("overrides").join(&file.output))
+        .collect::<Vec<_>>();
+    let options_path = Path::new("overrides").join("options.txt");
+
+    let mut entries = vec![(Path::new(index_name), ZipEntry::Contents(index.as_bytes()))];
+    for (path, file) in override_paths.iter().zip(overrides) {
+        let entry = match &file.src {
+            DownloadSource::Path(from) => ZipEntry::Path(from),
+            DownloadSource::Contents(contents) => ZipEntry::Contents(contents.as_bytes()),
+            DownloadSource::Urls(_) => continue,
+        };
+        entries.push((path, entry));
+    }
+    if !options_txt.is_empty() {
+        entries.push((&options_path, ZipEntry::Contents(&options_txt)));
+    }
+
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    write_zip(BufWriter::new(File::create(output)?), entries)?;
+    Ok(())
+}
+
+async fn write_mrpack(
+    ctx: &Context,
+    output: &Path,
+    pack: PackInfo,
+    resolution: Resolution,
+    mirrors: &BTreeMap<String, String>,
+) -> Result<()> {
+    let mut dependencies = HashMap::from([(DependencyID::Minecraft, pack.game_version)]);
+    if let Some((loader, version)) = pack.loader {
+        let id = match loader {
+            ModLoader::Fabric => DependencyID::FabricLoader,
+            ModLoader::Quilt => DependencyID::QuiltLoader,
+            ModLoader::Forge => DependencyID::Forge,
+            ModLoader::NeoForge => DependencyID::Neoforge,
+        };
+        dependencies.insert(id, version);
+    }
+
+    let (downloads, overrides) = split_files(resolution.files);
+
+    let unhosted = downloads
+        .iter()
+        .filter(|(urls, _)| {
+            !urls.iter().any(|url| {
+                url.host_str()
+                    .is_some_and(|host| MRPACK_HOSTS.contains(&host))
+            })
+        })
+        .map(|(_, file)| file.filename())
+        .collect::<Vec<_>>();
+    if !unhosted.is_empty() {
+        warn!(
+            "Modrinth won't host this modpack since these files aren't downloaded from {}: {}",
+            MRPACK_HOSTS.iter().display(", "),
+            unhosted.iter().display(", ")
+        );
+    }
+
+    let metadata = Metadata {
+        format_version: 1,
+        game: Game::Minecraft,
+        version_id: pack.version,
+        name: pack.name,
+        summary: None,
+        files: mrpack_files(ctx, downloads, mirrors).await?,
+        dependencies,
+    };
+
+    write_pack(
+        output,
+        "modrinth.index.json",
+        &serde_json::to_string_pretty(&metadata)?,
+        &overrides,
+        resolution.options,
+    )
+}
+
+/// Get the modpack entries of the `downloads`
+///
+/// Modpacks need both the SHA-1 and SHA-512 hashes of a file,
+/// files that weren't resolved from Modrinth are downloaded to calculate them, using the `mirrors`.
+async fn mrpack_files(
+    ctx: &Context,
+    downloads: Vec<(Vec<Url>, DownloadData)>,
+    mirrors: &BTreeMap<String, String>,
+) -> Result<Vec<modrinth::ModpackFile>> {
+    let mut tasks = JoinSet::new();
+    let client = reqwest::Client::new();
+    // Every export downloads to its own directory, which is removed even if a download fails
+    let tmp_dir = tempfile::Builder::new()
+        .prefix("export")
+        .tempdir_in(get_tmp_dir()?)?;
+
+    for (urls, mut file) in downloads {
+        let client = client.clone();
+        let ctx = ctx.clone();
+        let tmp_dir = tmp_dir.path().to_owned();
+        file.use_mirrors(mirrors);
+        tasks.spawn(async move {
+            let path = file.output.clone();
+            let env = modrinth::ModpackFileEnvironment::of(file.side);
+
+            let (hashes, file_size) = if let Some(Hash::Modrinth(hash)) = &file.hash {
+                (hash.clone(), file.length)
+            } else {
+                let _permits = ctx.acquire_permits(file.host()).await?;
+                let downloaded = tmp_dir.join(&file.output);
+                file.download(client, &tmp_dir, &ctx.download, |_| {})
+                    .await?;
+
+                let hashes = MRHash {
+                    sha512: calculate_sha512(&downloaded)?,
+                    sha1: calculate_sha1(&downloaded)?,
+                    others: HashMap::new(),
+                };
+                let file_size = fs::metadata(&downloaded)?.len();
+                fs::remove_file(downloaded)?;
+                (hashes, file_size)
+            };
+
+            Ok::<_, Error>(modrinth::ModpackFile {
+                path,
+                hashes,
+                env,
+                downloads: urls,
+                file_size: usize::try_from(file_size)?,
+            })
+        });
+    }
+
+    let mut files = tasks
+        .join_all()
+        .await
+        .into_iter()
+        .collect::<Result<Vec<_>>>()?;
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(files)
+}
+
+/// Write a `CurseForge` modpack
+///
+/// Files that aren't from `CurseForge` are downloaded and packed into the overrides.
+/// The `denied` files are listed, since the `CurseForge` app can still download them.
+async fn write_curseforge(
+    ctx: &Context,
+    output: &Path,
+    pack: PackInfo,
+    resolution: Resolution,
+    denied: Vec<(String, SourceId)>,
+    mirrors: &BTreeMap<String, String>,
+) -> Result<()> {
+    let (downloads, mut overrides) = split_files(resolution.files);
+
+    let mut files = vec![];
+    let mut elsewhere = vec![];
+    for (_, file) in downloads {
+        match file.id {
+            Some(SourceId::PinnedCurseforge(project_id, file_id)) => {
+                files.push(curseforge::ModpackFile {
+                    project_id,
+                    file_id,
+                    required: true,
+                });
+            }
+            _ => elsewhere.push(file),
+        }
+    }
+
+    if !denied.is_empty() {
+        warn!(
+            "the developers of {} have denied third party downloads, launchers other than the CurseForge app may ask players to download them manually",
+            denied.iter().map(|(name, _)| name).display(", ")
+        );
+        for (_, id) in denied {
+            if let SourceId::PinnedCurseforge(project_id, file_id) = id {
+                files.push(curseforge::ModpackFile {
+                    project_id,
+                    file_id,
+                    required: true,
+                });
+            }
+        }
+    }
+    files.sort_by_key(|file| (file.project_id, file.file_id));
+
+    // Every export downloads to its own directory, which is removed once it is dropped
+    let tmp_dir = tempfile::Builder::new()
+        .prefix("export")
+        .tempdir_in(get_tmp_dir()?)?;
+    if !elsewhere.is_empty() {
+        warn!(
+            "{} aren't on CurseForge, so they will be packed into the overrides. Make sure their licenses allow redistributing them",
+            elsewhere.iter().map(DownloadData::filename).display(", ")
+        );
+        let mut to_download = elsewhere.clone();
+        for file in &mut to_download {
+            file.use_mirrors(mirrors);
+        }
+        download(ctx, tmp_dir.path().to_owned(), to_download).await?;
+        overrides.extend(elsewhere.into_iter().map(|file| DownloadData {
+            src: DownloadSource::Path(tmp_dir.path().join(&file.output)),
+            ..file
+        }));
+    }
+
+    let manifest = Manifest {
+        minecraft: curseforge::Minecraft {
+            version: pack.game_version,
+            mod_loaders: pack
+                .loader
+                .map(|(loader, version)| ModpackModLoader {
+                    id: format!(
+                        "{}-{version}",
+                        match loader {
+                            ModLoader::Fabric => "fabric",
+                            ModLoader::Quilt => "quilt",
+                            ModLoader::Forge => "forge",
+                            ModLoader::NeoForge => "neoforge",
+                        }
+                    ),
+                    primary: true,
+                })
+                .into_iter()
+                .collect(),
+        },
+        manifest_type: ManifestType::MinecraftModpack,
+        manifest_version: 1,
+        name: pack.name,
+        version: pack.version,
+        author: pack.author,
+        files,
+        overrides: "overrides".to_owned(),
+    };
+
+    write_pack(
+        output,
+        "manifest.json",
+        &serde_json::to_string_pretty(&manifest)?,
+        &overrides,
+        resolution.options,
+    )
+}
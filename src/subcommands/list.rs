@@ -1,14 +1,26 @@
-use crate::TICK;
+use super::upgrade::{resolve, with_item_mirrors, Locking, SourceError, Update};
+use crate::{Context, CROSS, TICK};
 use anyhow::Result;
 use colored::Colorize as _;
 use ferinth::structures::{project::Project, user::TeamMember};
 use furse::structures::mod_structs::Mod;
 use libium::{
-    config::structs::{Profile, SourceId, SourceKindWithModpack},
+    config::structs::{
+        Filters, Host, Profile, ProfileItemConfig, Source, SourceId, SourceKind,
+        SourceKindWithModpack,
+    },
     iter_ext::IterExt as _,
+    rate_limit,
+    upgrade::resolve::{Node, ProjectKey},
     CURSEFORGE_API, GITHUB_API, MODRINTH_API,
 };
 use octocrab::models::{repos::Release, Repository};
+use serde::Serialize;
+use serde_json::json;
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
 use tokio::task::JoinSet;
 
 enum Metadata {
@@ -24,10 +36,51 @@ impl Metadata {
             Metadata::GH(p, _) => &p.name,
         }
     }
+
+    fn to_json(&self) -> serde_json::Value {
+        match self {
+            Metadata::CF(project) => json!({
+                "platform": "curseforge",
+                "project": project,
+            }),
+            Metadata::MD(project, team_members) => json!({
+                "platform": "modrinth",
+                "project": project,
+                "team-members": team_members,
+            }),
+            Metadata::GH(repository, releases) => json!({
+                "platform": "github",
+                "repository": repository,
+                "releases": releases,
+            }),
+        }
+    }
 }
 
-pub async fn verbose(profile: &mut Profile, markdown: bool) -> Result<()> {
-    if !markdown {
+/// Get the sources of the profile as JSON
+pub fn json(item: &ProfileItemConfig, profile: &Profile) -> serde_json::Value {
+    let mut sources = Vec::new();
+    for kind in SourceKind::ARRAY {
+        let mut map = profile.map(*kind).iter().collect_vec();
+        map.sort_unstable_by_key(|(name, _)| name.to_lowercase());
+        for (name, source) in map {
+            sources.push(json!({
+                "kind": kind,
+                "name": name,
+                "source": source,
+            }));
+        }
+    }
+
+    json!({
+        "name": item.name,
+        "filters": profile.filters,
+        "sources": sources,
+    })
+}
+
+pub async fn verbose(ctx: &Context, profile: &mut Profile, markdown: bool) -> Result<()> {
+    if !markdown || ctx.json {
         eprint!("Querying metadata... ");
     }
 
@@ -42,8 +95,11 @@ pub async fn verbose(profile: &mut Profile, markdown: bool) -> Result<()> {
                 let repo = GITHUB_API.repos(owner, repo);
                 tasks.spawn(async move {
                     Ok::<_, anyhow::Error>((
-                        repo.get().await?,
-                        repo.releases().list().send().await?,
+                        rate_limit::send(Host::Github, || repo.get()).await?,
+                        rate_limit::send(Host::Github, || async {
+                            repo.releases().list().send().await
+                        })
+                        .await?,
                     ))
                 });
             }
@@ -54,22 +110,26 @@ pub async fn verbose(profile: &mut Profile, markdown: bool) -> Result<()> {
     let mr_projects = if mr_ids.is_empty() {
         vec![]
     } else {
-        MODRINTH_API
-            .get_multiple_projects(&mr_ids.iter().map(AsRef::as_ref).collect_vec())
-            .await?
+        let mr_ids = mr_ids.iter().map(AsRef::as_ref).collect_vec();
+        rate_limit::send(Host::Modrinth, || {
+            MODRINTH_API.get_multiple_projects(&mr_ids)
+        })
+        .await?
     };
     let mr_teams_members = if mr_projects.is_empty() {
         vec![]
     } else {
-        MODRINTH_API
-            .list_multiple_teams_members(&mr_projects.iter().map(|p| p.team.as_ref()).collect_vec())
-            .await?
+        let teams = mr_projects.iter().map(|p| p.team.as_ref()).collect_vec();
+        rate_limit::send(Host::Modrinth, || {
+            MODRINTH_API.list_multiple_teams_members(&teams)
+        })
+        .await?
     };
 
     let cf_projects = if cf_ids.is_empty() {
         vec![]
     } else {
-        CURSEFORGE_API.get_mods(cf_ids).await?
+        rate_limit::send(Host::Curseforge, || CURSEFORGE_API.get_mods(cf_ids.clone())).await?
     };
 
     let mut metadata = Vec::new();
@@ -85,8 +145,12 @@ pub async fn verbose(profile: &mut Profile, markdown: bool) -> Result<()> {
     }
     metadata.sort_unstable_by_key(|e| e.name().to_lowercase());
 
+    if ctx.json {
+        eprintln!("{}", *TICK);
+        return ctx.output(&metadata.iter().map(Metadata::to_json).collect_vec());
+    }
     if !markdown {
-        println!("{}", &*TICK);
+        println!("{}", *TICK);
     }
 
     for project in &metadata {
@@ -156,14 +220,12 @@ pub fn curseforge(project: &Mod) {
             .iter()
             .map(|author| &author.name)
             .display(", ")
-            .to_string()
             .cyan(),
         project
             .categories
             .iter()
             .map(|category| &category.name)
             .display(", ")
-            .to_string()
             .magenta(),
     );
 }
@@ -209,14 +271,8 @@ pub fn modrinth(project: &Project, team_members: &[TeamMember]) {
             .iter()
             .map(|member| &member.user.username)
             .display(", ")
-            .to_string()
             .cyan(),
-        project
-            .categories
-            .iter()
-            .display(", ")
-            .to_string()
-            .magenta(),
+        project.categories.iter().display(", ").magenta(),
         {
             if project.license.name.is_empty() {
                 "Custom"
@@ -251,7 +307,7 @@ pub fn github(repo: &Repository, releases: &[Release]) {
   Authors:      {}
   Topics:       {}
   License:      {}",
-        &repo.name.bold(),
+        repo.name.bold(),
         repo.description
             .as_ref()
             .map_or(String::new(), |description| {
@@ -269,11 +325,9 @@ pub fn github(repo: &Repository, releases: &[Release]) {
         "Yes".green(),
         downloads.to_string().yellow(),
         repo.owner.as_ref().unwrap().login.cyan(),
-        repo.topics.as_ref().map_or("".into(), |topics| topics
-            .iter()
-            .display(", ")
-            .to_string()
-            .magenta()),
+        repo.topics
+            .as_ref()
+            .map_or("".into(), |topics| topics.iter().display(", ").magenta()),
         repo.license
             .as_ref()
             .map_or("None".into(), |license| format!(
@@ -378,3 +432,398 @@ pub fn github_md(repo: &Repository) {
         )),
     );
 }
+
+/// The mods of a profile resolved like upgrading it would, along with their dependencies
+struct ResolvedMods {
+    /// The resolved files, with every project appearing once
+    nodes: Vec<Node>,
+    /// The titles of the resolved files by the name of the source they were resolved from
+    titles: HashMap<String, String>,
+    /// The sources that couldn't be resolved
+    errors: Vec<SourceError>,
+}
+
+impl ResolvedMods {
+    /// The names of the mods of the profile and its imports, sorted by name,
+    /// with their resolved file or why resolving it failed
+    fn roots(&self) -> Vec<(&str, Result<&Node, &str>)> {
+        let mut roots = self
+            .nodes
+            .iter()
+            .filter(|node| {
+                node.required_by.is_none()
+                    && node
+                        .file
+                        .output
+                        .starts_with(SourceKind::Mods.directory(None))
+            })
+            .map(|node| (node.name.as_str(), Ok(node)))
+            .chain(
+                self.errors
+                    .iter()
+                    .filter(|error| error.kind == SourceKind::Mods && error.dependency.is_none())
+                    .map(|error| (error.name.as_str(), Err(error.error.as_str()))),
+            )
+            .collect_vec();
+        roots.sort_unstable_by_key(|(name, _)| name.to_lowercase());
+        roots
+    }
+
+    /// The resolved file of the dependency `dep`
+    fn get(&self, dep: &SourceId) -> Option<&Node> {
+        let project = ProjectKey::of(dep)?;
+        self.nodes
+            .iter()
+            .find(|node| node.file.id.as_ref().and_then(ProjectKey::of).as_ref() == Some(&project))
+    }
+
+    /// Why resolving the dependency `dep` failed
+    fn error(&self, dep: &SourceId) -> Option<&str> {
+        let project = ProjectKey::of(dep);
+        self.errors
+            .iter()
+            .find(|error| {
+                error.dependency.as_ref().is_some_and(|id| {
+                    id == dep || (project.is_some() && ProjectKey::of(id) == project)
+                })
+            })
+            .map(|error| error.error.as_str())
+    }
+
+    /// The title of the file of `node`
+    fn title(&self, node: &Node) -> String {
+        self.titles
+            .get(&node.name)
+            .cloned()
+            .unwrap_or_else(|| node.file.filename())
+    }
+}
+
+/// Resolve the profile like upgrading it would, using its lockfile without updating it
+async fn resolve_mods(
+    ctx: &Context,
+    profile_path: Option<&Path>,
+    profile_item: &ProfileItemConfig,
+    profile: &Profile,
+) -> Result<ResolvedMods> {
+    let mut locking = Locking::read(profile_path, Update::Locked)?;
+    let profile = &with_item_mirrors(profile, profile_item);
+    let resolution = resolve(
+        ctx,
+        profile_path,
+        profile,
+        profile_item.side.unwrap_or(profile.side),
+        Filters::empty(),
+        &mut locking,
+    )
+    .await?;
+
+    Ok(ResolvedMods {
+        nodes: resolution.nodes,
+        titles: locking
+            .resolved
+            .mods
+            .into_iter()
+            .map(|(name, locked)| (name, locked.title))
+            .collect(),
+        errors: resolution.errors,
+    })
+}
+
+/// A source in the dependency tree and the dependencies it pulls in
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct TreeNode {
+    /// The name of the profile's source, or the title of a dependency's file
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    filename: Option<String>,
+    /// The reason resolving the source failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    /// The name of the profile's source for a dependency that is also declared explicitly
+    #[serde(skip_serializing_if = "Option::is_none")]
+    declared_as: Option<String>,
+    /// Whether this is an optional dependency
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    optional: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    dependencies: Vec<TreeNode>,
+}
+
+/// Resolve the mods of the profile and its imports along with their dependencies,
+/// and print which mod pulled in each dependency
+pub async fn tree(
+    ctx: &Context,
+    profile_path: Option<&Path>,
+    profile_item: &ProfileItemConfig,
+    profile: &Profile,
+) -> Result<()> {
+    let resolved = resolve_mods(ctx, profile_path, profile_item, profile).await?;
+
+    let nodes = resolved
+        .roots()
+        .into_iter()
+        .map(|(name, node)| match node {
+            Ok(node) => tree_node(&resolved, node, name.to_owned(), &mut vec![]),
+            Err(err) => TreeNode {
+                name: name.to_owned(),
+                filename: None,
+                error: Some(err.to_owned()),
+                declared_as: None,
+                optional: false,
+                dependencies: vec![],
+            },
+        })
+        .collect_vec();
+
+    if ctx.json {
+        ctx.output(&nodes)?;
+    } else {
+        for node in &nodes {
+            print_tree_node(node, "", None);
+        }
+    }
+
+    Ok(())
+}
+
+/// Build the tree of `node`, where `path` contains the projects of the files above it
+///
+/// Optional dependencies are only pulled in for the profile's own sources, so only theirs are shown.
+fn tree_node(
+    resolved: &ResolvedMods,
+    node: &Node,
+    name: String,
+    path: &mut Vec<ProjectKey>,
+) -> TreeNode {
+    let project = node.file.id.as_ref().and_then(ProjectKey::of);
+    let mut children = vec![];
+    if project
+        .as_ref()
+        .map_or(true, |project| !path.contains(project))
+    {
+        path.extend(project.clone());
+        let optional_dependencies = if node.required_by.is_none() {
+            node.file.optional_dependencies.as_slice()
+        } else {
+            &[]
+        };
+        for (dep, optional) in node
+            .file
+            .dependencies
+            .iter()
+            .map(|dep| (dep, false))
+            .chain(optional_dependencies.iter().map(|dep| (dep, true)))
+        {
+            let mut child = match resolved.get(dep) {
+                // Explicitly declared mods are already shown at the top level
+                Some(dep_node) if dep_node.required_by.is_none() => TreeNode {
+                    name: dep_node.name.clone(),
+                    filename: Some(dep_node.file.filename()),
+                    error: None,
+                    declared_as: Some(dep_node.name.clone()),
+                    optional: false,
+                    dependencies: vec![],
+                },
+                Some(dep_node) => tree_node(resolved, dep_node, resolved.title(dep_node), path),
+                None => match resolved.error(dep) {
+                    Some(err) => TreeNode {
+                        name: dep.to_string(),
+                        filename: None,
+                        error: Some(err.to_owned()),
+                        declared_as: None,
+                        optional: false,
+                        dependencies: vec![],
+                    },
+                    // The dependency is excluded, or it is optional and not pulled in
+                    None => continue,
+                },
+            };
+            child.optional = optional;
+            children.push(child);
+        }
+        if project.is_some() {
+            path.pop();
+        }
+    }
+
+    TreeNode {
+        name,
+        filename: Some(node.file.filename()),
+        error: None,
+        declared_as: None,
+        optional: false,
+        dependencies: children,
+    }
+}
+
+/// Print `node` and its dependencies, `last` is `None` for the profile's sources
+fn print_tree_node(node: &TreeNode, prefix: &str, last: Option<bool>) {
+    let (branch, child_prefix) = match last {
+        None => ("", String::new()),
+        Some(true) => ("└── ", format!("{prefix}    ")),
+        Some(false) => ("├── ", format!("{prefix}│   ")),
+    };
+    let name = if last.is_none() {
+        node.name.bold()
+    } else {
+        node.name.normal()
+    };
+    let optional = if node.optional {
+        format!(" {}", "(optional)".cyan())
+    } else {
+        String::new()
+    };
+
+    match (&node.error, &node.declared_as) {
+        (Some(err), _) => println!(
+            "{prefix}{branch}{}{optional}",
+            format!("{CROSS} {name}  {err}").red()
+        ),
+        (None, Some(_)) => println!(
+            "{prefix}{branch}{name}{optional}  {}",
+            "(also declared explicitly)".yellow()
+        ),
+        (None, None) => println!(
+            "{prefix}{branch}{name}{optional}  {}",
+            node.filename.as_deref().unwrap_or_default().dimmed()
+        ),
+    }
+
+    for (i, dep) in node.dependencies.iter().enumerate() {
+        print_tree_node(dep, &child_prefix, Some(i + 1 == node.dependencies.len()));
+    }
+}
+
+/// An optional dependency of the profile's mods
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct OptionalDependency {
+    /// The identifier to add the dependency with
+    id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+    /// The reason the dependency is not available, e.g it isn't compatible with the profile
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    /// The names of the mods it is an optional dependency of
+    optional_for: Vec<String>,
+    /// Whether the dependency is already downloaded, e.g. because optional dependencies are enabled for one of the mods
+    enabled: bool,
+}
+
+/// List the optional dependencies of the mods of the profile and its imports that aren't in the profile
+pub async fn optional(
+    ctx: &Context,
+    profile_path: Option<&Path>,
+    profile_item: &ProfileItemConfig,
+    profile: &Profile,
+) -> Result<()> {
+    let resolved = resolve_mods(ctx, profile_path, profile_item, profile).await?;
+    let roots = resolved.roots();
+    let declared = roots
+        .iter()
+        .filter_map(|(_, node)| node.ok()?.file.id.as_ref().and_then(ProjectKey::of))
+        .collect::<HashSet<_>>();
+
+    let mut available: Vec<OptionalDependency> = vec![];
+    let mut unresolved = vec![];
+    for (name, node) in roots {
+        let Ok(node) = node else {
+            continue;
+        };
+        for dep in &node.file.optional_dependencies {
+            if ProjectKey::of(dep).is_some_and(|project| declared.contains(&project)) {
+                continue;
+            }
+
+            let id = match dep {
+                SourceId::PinnedModrinth(id, _) => SourceId::Modrinth(id.clone()),
+                dep => dep.clone(),
+            }
+            .to_string();
+            if let Some(existing) = available.iter_mut().find(|existing| existing.id == id) {
+                existing.optional_for.push(name.to_owned());
+                continue;
+            }
+            let dep_node = resolved.get(dep);
+            if dep_node.is_none() {
+                unresolved.push((available.len(), dep.clone()));
+            }
+            available.push(OptionalDependency {
+                id,
+                title: dep_node.map(|dep_node| resolved.title(dep_node)),
+                error: None,
+                optional_for: vec![name.to_owned()],
+                enabled: dep_node.is_some(),
+            });
+        }
+    }
+
+    // Optional dependencies that aren't pulled in are resolved to check that they're available
+    let src_path = profile_path.and_then(Path::parent).map(Path::to_owned);
+    let mut tasks = JoinSet::new();
+    for (index, dep) in unresolved {
+        let ctx = ctx.clone();
+        let filters = profile.filters.clone();
+        let src_path = src_path.clone();
+        tasks.spawn(async move {
+            let source = Source::from_id(dep, Filters::empty());
+            let _permits = ctx.acquire_permits(Host::of_source(&source)).await?;
+            let result = source
+                .fetch_download_file(
+                    &ctx.download.cache,
+                    src_path.as_deref(),
+                    SourceKind::Mods,
+                    vec![&filters],
+                )
+                .await;
+            Ok::<_, anyhow::Error>((index, result))
+        });
+    }
+    while let Some(task) = tasks.join_next().await {
+        let (index, result) = task??;
+        match result {
+            Ok((metadata, _)) => available[index].title = Some(metadata.title),
+            Err(err) => available[index].error = Some(err.to_string()),
+        }
+    }
+
+    if ctx.json {
+        return ctx.output(&available);
+    }
+
+    if available.is_empty() {
+        println!("{}", "No optional dependencies are available".bold());
+        return Ok(());
+    }
+
+    println!("{}\n", "Optional Dependencies".bold());
+    for dep in available {
+        let for_mods = format!("for {}", dep.optional_for.iter().display(", ")).dimmed();
+        let enabled = if dep.enabled {
+            format!("  {}", "(enabled)".green())
+        } else {
+            String::new()
+        };
+        match (&dep.title, &dep.error) {
+            (Some(title), _) => println!(
+                "• {} {}  {for_mods}{enabled}",
+                title.bold(),
+                format!("({})", dep.id).dimmed()
+            ),
+            (None, error) => println!(
+                "{}  {for_mods}",
+                format!(
+                    "{CROSS} {}  {}",
+                    dep.id,
+                    error.as_deref().unwrap_or_default()
+                )
+                .red()
+            ),
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,218 @@
+use super::upgrade::with_item_mirrors;
+use crate::{status, Context, TICK};
+use anyhow::{bail, Context as _, Result};
+use colored::Colorize as _;
+use libium::{
+    config::structs::{ModLoader, Profile, ProfileItemConfig, Side, Version},
+    get_tmp_dir, loader,
+    upgrade::{DownloadData, DownloadSource},
+};
+use reqwest::Url;
+use std::{
+    collections::BTreeMap,
+    env::var_os,
+    fs::{self, File},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// Install the profile's mod loader into the minecraft directory
+///
+/// The version the profile's `loader-version` pins is installed. Otherwise the latest stable version
+/// meeting it is installed and, unless `loader-version` is a range, pinned in the profile.
+/// If `update` is set, a pinned version is replaced with the latest stable version.
+/// Fabric and Quilt are installed by writing their version JSON to `.minecraft/versions`,
+/// Forge and `NeoForge` by running their installer.
+///
+/// If `dry_run` is set, only report the version that would be installed.
+pub async fn install_loader(
+    ctx: &Context,
+    profile_item: &ProfileItemConfig,
+    profile: &mut Profile,
+    update: bool,
+    dry_run: bool,
+) -> Result<()> {
+    let Some(game_version) = profile
+        .filters
+        .versions
+        .iter()
+        .flatten()
+        .find_map(Version::exact)
+    else {
+        bail!("Only profiles that require a single game version, e.g. `=1.21.1`, can have their mod loader installed")
+    };
+    let Some(&mod_loader) = profile
+        .filters
+        .mod_loaders
+        .as_ref()
+        .and_then(|loaders| loaders.first())
+    else {
+        bail!("The profile doesn't have a mod loader to install")
+    };
+    let side = profile_item.side.unwrap_or(profile.side);
+
+    let pinned = profile.loader_version.as_ref().and_then(Version::exact);
+    let loader_version = if let Some(version) = pinned.clone().filter(|_| !update) {
+        version
+    } else {
+        let range = profile.loader_version.as_ref().filter(|_| pinned.is_none());
+        loader::latest_version(&ctx.loader_servers, mod_loader, &game_version, range)
+            .await
+            .with_context(|| format!("Could not find a {mod_loader} version to install"))?
+    };
+    let id = loader::version_id(mod_loader, &game_version, &loader_version);
+    let minecraft_dir = &profile_item.minecraft_dir;
+
+    if dry_run {
+        status!(
+            ctx,
+            "{mod_loader} {loader_version} would be installed as {id}\n"
+        );
+        return Ok(());
+    }
+
+    let marker = installed_marker(mod_loader, &game_version, &loader_version, side);
+    if minecraft_dir.join(&marker).exists() {
+        status!(
+            ctx,
+            "{}\n",
+            format!("{mod_loader} {loader_version} is already installed").dimmed()
+        );
+    } else {
+        status!(
+            ctx,
+            "{}",
+            format!("Installing {mod_loader} {loader_version}").bold()
+        );
+        if let Some(url) = loader::installer_url(
+            &ctx.loader_servers,
+            mod_loader,
+            &game_version,
+            &loader_version,
+        ) {
+            let mirrors = with_item_mirrors(profile, profile_item).mirrors;
+            run_installer(ctx, url.parse()?, &mirrors, minecraft_dir, side).await?;
+        } else if side == Side::Server {
+            bail!("{mod_loader} can't be installed on a server yet, use its installer instead")
+        } else if let Some(json) = loader::version_json(
+            &ctx.loader_servers,
+            mod_loader,
+            &game_version,
+            &loader_version,
+        )
+        .await?
+        {
+            let version_dir = minecraft_dir.join("versions").join(&id);
+            fs::create_dir_all(&version_dir)?;
+            fs::write(version_dir.join(format!("{id}.json")), json)?;
+            // Some launchers expect every version to have a jar, even if it is empty
+            File::create(version_dir.join(format!("{id}.jar")))?;
+        }
+        status!(
+            ctx,
+            "{} Installed {mod_loader} {loader_version}\n",
+            TICK.clone()
+        );
+    }
+
+    if profile.loader_version.is_none() || pinned.is_some() {
+        profile.loader_version = Some(format!("={loader_version}").parse()?);
+    }
+    Ok(())
+}
+
+/// Get the path, relative to the minecraft directory, that exists once the loader is installed for `side`
+fn installed_marker(
+    mod_loader: ModLoader,
+    game_version: &str,
+    loader_version: &str,
+    side: Side,
+) -> PathBuf {
+    let id = loader::version_id(mod_loader, game_version, loader_version);
+    match (side, mod_loader) {
+        (Side::Server, ModLoader::Forge) => Path::new("libraries/net/minecraftforge/forge")
+            .join(format!("{game_version}-{loader_version}")),
+        (Side::Server, ModLoader::NeoForge) => {
+            Path::new("libraries/net/neoforged/neoforge").join(loader_version)
+        }
+        _ => Path::new("versions").join(&id).join(format!("{id}.json")),
+    }
+}
+
+/// Download the installer jar at `url`, using the `mirrors`,
+/// and run it headlessly to install the loader to `minecraft_dir`
+async fn run_installer(
+    ctx: &Context,
+    url: Url,
+    mirrors: &BTreeMap<String, String>,
+    minecraft_dir: &Path,
+    side: Side,
+) -> Result<()> {
+    // Every install downloads to its own directory, which is removed once it is dropped
+    let tmp_dir = tempfile::Builder::new()
+        .prefix("loader-installer")
+        .tempdir_in(get_tmp_dir()?)?;
+    let mut download = DownloadData {
+        id: None,
+        src: DownloadSource::Urls(vec![url]),
+        output: PathBuf::from("installer.jar"),
+        length: 0,
+        dependencies: vec![],
+        optional_dependencies: vec![],
+        conflicts: vec![],
+        kind: None,
+        side: Side::Both,
+        hash: None,
+        user_hash: vec![],
+    };
+    download.use_mirrors(mirrors);
+    let installer = tmp_dir.path().join(&download.output);
+    let permits = ctx.acquire_permits(download.host()).await?;
+    download
+        .download(
+            reqwest::Client::new(),
+            tmp_dir.path(),
+            &ctx.download,
+            |_| {},
+        )
+        .await?;
+    drop(permits);
+
+    let flag = if side == Side::Server {
+        "--installServer"
+    } else {
+        // The client installer refuses to run without a launcher profile
+        let launcher_profiles = minecraft_dir.join("launcher_profiles.json");
+        if !launcher_profiles.exists() {
+            fs::create_dir_all(minecraft_dir)?;
+            fs::write(launcher_profiles, r#"{"profiles":{}}"#)?;
+        }
+        "--installClient"
+    };
+    let java = var_os("JAVA_HOME").map_or_else(
+        || PathBuf::from("java"),
+        |home| PathBuf::from(home).join("bin").join("java"),
+    );
+
+    let mut command = Command::new(&java);
+    command
+        .arg("-jar")
+        .arg(&installer)
+        .arg(flag)
+        .arg(minecraft_dir)
+        // The installer writes its log to the current directory
+        .current_dir(tmp_dir.path());
+    let output = tokio::task::spawn_blocking(move || command.output())
+        .await?
+        .with_context(|| format!("Could not run the installer using {}", java.display()))?;
+
+    if !output.status.success() {
+        let log = String::from_utf8_lossy(&output.stdout);
+        let tail = log.lines().rev().take(10).collect::<Vec<_>>();
+        bail!(
+            "The installer failed:\n{}",
+            tail.into_iter().rev().collect::<Vec<_>>().join("\n")
+        )
+    }
+    Ok(())
+}
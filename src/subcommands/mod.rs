@@ -1,8 +1,18 @@
+pub mod export;
 pub mod list;
+mod loader;
 mod migrate;
 pub mod profile;
 mod remove;
+mod search;
+pub mod tui;
+mod update;
 mod upgrade;
+pub use export::export;
+pub use loader::install_loader;
 pub use migrate::migrate;
 pub use remove::remove;
+pub use search::search;
+pub use tui::tui;
+pub use update::update;
 pub use upgrade::upgrade;
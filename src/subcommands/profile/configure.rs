@@ -3,7 +3,7 @@ use crate::{file_picker::pick_folder, get_active_profile};
 use anyhow::Result;
 use inquire::{Select, Text};
 use libium::{
-    config::structs::{Config, ModLoader, Version},
+    config::structs::{Config, ModLoader, Side, Version},
     iter_ext::IterExt,
 };
 use std::path::PathBuf;
@@ -27,6 +27,9 @@ pub struct Args {
     #[clap(long, short)]
     #[clap(value_hint(clap::ValueHint::DirPath))]
     minecraft_dir: Option<PathBuf>,
+    /// The side to install files for, files only for the other side are skipped
+    #[clap(long, value_enum)]
+    side: Option<Side>,
 }
 
 pub async fn configure(
@@ -36,6 +39,7 @@ pub async fn configure(
         mod_loaders,
         name,
         minecraft_dir,
+        side,
     }: Args,
 ) -> Result<()> {
     let (profile_item, mut profile) = get_active_profile(config)?;
@@ -58,6 +62,10 @@ pub async fn configure(
         profile_item.minecraft_dir = minecraft_dir;
         interactive = false;
     }
+    if let Some(side) = side {
+        profile_item.side = Some(side);
+        interactive = false;
+    }
 
     if interactive {
         let items = vec![
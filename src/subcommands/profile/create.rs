@@ -1,7 +1,12 @@
-use super::{check_output_directory, pick_minecraft_version, pick_mod_loader};
-use crate::{file_picker::pick_folder, try_iter_profiles};
+use super::{
+    check_output_directory,
+    modpack::{read_modpack, ModpackProfile},
+    pick_minecraft_version, pick_mod_loader,
+};
+use crate::{file_picker::pick_folder, try_iter_profiles, Context};
 use anyhow::{bail, ensure, Context as _, Result};
 use colored::Colorize as _;
+use fs_extra::dir::{copy, CopyOptions};
 use inquire::{
     validator::{ErrorMessage, Validation},
     Confirm, Select, Text,
@@ -9,12 +14,14 @@ use inquire::{
 use libium::{
     config::{
         self,
-        structs::{Config, ModLoader, Profile, ProfileItem, ProfileSource, Version},
+        structs::{
+            Config, ModLoader, Overrides, Profile, ProfileItem, ProfileSource, Side, Version,
+        },
     },
     get_minecraft_dir,
     iter_ext::IterExt,
 };
-use std::path::PathBuf;
+use std::{fs, path::PathBuf};
 
 /// Create a new profile.
 /// Optionally, provide the settings as arguments.
@@ -49,9 +56,19 @@ pub struct Args {
     /// i.e not make a file for it and instead store it directly in the ferium/ogj-config.toml
     #[clap(long, short)]
     pub embed: bool,
+    /// The side to install files for, files only for the other side are skipped.
+    /// A modpack's overrides for this side are extracted too.
+    #[clap(long, value_enum)]
+    pub side: Option<Side>,
+    /// Create the profile from a `.mrpack` or Curseforge modpack zip file, or from a modpack source.
+    /// Every file of the modpack is added as a pinned source and its overrides are extracted next to the profile.
+    #[clap(long, value_name = "FILE|SOURCE")]
+    #[clap(conflicts_with_all = ["import", "game_versions", "mod_loader"])]
+    pub from_modpack: Option<String>,
 }
 
 pub async fn create(
+    ctx: &Context,
     config: &mut Config,
     Args {
         import,
@@ -61,10 +78,26 @@ pub async fn create(
         minecraft_dir,
         profile_path,
         embed,
+        side,
+        from_modpack,
     }: Args,
 ) -> Result<()> {
-    let item = match (game_versions, mod_loader, name, minecraft_dir) {
-        (Some(game_versions), Some(mod_loader), Some(name), minecraft_dir) => {
+    let side = side.unwrap_or_default();
+    let item = match (from_modpack, game_versions, mod_loader, name, minecraft_dir) {
+        (Some(from), None, None, name, minecraft_dir) => {
+            create_from_modpack(
+                ctx,
+                config,
+                &from,
+                name,
+                minecraft_dir,
+                profile_path,
+                embed,
+                side,
+            )
+            .await?
+        }
+        (None, Some(game_versions), Some(mod_loader), Some(name), minecraft_dir) => {
             for item in &config.profiles {
                 ensure!(
                     !item.config.name.eq_ignore_ascii_case(&name),
@@ -78,6 +111,7 @@ pub async fn create(
             );
 
             let mut profile = Profile::new(Some(game_versions), mod_loader);
+            profile.side = side;
 
             import_from(config, import, &mut profile)?;
 
@@ -93,7 +127,7 @@ pub async fn create(
                 ProfileItem::new(ProfileSource::Path(path), name, minecraft_dir)
             }
         }
-        (None, None, None, None) => {
+        (None, None, None, None, None) => {
             let mut minecraft_dir = get_minecraft_dir();
             println!(
                 "The default .minecraft directory is {}",
@@ -136,6 +170,7 @@ pub async fn create(
                 Some(pick_minecraft_version(&[]).await?),
                 pick_mod_loader(None)?,
             );
+            profile.side = side;
 
             import_from(config, import, &mut profile)?;
 
@@ -166,6 +201,72 @@ pub async fn create(
     Ok(())
 }
 
+/// Create a profile item for `side` from the modpack at `from`
+#[expect(clippy::too_many_arguments)]
+async fn create_from_modpack(
+    ctx: &Context,
+    config: &Config,
+    from: &str,
+    name: Option<String>,
+    minecraft_dir: Option<PathBuf>,
+    profile_path: Option<PathBuf>,
+    embed: bool,
+    side: Side,
+) -> Result<ProfileItem> {
+    let ModpackProfile {
+        name: pack_name,
+        mut profile,
+        overrides,
+        extracted,
+    } = read_modpack(ctx, from, side).await?;
+
+    let name = name.unwrap_or(pack_name);
+    ensure!(
+        !config
+            .profiles
+            .iter()
+            .any(|item| item.config.name.eq_ignore_ascii_case(&name)),
+        "A profile with that name already exists"
+    );
+    let minecraft_dir = minecraft_dir.unwrap_or_else(get_minecraft_dir);
+    ensure!(
+        minecraft_dir.is_absolute(),
+        "The provided mods directory is not absolute, i.e. it is a relative path"
+    );
+
+    Ok(if embed {
+        ensure!(
+            overrides.is_none(),
+            "The modpack has overrides, which embedded profiles can't have"
+        );
+        ProfileItem::new(
+            ProfileSource::Embedded(Box::new(profile)),
+            name,
+            minecraft_dir,
+        )
+    } else {
+        let path = ProfileItem::infer_path(profile_path, &name)?;
+        if let Some(overrides) = overrides {
+            // The overrides are stored next to the profile, where its relative paths are resolved from
+            let dirname = format!(
+                "{}-overrides",
+                path.file_stem().unwrap_or_default().to_string_lossy()
+            );
+            let target = path.with_file_name(&dirname);
+            fs::create_dir_all(&target)?;
+            copy(
+                overrides,
+                &target,
+                &CopyOptions::new().content_only(true).overwrite(true),
+            )?;
+            profile.overrides = Some(Overrides::Directory(dirname.into()));
+        }
+        drop(extracted);
+        config::write_profile(&path, &profile)?;
+        ProfileItem::new(ProfileSource::Path(path), name, minecraft_dir)
+    })
+}
+
 #[expect(clippy::option_option)]
 fn import_from(
     config: &mut Config,
@@ -1,8 +1,47 @@
 use colored::Colorize;
 use libium::{
-    config::structs::{ProfileItemConfig, ProfileSourceMut},
+    config::structs::{Filters, ProfileItemConfig, ProfileSourceMut, Side},
     iter_ext::IterExt as _,
 };
+use serde::Serialize;
+use std::path::PathBuf;
+
+/// The machine-readable information about a profile
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Info {
+    pub name: String,
+    pub minecraft_dir: PathBuf,
+    pub active: bool,
+    /// The path to the profile file, `None` if it is embedded
+    pub profile_path: Option<PathBuf>,
+    pub filters: Filters,
+    /// The side files are installed for
+    pub side: Side,
+    pub sources: usize,
+}
+
+pub fn info_json(
+    profile_item: &ProfileItemConfig,
+    profile: &ProfileSourceMut,
+    active: bool,
+) -> Info {
+    Info {
+        name: profile_item.name.clone(),
+        minecraft_dir: profile_item.minecraft_dir.clone(),
+        active,
+        profile_path: match profile {
+            ProfileSourceMut::Path(path, _) => Some((*path).clone()),
+            ProfileSourceMut::Embedded(_) => None,
+        },
+        filters: profile.filters.clone(),
+        side: profile_item.side.unwrap_or(profile.side),
+        sources: profile.mods.len()
+            + profile.resourcepacks.len()
+            + profile.shaders.len()
+            + profile.modpacks.len(),
+    }
+}
 
 pub fn info(profile_item: &ProfileItemConfig, profile: &ProfileSourceMut, active: bool) {
     let name = if active {
@@ -39,6 +78,8 @@ pub fn info(profile_item: &ProfileItemConfig, profile: &ProfileSourceMut, active
         .map(|l| l.to_string().purple())
         .display(" or ");
 
+    let side = profile_item.side.unwrap_or(profile.side).to_string().cyan();
+
     let sources = (profile.mods.len()
         + profile.resourcepacks.len()
         + profile.shaders.len()
@@ -52,6 +93,7 @@ pub fn info(profile_item: &ProfileItemConfig, profile: &ProfileSourceMut, active
         \r  Minecraft Dir:      {minecraft_dir}
         \r  Minecraft Version:  {version}
         \r  Mod Loader:         {mod_loader}
+        \r  Side:               {side}
         \r  Sources:            {sources}\n"
     );
 }
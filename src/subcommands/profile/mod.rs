@@ -4,6 +4,7 @@ mod delete;
 pub mod embed;
 pub mod import;
 mod info;
+mod modpack;
 pub mod switch;
 pub mod unembed;
 pub use configure::configure;
@@ -11,7 +12,7 @@ pub use create::create;
 pub use delete::delete;
 pub use embed::embed;
 pub use import::import;
-pub use info::info;
+pub use info::{info, info_json};
 pub use switch::switch;
 pub use unembed::unembed;
 
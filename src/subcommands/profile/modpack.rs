@@ -0,0 +1,369 @@
+use crate::{warn, Context};
+use anyhow::{bail, Context as _, Result};
+use fs_extra::dir::{copy, CopyOptions};
+use libium::{
+    config::{
+        modpack::{
+            curseforge,
+            modrinth::{self, DependencyID},
+            zip_extract,
+        },
+        structs::{
+            Filters, Host, ModLoader, Profile, Side, Source, SourceId, SourceKind,
+            SourceKindWithModpack, Version,
+        },
+    },
+    get_tmp_dir,
+    iter_ext::IterExt as _,
+    rate_limit, CURSEFORGE_API, MODRINTH_API,
+};
+use reqwest::Url;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Component, Path, PathBuf},
+};
+use tempfile::TempDir;
+
+/// A profile read from a modpack
+pub struct ModpackProfile {
+    /// The name of the modpack
+    pub name: String,
+    pub profile: Profile,
+    /// The extracted overrides of the modpack, `None` if it doesn't have any
+    pub overrides: Option<PathBuf>,
+    /// The directory the modpack is extracted to, which is removed once this is dropped
+    pub extracted: TempDir,
+}
+
+/// Read the modpack file at `from`, or download it if `from` is a source identifier,
+/// and turn it into a profile for `side` with a pinned source for every file
+pub async fn read_modpack(ctx: &Context, from: &str, side: Side) -> Result<ModpackProfile> {
+    let tmp_dir = tempfile::Builder::new()
+        .prefix("from-modpack")
+        .tempdir_in(get_tmp_dir()?)?;
+    let path = if Path::new(from).exists() {
+        PathBuf::from(from)
+    } else {
+        let id: SourceId = from
+            .parse()
+            .with_context(|| format!("{from} is neither a modpack file nor a source"))?;
+        let (_, download_file) = Source::Single(id)
+            .fetch_download_file(&ctx.download.cache, None, SourceKind::Modpacks, vec![])
+            .await?;
+        let path = tmp_dir.path().join(&download_file.output);
+        download_file
+            .download(
+                reqwest::Client::new(),
+                tmp_dir.path(),
+                &ctx.download,
+                |_| {},
+            )
+            .await?;
+        path
+    };
+
+    let extracted = tmp_dir.path().join("extracted");
+    zip_extract(&path, &extracted)?;
+
+    let (name, mut profile, overrides) = match SourceKindWithModpack::infer(&path)? {
+        Some(SourceKindWithModpack::ModpacksModrinth) => from_modrinth(&extracted, side).await?,
+        Some(SourceKindWithModpack::ModpacksCurseforge) => from_curseforge(&extracted).await?,
+        _ => bail!("That is not a modpack!"),
+    };
+    profile.side = side;
+    Ok(ModpackProfile {
+        name,
+        profile,
+        overrides,
+        extracted: tmp_dir,
+    })
+}
+
+/// Create a profile for `game_version` and `loader` pinned to `loader_version`,
+/// without a mod loader filter for vanilla modpacks
+fn new_profile(
+    game_version: Option<&str>,
+    loader: Option<ModLoader>,
+    loader_version: Option<&str>,
+) -> Result<Profile> {
+    let exact = |version: &str| format!("={version}").parse::<Version>();
+    let versions = game_version
+        .map(exact)
+        .transpose()?
+        .map(|version| vec![version]);
+
+    let mut profile = match loader {
+        Some(loader) => Profile::new(versions, loader),
+        None => Profile::with_filters(Filters {
+            versions,
+            ..Filters::empty()
+        }),
+    };
+    // Modpacks may use loader versions that aren't semver, which can't be pinned
+    profile.loader_version = loader_version.and_then(|version| exact(version).ok());
+    Ok(profile)
+}
+
+/// Add `source` to the profile under `name`, numbering it if the name is already taken
+fn insert_source(profile: &mut Profile, kind: SourceKind, name: &str, source: Source) {
+    let sources = profile.map_mut(kind);
+    let mut unique = name.to_owned();
+    let mut number = 2;
+    while sources.contains_key(&unique) {
+        unique = format!("{name} ({number})");
+        number += 1;
+    }
+    sources.insert(unique, source);
+}
+
+/// Get the overrides in `dirs` of the `extracted` modpack, merging them into the first one
+fn overrides(extracted: &Path, dirs: &[&str]) -> Result<Option<PathBuf>> {
+    let mut overrides = None;
+    for dir in dirs {
+        let path = extracted.join(dir);
+        if !path.is_dir() {
+            continue;
+        }
+        match &overrides {
+            None => overrides = Some(path),
+            Some(into) => {
+                copy(
+                    &path,
+                    into,
+                    &CopyOptions::new().content_only(true).overwrite(true),
+                )?;
+            }
+        }
+    }
+    Ok(overrides)
+}
+
+/// Read the Modrinth modpack `extracted`, returning its name, profile, and overrides for `side`
+async fn from_modrinth(extracted: &Path, side: Side) -> Result<(String, Profile, Option<PathBuf>)> {
+    let metadata: modrinth::Metadata = serde_json::from_str(
+        &fs::read_to_string(extracted.join("modrinth.index.json"))
+            .context("Does not contain metadata file")?,
+    )?;
+
+    let mut game_version = None;
+    let mut loader = None;
+    for (id, version) in &metadata.dependencies {
+        let mod_loader = match id {
+            DependencyID::Minecraft => {
+                game_version = Some(version.as_str());
+                continue;
+            }
+            DependencyID::FabricLoader => ModLoader::Fabric,
+            DependencyID::QuiltLoader => ModLoader::Quilt,
+            DependencyID::Forge => ModLoader::Forge,
+            DependencyID::Neoforge => ModLoader::NeoForge,
+        };
+        loader = Some((mod_loader, version.as_str()));
+    }
+    let mut profile = new_profile(
+        game_version,
+        loader.map(|(loader, _)| loader),
+        loader.map(|(_, version)| version),
+    )?;
+
+    let mut skipped = vec![];
+    let mut sources = vec![];
+    for file in &metadata.files {
+        let (Some(kind), Some(url), Some(stem)) = (
+            kind_of(&file.path),
+            file.downloads.first(),
+            file.path.file_stem(),
+        ) else {
+            skipped.push(file.path.display());
+            continue;
+        };
+        sources.push((
+            kind,
+            stem.to_string_lossy(),
+            mrpack_source(url, &file.hashes.sha512),
+        ));
+    }
+    if !skipped.is_empty() {
+        warn!(
+            "these files can't be added as sources since they aren't mods, resourcepacks, or shaders: {}",
+            skipped.iter().display(", ")
+        );
+    }
+
+    // Sources pinned to Modrinth versions are named after their project, others after their file
+    let project_ids = sources
+        .iter()
+        .filter_map(|(_, _, source)| match source {
+            Source::Single(SourceId::PinnedModrinth(project, _)) => Some(project.as_str()),
+            _ => None,
+        })
+        .collect_vec();
+    let titles: HashMap<_, _> = if project_ids.is_empty() {
+        HashMap::new()
+    } else {
+        match rate_limit::send(Host::Modrinth, || {
+            MODRINTH_API.get_multiple_projects(&project_ids)
+        })
+        .await
+        {
+            Ok(projects) => projects
+                .into_iter()
+                .map(|project| (project.id, project.title))
+                .collect(),
+            Err(err) => {
+                warn!("could not look up the modpack's projects, their filenames will be used as names: {err}");
+                HashMap::new()
+            }
+        }
+    };
+    for (kind, stem, source) in sources {
+        let title = match &source {
+            Source::Single(SourceId::PinnedModrinth(project, _)) => titles.get(project),
+            _ => None,
+        };
+        insert_source(
+            &mut profile,
+            kind,
+            title.map_or(&*stem, String::as_str),
+            source,
+        );
+    }
+
+    // Files only for the other side are in separate overrides
+    let side_overrides = match side {
+        Side::Client => Some("client-overrides"),
+        Side::Server => Some("server-overrides"),
+        Side::Both => {
+            let skipped = ["client-overrides", "server-overrides"]
+                .into_iter()
+                .filter(|dir| extracted.join(dir).is_dir())
+                .collect_vec();
+            if !skipped.is_empty() {
+                warn!(
+                    "the modpack's {} aren't extracted since the profile is for both sides, create it with `--side` to include them",
+                    skipped.iter().display(" and ")
+                );
+            }
+            None
+        }
+    };
+    let overrides = overrides(
+        extracted,
+        &["overrides"]
+            .into_iter()
+            .chain(side_overrides)
+            .collect_vec(),
+    )?;
+
+    Ok((metadata.name, profile, overrides))
+}
+
+/// Get the kind of source a modpack file at `path` is from the directory it is in
+fn kind_of(path: &Path) -> Option<SourceKind> {
+    let Some(Component::Normal(dir)) = path.components().next() else {
+        return None;
+    };
+    SourceKind::ARRAY
+        .iter()
+        .copied()
+        .find(|kind| kind.dirname().is_some_and(|dirname| dir == dirname))
+}
+
+/// Get the source of a modpack file downloaded from `url`
+///
+/// Files from Modrinth's CDN are pinned to their project and version,
+/// others are downloaded from the URL and checked against their `sha512` hash.
+fn mrpack_source(url: &Url, sha512: &str) -> Source {
+    if url.host_str() == Some("cdn.modrinth.com") {
+        if let Some(["data", project, "versions", version, ..]) = url
+            .path_segments()
+            .map(Iterator::collect::<Vec<_>>)
+            .as_deref()
+        {
+            return Source::Single(SourceId::PinnedModrinth(
+                (*project).to_owned(),
+                (*version).to_owned(),
+            ));
+        }
+    }
+
+    Source::Detailed {
+        filters: Filters {
+            hashes: Some(vec![sha512.to_owned()]),
+            ..Filters::empty()
+        },
+        src: Box::new(Source::Single(SourceId::Url(url.clone()))),
+    }
+}
+
+/// Read the `CurseForge` modpack `extracted`, returning its name, profile, and overrides
+async fn from_curseforge(extracted: &Path) -> Result<(String, Profile, Option<PathBuf>)> {
+    let manifest: curseforge::Manifest = serde_json::from_str(
+        &fs::read_to_string(extracted.join("manifest.json"))
+            .context("Does not contain manifest")?,
+    )?;
+
+    // Mod loaders are identified by their name and version, e.g. `fabric-0.16.5`
+    let loader = manifest
+        .minecraft
+        .mod_loaders
+        .iter()
+        .find(|loader| loader.primary)
+        .or(manifest.minecraft.mod_loaders.first())
+        .and_then(|loader| loader.id.split_once('-'))
+        .and_then(|(loader, version)| Some((loader.parse().ok()?, version)));
+    let mut profile = new_profile(
+        Some(&manifest.minecraft.version),
+        loader.map(|(loader, _)| loader),
+        loader.map(|(_, version)| version),
+    )?;
+
+    let (files, optional): (Vec<_>, Vec<_>) = manifest.files.iter().partition(|file| file.required);
+    if !optional.is_empty() {
+        warn!(
+            "the modpack's optional files aren't added: {}",
+            optional.iter().map(|file| file.project_id).display(", ")
+        );
+    }
+
+    let project_ids = files.iter().map(|file| file.project_id).collect_vec();
+    let projects = match rate_limit::send(Host::Curseforge, || {
+        CURSEFORGE_API.get_mods(project_ids.clone())
+    })
+    .await
+    {
+        Ok(projects) => projects
+            .into_iter()
+            .map(|project| (project.id, project))
+            .collect(),
+        Err(err) => {
+            warn!(
+                "could not look up the modpack's projects, their IDs will be used as names: {err}"
+            );
+            HashMap::new()
+        }
+    };
+
+    for file in files {
+        let (name, kind) = match projects.get(&file.project_id) {
+            Some(project) => (
+                project.name.clone(),
+                project
+                    .class_id
+                    .and_then(SourceKindWithModpack::from_cf_class_id)
+                    .map_or(SourceKind::Mods, SourceKindWithModpack::to_kind),
+            ),
+            None => (file.project_id.to_string(), SourceKind::Mods),
+        };
+        insert_source(
+            &mut profile,
+            kind,
+            &name,
+            Source::Single(SourceId::PinnedCurseforge(file.project_id, file.file_id)),
+        );
+    }
+
+    let overrides = overrides(extracted, &[&manifest.overrides])?;
+    Ok((manifest.name, profile, overrides))
+}
@@ -1,15 +1,15 @@
+use super::update::find_top_source;
+use crate::Context;
 use anyhow::{bail, Result};
 use colored::Colorize as _;
 use inquire::MultiSelect;
-use libium::{
-    config::structs::{Profile, SourceId},
-    iter_ext::IterExt as _,
-};
+use libium::{config::structs::Profile, iter_ext::IterExt as _};
+use serde_json::json;
 
 /// If `to_remove` is empty, display a list of projects in the profile to select from and remove selected ones
 ///
 /// Else, search the given strings with the projects' name and IDs and remove them
-pub fn remove(profile: &mut Profile, to_remove: Vec<String>) -> Result<()> {
+pub fn remove(ctx: &Context, profile: &mut Profile, to_remove: Vec<String>) -> Result<()> {
     let keys_to_remove = if to_remove.is_empty() {
         let ids = profile.top_sources().collect_vec();
 
@@ -28,17 +28,7 @@ pub fn remove(profile: &mut Profile, to_remove: Vec<String>) -> Result<()> {
         let mut items_to_remove = Vec::new();
 
         for to_remove in to_remove {
-            if let Some((kind, (name, _))) = profile.top_sources().find(|(_, (name, source))| {
-                name.eq_ignore_ascii_case(&to_remove)
-                    || source.ids().any(|id| match id {
-                        SourceId::Curseforge(id) => id.to_string() == to_remove,
-                        SourceId::Modrinth(id) => *id == to_remove,
-                        SourceId::Github(owner, repo) => {
-                            format!("{owner}/{repo}").eq_ignore_ascii_case(&to_remove)
-                        }
-                        _ => todo!(),
-                    })
-            }) {
+            if let Some((kind, name)) = find_top_source(profile, &to_remove) {
                 items_to_remove.push((kind, name.to_owned()));
             } else {
                 bail!("A mod with ID or name {to_remove} is not present in this profile");
@@ -54,7 +44,9 @@ pub fn remove(profile: &mut Profile, to_remove: Vec<String>) -> Result<()> {
         removed.push(key);
     }
 
-    if !removed.is_empty() {
+    if ctx.json {
+        ctx.output(&json!({ "removed": removed }))?;
+    } else if !removed.is_empty() {
         println!(
             "Removed {}",
             removed.iter().map(|txt| txt.bold()).display(", ")
@@ -0,0 +1,113 @@
+use crate::{add::display_successes_failures, cli::Platform, status, warn, Context};
+use anyhow::{bail, Result};
+use colored::Colorize as _;
+use inquire::MultiSelect;
+use libium::{
+    config::structs::{Filters, Profile},
+    iter_ext::IterExt as _,
+    search::{self, SearchResult},
+};
+
+/// Search `platform`, or both Modrinth and `CurseForge`, for up to `limit` mods matching `query`
+/// that are compatible with `profile`, and add the ones that are selected
+///
+/// Returns whether adding any of them failed.
+pub async fn search(
+    ctx: &Context,
+    profile: &mut Profile,
+    query: &str,
+    platform: Option<Platform>,
+    limit: usize,
+) -> Result<bool> {
+    let search_modrinth = !matches!(platform, Some(Platform::Curseforge));
+    let search_curseforge = !matches!(platform, Some(Platform::Modrinth));
+    let (modrinth, curseforge) = tokio::join!(
+        async {
+            if search_modrinth {
+                Some(search::modrinth(&ctx.download.cache, query, &profile.filters, limit).await)
+            } else {
+                None
+            }
+        },
+        async {
+            if search_curseforge {
+                Some(search::curseforge(&ctx.download.cache, query, &profile.filters, limit).await)
+            } else {
+                None
+            }
+        },
+    );
+
+    let mut results = vec![];
+    let mut errors = vec![];
+    for (platform, result) in [("Modrinth", modrinth), ("CurseForge", curseforge)] {
+        match result {
+            Some(Ok(found)) => results.extend(found.into_iter().map(|result| (platform, result))),
+            Some(Err(err)) => errors.push(format!("{platform}: {err}")),
+            None => {}
+        }
+    }
+    if results.is_empty() {
+        if !errors.is_empty() {
+            bail!("Could not search for {query}\n{}", errors.join("\n"));
+        }
+        status!(
+            ctx,
+            "{}",
+            format!("No compatible mods found for {query}").yellow()
+        );
+        return Ok(false);
+    }
+    for err in errors {
+        warn!("could not search {err}");
+    }
+
+    let pad_len = results
+        .iter()
+        .map(|(_, result)| result.title.len())
+        .max()
+        .unwrap_or_default()
+        .clamp(20, 40);
+    let options = results
+        .iter()
+        .map(|(platform, result)| describe(platform, result, pad_len))
+        .collect_vec();
+    let selected = MultiSelect::new("Select mods to add", options)
+        .with_page_size(20)
+        .raw_prompt_skippable()?
+        .unwrap_or_default();
+    if selected.is_empty() {
+        return Ok(false);
+    }
+
+    let ids = selected
+        .iter()
+        .map(|option| results[option.index].1.id.clone())
+        .collect_vec();
+    let (successes, failures) = libium::add(profile, ids, true, Filters::empty()).await?;
+    display_successes_failures(ctx, &successes, failures)
+}
+
+/// Describe the `result` from `platform` on one line, padding its title to `pad_len`
+fn describe(platform: &str, result: &SearchResult, pad_len: usize) -> String {
+    format!(
+        "{:pad_len$}  {:10}  {:>6} downloads  {:6}  {}  {}",
+        result.title,
+        platform,
+        short_count(result.downloads),
+        result
+            .side
+            .map_or_else(|| "-".to_owned(), |side| side.to_string()),
+        result.categories.iter().display(", "),
+        result.slug.dimmed(),
+    )
+}
+
+/// Shorten a large `count` using a suffix, e.g. 1.2M
+pub fn short_count(count: usize) -> String {
+    match count {
+        1_000_000.. => format!("{}.{}M", count / 1_000_000, count / 100_000 % 10),
+        1_000.. => format!("{}.{}k", count / 1_000, count / 100 % 10),
+        _ => count.to_string(),
+    }
+}
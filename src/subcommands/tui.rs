@@ -0,0 +1,745 @@
+use super::{
+    search::short_count,
+    upgrade::{upgrade, Update},
+};
+use crate::Context;
+use anyhow::Result;
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    execute, queue,
+    style::{Print, PrintStyledContent, StyledContent, Stylize as _},
+    terminal::{self, ClearType, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use libium::{
+    cache::Cache,
+    config::{
+        lock::{lockfile_path, LockedFile, Lockfile},
+        read_lockfile,
+        structs::{Filters, Host, Profile, ProfileItemConfig, Source, SourceId, SourceKind},
+    },
+    iter_ext::IterExt as _,
+    search::{self, SearchResult},
+};
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+    path::Path,
+};
+use tokio::task::JoinSet;
+
+/// The maximum number of results to show from each platform when searching
+const SEARCH_LIMIT: usize = 20;
+/// The height of the pane showing the selected source's resolved version
+const DETAILS_HEIGHT: u16 = 8;
+const HELP: &str = "←/→ kind  ↑/↓ move  tab pane  / search  a add  d remove  p pin  c check updates  u update  U upgrade all  q quit";
+
+/// Browse and manage the profile in a full-screen terminal UI
+///
+/// Only the terminal's keyboard input and escape codes are used, so it also works over SSH.
+/// Changes are made to `profile`, which is written by the caller once the UI is closed.
+pub async fn tui(
+    ctx: &Context,
+    // The path to the profile file or `None` if it is embedded
+    profile_path: Option<&Path>,
+    profile_item: &ProfileItemConfig,
+    profile: &mut Profile,
+) -> Result<()> {
+    let mut app = App::new(ctx, profile_path, profile_item, profile)?;
+    let mut terminal = Terminal::enter()?;
+
+    loop {
+        app.draw()?;
+        // Other events, e.g. resizing, only need a redraw
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        match app.handle(key).await? {
+            Action::Continue => {}
+            Action::Quit => return Ok(()),
+            Action::Upgrade(update) => {
+                // Upgrading prints its progress, so it is shown outside of the UI
+                drop(terminal);
+                let result = upgrade(
+                    ctx,
+                    app.profile_path,
+                    app.profile_item,
+                    app.profile,
+                    Filters::empty(),
+                    update,
+                    false,
+                )
+                .await;
+                if let Err(err) = result {
+                    println!("{}", err.to_string().red().bold());
+                }
+                println!("\n{}", "Press enter to return".bold());
+                io::stdin().read_line(&mut String::new())?;
+
+                terminal = Terminal::enter()?;
+                app.lockfile = read_lockfile_of(app.profile_path)?;
+                app.updates.clear();
+            }
+        }
+    }
+}
+
+/// Puts the terminal into raw mode on an alternate screen, and restores it when dropped
+struct Terminal;
+
+impl Terminal {
+    fn enter() -> Result<Self> {
+        terminal::enable_raw_mode()?;
+        execute!(io::stdout(), EnterAlternateScreen, cursor::Hide)?;
+        Ok(Self)
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        // There is nothing else that can be done if the terminal can't be restored
+        let _ = execute!(io::stdout(), cursor::Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// What to do after a key is handled
+enum Action {
+    Continue,
+    Quit,
+    /// Leave the UI to upgrade the profile
+    Upgrade(Update),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Pane {
+    Sources,
+    Search,
+}
+
+/// Whether a newer compatible version of a source is available
+enum UpdateStatus {
+    /// The filename of the version that would be installed
+    Available(String),
+    UpToDate,
+    Failed(String),
+}
+
+/// A rectangle of the terminal
+#[derive(Clone, Copy)]
+struct Area {
+    x: u16,
+    y: u16,
+    width: u16,
+    height: u16,
+}
+
+pub struct App<'a> {
+    ctx: &'a Context,
+    profile_path: Option<&'a Path>,
+    profile_item: &'a ProfileItemConfig,
+    profile: &'a mut Profile,
+    lockfile: Lockfile,
+    /// The kind of sources being shown
+    kind: SourceKind,
+    focus: Pane,
+    /// The sorted names of the sources of `kind`
+    names: Vec<String>,
+    selected_source: usize,
+    query: String,
+    /// Whether the search query is being typed
+    editing: bool,
+    results: Vec<(&'static str, SearchResult)>,
+    selected_result: usize,
+    updates: HashMap<(SourceKind, String), UpdateStatus>,
+    /// Shown instead of the help until the next key is pressed
+    message: Option<StyledContent<String>>,
+    /// The source that is removed if it is removed again
+    removing: Option<String>,
+}
+
+impl<'a> App<'a> {
+    pub fn new(
+        ctx: &'a Context,
+        profile_path: Option<&'a Path>,
+        profile_item: &'a ProfileItemConfig,
+        profile: &'a mut Profile,
+    ) -> Result<Self> {
+        let mut app = Self {
+            ctx,
+            profile_path,
+            profile_item,
+            profile,
+            lockfile: read_lockfile_of(profile_path)?,
+            kind: SourceKind::Mods,
+            focus: Pane::Sources,
+            names: vec![],
+            selected_source: 0,
+            query: String::new(),
+            editing: false,
+            results: vec![],
+            selected_result: 0,
+            updates: HashMap::new(),
+            message: None,
+            removing: None,
+        };
+        app.refresh_names();
+        Ok(app)
+    }
+
+    /// Read the names of the sources of the current kind again, keeping the selection in bounds
+    fn refresh_names(&mut self) {
+        self.names = self.profile.map(self.kind).keys().cloned().collect();
+        self.names.sort_by_key(|name| name.to_lowercase());
+        self.selected_source = self.selected_source.min(self.names.len().saturating_sub(1));
+    }
+
+    fn selected_name(&self) -> Option<String> {
+        self.names.get(self.selected_source).cloned()
+    }
+
+    /// Show `message` in the status bar and draw it immediately, for before a long running action
+    fn show_progress(&mut self, message: String) -> Result<()> {
+        self.message = Some(message.dim());
+        self.draw()
+    }
+
+    async fn handle(&mut self, key: KeyEvent) -> Result<Action> {
+        self.message = None;
+        if self.editing {
+            self.edit_query(key).await?;
+            return Ok(Action::Continue);
+        }
+        let removing = self.removing.take();
+
+        match key.code {
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                return Ok(Action::Quit)
+            }
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(Action::Quit),
+            KeyCode::Tab | KeyCode::BackTab => {
+                self.focus = match self.focus {
+                    Pane::Sources => Pane::Search,
+                    Pane::Search => Pane::Sources,
+                };
+            }
+            KeyCode::Left | KeyCode::Char('h') => self.switch_kind(SourceKind::ARRAY.len() - 1),
+            KeyCode::Right | KeyCode::Char('l') => self.switch_kind(1),
+            KeyCode::Up | KeyCode::Char('k') => self.move_selection(false),
+            KeyCode::Down | KeyCode::Char('j') => self.move_selection(true),
+            KeyCode::Char('/') => {
+                self.focus = Pane::Search;
+                self.editing = true;
+            }
+            KeyCode::Char('a') | KeyCode::Enter if self.focus == Pane::Search => {
+                self.add_selected().await?;
+            }
+            KeyCode::Char('d') | KeyCode::Delete if self.focus == Pane::Sources => {
+                self.remove_selected(removing.as_deref());
+            }
+            KeyCode::Char('p') if self.focus == Pane::Sources => self.toggle_pin(),
+            KeyCode::Char('c') => self.check_updates().await?,
+            KeyCode::Char('u') if self.focus == Pane::Sources => {
+                if let Some(name) = self.selected_name() {
+                    return Ok(Action::Upgrade(Update::Selected {
+                        names: vec![name],
+                        kinds: vec![],
+                    }));
+                }
+            }
+            KeyCode::Char('U') => return Ok(Action::Upgrade(Update::All)),
+            _ => {}
+        }
+        Ok(Action::Continue)
+    }
+
+    /// Show the kind `offset` places to the right of the current one, wrapping around
+    fn switch_kind(&mut self, offset: usize) {
+        let index = SourceKind::ARRAY
+            .iter()
+            .position(|kind| *kind == self.kind)
+            .unwrap_or_default();
+        self.kind = SourceKind::ARRAY[(index + offset) % SourceKind::ARRAY.len()];
+        self.selected_source = 0;
+        self.refresh_names();
+    }
+
+    fn move_selection(&mut self, down: bool) {
+        let (selected, len) = match self.focus {
+            Pane::Sources => (&mut self.selected_source, self.names.len()),
+            Pane::Search => (&mut self.selected_result, self.results.len()),
+        };
+        *selected = if down {
+            (*selected + 1).min(len.saturating_sub(1))
+        } else {
+            selected.saturating_sub(1)
+        };
+    }
+
+    async fn edit_query(&mut self, key: KeyEvent) -> Result<()> {
+        match key.code {
+            KeyCode::Char(char) if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.query.push(char);
+            }
+            KeyCode::Backspace => {
+                self.query.pop();
+            }
+            KeyCode::Esc => self.editing = false,
+            KeyCode::Enter => {
+                self.editing = false;
+                self.search().await?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Search Modrinth and `CurseForge` for mods compatible with the profile matching the query
+    async fn search(&mut self) -> Result<()> {
+        let query = self.query.trim().to_owned();
+        if query.is_empty() {
+            return Ok(());
+        }
+        self.show_progress(format!("Searching for {query}..."))?;
+
+        let filters = &self.profile.filters;
+        let (modrinth, curseforge) = tokio::join!(
+            search::modrinth(&self.ctx.download.cache, &query, filters, SEARCH_LIMIT),
+            search::curseforge(&self.ctx.download.cache, &query, filters, SEARCH_LIMIT),
+        );
+        self.results.clear();
+        self.selected_result = 0;
+        let mut errors = vec![];
+        for (platform, result) in [("Modrinth", modrinth), ("CurseForge", curseforge)] {
+            match result {
+                Ok(found) => self
+                    .results
+                    .extend(found.into_iter().map(|result| (platform, result))),
+                Err(err) => errors.push(format!("{platform}: {err}")),
+            }
+        }
+
+        self.message = if !errors.is_empty() {
+            Some(format!("Could not search {}", errors.join(", ")).red())
+        } else if self.results.is_empty() {
+            Some(format!("No compatible mods found for {query}").yellow())
+        } else {
+            None
+        };
+        Ok(())
+    }
+
+    async fn add_selected(&mut self) -> Result<()> {
+        let Some((_, result)) = self.results.get(self.selected_result) else {
+            return Ok(());
+        };
+        let (id, title) = (result.id.clone(), result.title.clone());
+        self.show_progress(format!("Adding {title}..."))?;
+
+        // Errors are shown in the status bar rather than closing the UI
+        self.message = Some(
+            match libium::add(self.profile, vec![id], true, Filters::empty()).await {
+                Ok((_, failures)) if !failures.is_empty() => {
+                    let (name, err) = &failures[0];
+                    format!("Could not add {name}: {err}").red()
+                }
+                Ok((successes, _)) => format!("Added {}", successes.iter().display(", ")).green(),
+                Err(err) => format!("Could not add {title}: {err}").red(),
+            },
+        );
+        self.refresh_names();
+        Ok(())
+    }
+
+    /// Remove the selected source if it was already going to be removed, otherwise ask for confirmation
+    fn remove_selected(&mut self, removing: Option<&str>) {
+        let Some(name) = self.selected_name() else {
+            return;
+        };
+        if removing == Some(name.as_str()) {
+            self.profile.map_mut(self.kind).remove(&name);
+            self.message = Some(format!("Removed {name}").green());
+            self.refresh_names();
+        } else {
+            self.message = Some(format!("Press d again to remove {name}").yellow());
+            self.removing = Some(name);
+        }
+    }
+
+    /// Pin the selected source to its resolved version, or unpin it if it is already pinned
+    pub fn toggle_pin(&mut self) {
+        let Some(name) = self.selected_name() else {
+            return;
+        };
+        let locked_id = self
+            .lockfile
+            .get(self.kind, &name)
+            .and_then(|locked| locked.id.clone());
+        let Some(source) = self.profile.map_mut(self.kind).get_mut(&name) else {
+            return;
+        };
+
+        if let Some(toggled) = toggled_pin(source, locked_id) {
+            self.message = Some(if is_pinned(&toggled) {
+                format!("Pinned {name} to its resolved version").green()
+            } else {
+                format!("Unpinned {name}").green()
+            });
+            *source = toggled;
+        } else {
+            self.message = Some(
+                format!("{name} has no resolved version to pin, upgrade the profile first")
+                    .yellow(),
+            );
+        }
+    }
+
+    /// Resolve the latest compatible version of every source of the current kind
+    /// and compare them with the locked versions
+    async fn check_updates(&mut self) -> Result<()> {
+        self.show_progress(format!(
+            "Checking {} sources for updates...",
+            self.names.len()
+        ))?;
+
+        let kind = self.kind;
+        let src_path = self.profile_path.and_then(Path::parent).map(Path::to_owned);
+        let mut tasks = JoinSet::new();
+        for (name, source) in self.profile.map(kind) {
+            let name = name.clone();
+            let source = source.clone();
+            let filters = self.profile.filters.clone();
+            let src_path = src_path.clone();
+            let ctx = self.ctx.clone();
+            // Lists of versions are fetched again, so that new versions show up
+            let cache = Cache {
+                refresh: true,
+                ..self.ctx.download.cache.clone()
+            };
+            tasks.spawn(async move {
+                let result = async {
+                    let _permits = ctx.acquire_permits(Host::of_source(&source)).await?;
+                    let (_, file) = source
+                        .fetch_download_file(&cache, src_path.as_deref(), kind, vec![&filters])
+                        .await?;
+                    Ok::<_, anyhow::Error>(file.filename())
+                }
+                .await;
+                (name, result)
+            });
+        }
+
+        let mut available = 0;
+        while let Some(joined) = tasks.join_next().await {
+            let (name, result) = joined?;
+            let status = match result {
+                Ok(filename)
+                    if self
+                        .lockfile
+                        .get(kind, &name)
+                        .is_some_and(|locked| locked.filename() == filename) =>
+                {
+                    UpdateStatus::UpToDate
+                }
+                Ok(filename) => {
+                    available += 1;
+                    UpdateStatus::Available(filename)
+                }
+                Err(err) => UpdateStatus::Failed(err.to_string()),
+            };
+            self.updates.insert((kind, name), status);
+        }
+        self.message = Some(if available == 0 {
+            "Everything is up to date".to_owned().green()
+        } else {
+            format!("{available} updates available, press U to upgrade").green()
+        });
+        Ok(())
+    }
+
+    fn draw(&self) -> Result<()> {
+        let (width, height) = terminal::size()?;
+        let mut out = io::stdout().lock();
+        queue!(out, terminal::Clear(ClearType::All), cursor::MoveTo(0, 0))?;
+
+        queue!(
+            out,
+            PrintStyledContent(format!(" {} ", self.profile_item.name).bold()),
+        )?;
+        for kind in SourceKind::ARRAY {
+            let tab = format!(" {} ({}) ", kind_name(*kind), self.profile.map(*kind).len());
+            let tab = if *kind == self.kind {
+                tab.reverse()
+            } else {
+                tab.stylize()
+            };
+            queue!(out, Print(" "), PrintStyledContent(tab))?;
+        }
+
+        let body_height = height.saturating_sub(2);
+        let left_width = width / 2;
+        let details_height = DETAILS_HEIGHT.min(body_height);
+        draw_pane(
+            &mut out,
+            Area {
+                x: 0,
+                y: 1,
+                width: left_width,
+                height: body_height,
+            },
+            kind_name(self.kind),
+            self.focus == Pane::Sources,
+            self.source_lines(),
+            (self.focus == Pane::Sources).then_some(self.selected_source),
+        )?;
+        draw_pane(
+            &mut out,
+            Area {
+                x: left_width,
+                y: 1,
+                width: width - left_width,
+                height: details_height,
+            },
+            "Resolved Version",
+            false,
+            self.detail_lines(),
+            None,
+        )?;
+        draw_pane(
+            &mut out,
+            Area {
+                x: left_width,
+                y: 1 + details_height,
+                width: width - left_width,
+                height: body_height - details_height,
+            },
+            "Search",
+            self.focus == Pane::Search,
+            self.search_lines(),
+            // The first line is the query
+            (self.focus == Pane::Search && !self.results.is_empty())
+                .then_some(self.selected_result + 1),
+        )?;
+
+        let status = self
+            .message
+            .clone()
+            .unwrap_or_else(|| HELP.to_owned().dim());
+        queue!(
+            out,
+            cursor::MoveTo(0, height.saturating_sub(1)),
+            PrintStyledContent(fit_styled(&status, width.into())),
+        )?;
+        out.flush()?;
+        Ok(())
+    }
+
+    fn source_lines(&self) -> Vec<StyledContent<String>> {
+        let pad_len = self
+            .names
+            .iter()
+            .map(String::len)
+            .max()
+            .unwrap_or_default()
+            .clamp(10, 30);
+        self.names
+            .iter()
+            .map(|name| {
+                let locked = self.lockfile.get(self.kind, name);
+                let pinned = self.profile.map(self.kind).get(name).is_some_and(is_pinned);
+                let line = format!(
+                    "{} {name:pad_len$}  {}{}",
+                    match self.updates.get(&(self.kind, name.clone())) {
+                        Some(UpdateStatus::Available(_)) => '↑',
+                        Some(UpdateStatus::Failed(_)) => '!',
+                        _ => ' ',
+                    },
+                    locked.map_or_else(|| "not resolved".to_owned(), LockedFile::filename),
+                    if pinned { " (pinned)" } else { "" },
+                );
+                match self.updates.get(&(self.kind, name.clone())) {
+                    Some(UpdateStatus::Available(_)) => line.green(),
+                    Some(UpdateStatus::Failed(_)) => line.red(),
+                    _ if locked.is_none() => line.dim(),
+                    _ => line.stylize(),
+                }
+            })
+            .collect()
+    }
+
+    fn detail_lines(&self) -> Vec<StyledContent<String>> {
+        let Some(name) = self.names.get(self.selected_source) else {
+            return vec![format!(
+                "There are no {} in this profile",
+                kind_name(self.kind).to_lowercase()
+            )
+            .dim()];
+        };
+        let mut lines = vec![name.clone().bold()];
+        if let Some(source) = self.profile.map(self.kind).get(name) {
+            lines.push(format!("Source:  {}", source.ids().display(", ")).stylize());
+        }
+        match self.lockfile.get(self.kind, name) {
+            Some(locked) => {
+                lines.push(format!("Version: {}", locked.title).stylize());
+                lines.push(format!("File:    {}", locked.filename()).stylize());
+                if let Some(id) = &locked.id {
+                    lines.push(format!("Pin:     {id}").dim());
+                }
+            }
+            None => lines.push(
+                "Not resolved yet, upgrade the profile to install it"
+                    .to_owned()
+                    .dim(),
+            ),
+        }
+        lines.push(match self.updates.get(&(self.kind, name.clone())) {
+            Some(UpdateStatus::Available(filename)) => format!("Update:  {filename}").green(),
+            Some(UpdateStatus::UpToDate) => "Up to date".to_owned().green(),
+            Some(UpdateStatus::Failed(err)) => format!("Could not check for updates: {err}").red(),
+            None => "Press c to check for updates".to_owned().dim(),
+        });
+        lines
+    }
+
+    fn search_lines(&self) -> Vec<StyledContent<String>> {
+        let query = format!("/ {}{}", self.query, if self.editing { "_" } else { "" });
+        let mut lines = vec![if self.editing {
+            query.yellow()
+        } else if self.query.is_empty() {
+            "/ Press / to search for mods".to_owned().dim()
+        } else {
+            query.stylize()
+        }];
+
+        let pad_len = self
+            .results
+            .iter()
+            .map(|(_, result)| result.title.len())
+            .max()
+            .unwrap_or_default()
+            .clamp(20, 40);
+        lines.extend(self.results.iter().map(|(platform, result)| {
+            format!(
+                "{:pad_len$}  {platform:10}  {:>6}  {}",
+                result.title,
+                short_count(result.downloads),
+                result.description,
+            )
+            .stylize()
+        }));
+        lines
+    }
+}
+
+/// Draw a bordered pane titled `title` in `area` containing `lines`,
+/// scrolled so that the `selected` line is visible and highlighted
+fn draw_pane(
+    out: &mut impl Write,
+    area: Area,
+    title: &str,
+    focused: bool,
+    lines: Vec<StyledContent<String>>,
+    selected: Option<usize>,
+) -> Result<()> {
+    if area.width < 2 || area.height < 2 {
+        return Ok(());
+    }
+    let inner_width = usize::from(area.width - 2);
+    let inner_height = usize::from(area.height - 2);
+    let border = |text: String| if focused { text.cyan() } else { text.stylize() };
+
+    let title = fit(&format!("─ {title} "), inner_width);
+    queue!(
+        out,
+        cursor::MoveTo(area.x, area.y),
+        PrintStyledContent(border(format!(
+            "┌{title}{}┐",
+            "─".repeat(inner_width - title.chars().count())
+        ))),
+    )?;
+    let offset = selected.map_or(0, |selected| {
+        selected.saturating_sub(inner_height.saturating_sub(1))
+    });
+    let mut lines = lines.into_iter().enumerate().skip(offset);
+    for row in 1..=area.height - 2 {
+        let line = match lines.next() {
+            Some((index, line)) if Some(index) == selected => {
+                fit_styled(&line, inner_width).reverse()
+            }
+            Some((_, line)) => fit_styled(&line, inner_width),
+            None => " ".repeat(inner_width).stylize(),
+        };
+        queue!(
+            out,
+            cursor::MoveTo(area.x, area.y + row),
+            PrintStyledContent(border("│".to_owned())),
+            PrintStyledContent(line),
+            PrintStyledContent(border("│".to_owned())),
+        )?;
+    }
+    queue!(
+        out,
+        cursor::MoveTo(area.x, area.y + area.height - 1),
+        PrintStyledContent(border(format!("└{}┘", "─".repeat(inner_width)))),
+    )?;
+    Ok(())
+}
+
+/// Truncate or pad `text` to exactly `width` characters
+pub fn fit(text: &str, width: usize) -> String {
+    let mut fitted = text.chars().take(width).collect::<String>();
+    if text.chars().count() > width && width > 0 {
+        fitted.pop();
+        fitted.push('…');
+    }
+    format!("{fitted:width$}")
+}
+
+fn fit_styled(text: &StyledContent<String>, width: usize) -> StyledContent<String> {
+    StyledContent::new(*text.style(), fit(text.content(), width))
+}
+
+fn kind_name(kind: SourceKind) -> &'static str {
+    match kind {
+        SourceKind::Mods => "Mods",
+        SourceKind::Resourcepacks => "Resourcepacks",
+        SourceKind::Shaders => "Shaders",
+        SourceKind::Modpacks => "Modpacks",
+    }
+}
+
+fn read_lockfile_of(profile_path: Option<&Path>) -> Result<Lockfile> {
+    Ok(match profile_path {
+        Some(path) => read_lockfile(lockfile_path(path))?,
+        // Embedded profiles don't have a lockfile
+        None => Lockfile::default(),
+    })
+}
+
+fn is_pinned(source: &Source) -> bool {
+    source.ids().all(|id| id.unpinned().is_some())
+}
+
+/// `source` unpinned if it is pinned to a single version, otherwise pinned to `locked_id`
+///
+/// `None` is returned if it isn't pinned and has no resolved version to pin to.
+fn toggled_pin(source: &Source, locked_id: Option<SourceId>) -> Option<Source> {
+    let mut ids = source.ids();
+    let unpinned = match (ids.next(), ids.next()) {
+        (Some(id), None) => id.unpinned(),
+        _ => None,
+    };
+    unpinned.or(locked_id).map(|id| with_id(source, id))
+}
+
+/// Replace the identifiers of `source` with `id`, keeping its filters
+pub fn with_id(source: &Source, id: SourceId) -> Source {
+    match source {
+        Source::Detailed { filters, .. } => Source::from_id(id, filters.clone()),
+        _ => Source::Single(id),
+    }
+}
@@ -0,0 +1,143 @@
+use super::upgrade::{upgrade_locked, Locking, Update};
+use crate::{status, Context};
+use anyhow::{bail, ensure, Context as _, Result};
+use colored::Colorize as _;
+use libium::{
+    config::{
+        lock::{self, LockedFile},
+        structs::{Filters, Profile, ProfileItemConfig, SourceId, SourceKind},
+    },
+    iter_ext::IterExt as _,
+};
+use std::path::Path;
+
+/// Resolve the latest compatible versions of the sources named in `names` and of the kinds in `kinds`,
+/// the other sources are installed from the lockfile.
+///
+/// If both are empty, every source is updated.
+/// Sources that aren't selected have to be locked already, otherwise they would be updated too.
+pub async fn update(
+    ctx: &Context,
+    // The path to the profile file or `None` if it is embedded
+    profile_path: Option<&Path>,
+    profile_item: &ProfileItemConfig,
+    profile: &Profile,
+    names: Vec<String>,
+    kinds: Vec<SourceKind>,
+    filters: Filters,
+) -> Result<()> {
+    let Some(profile_path) = profile_path else {
+        bail!("Embedded profiles don't have a lockfile, use `ogj-ferium upgrade` instead")
+    };
+
+    let names = names
+        .iter()
+        .map(|name| {
+            find_top_source(profile, name)
+                .map(|(_, name)| name.to_owned())
+                .with_context(|| {
+                    format!("A source with ID or name {name} is not present in this profile")
+                })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut locking = Locking::read(Some(profile_path), Update::Selected { names, kinds })?;
+
+    let all_filters = filters.clone().concat(profile.filters.clone());
+    let unlocked = profile
+        .top_sources()
+        .filter(|(kind, (name, source))| {
+            !locking.update.includes(*kind, name)
+                && !locking
+                    .locked
+                    .get(*kind, name)
+                    .is_some_and(|locked| locked.checksum == lock::checksum(source, &all_filters))
+        })
+        .map(|(_, (name, _))| name)
+        .collect_vec();
+    ensure!(
+        unlocked.is_empty(),
+        "{} {} not locked yet, so {} would be updated too. Run `ogj-ferium upgrade` to lock the installed versions first",
+        unlocked.iter().display(", "),
+        if unlocked.len() == 1 { "is" } else { "are" },
+        if unlocked.len() == 1 { "it" } else { "they" },
+    );
+
+    let result = upgrade_locked(
+        ctx,
+        Some(profile_path),
+        profile_item,
+        profile,
+        filters,
+        &mut locking,
+        false,
+    )
+    .await;
+
+    print_diff(ctx, &locking);
+
+    result
+}
+
+/// Find the top level source in `profile` with the case-insensitive name or the ID `query`
+pub fn find_top_source<'a>(profile: &'a Profile, query: &str) -> Option<(SourceKind, &'a String)> {
+    profile
+        .top_sources()
+        .find(|(_, (name, source))| {
+            name.eq_ignore_ascii_case(query)
+                || source.ids().any(|id| match id {
+                    SourceId::Curseforge(id) => id.to_string() == query,
+                    SourceId::Modrinth(id) => *id == query,
+                    SourceId::Github(owner, repo) => {
+                        format!("{owner}/{repo}").eq_ignore_ascii_case(query)
+                    }
+                    _ => false,
+                })
+        })
+        .map(|(kind, (name, _))| (kind, name))
+}
+
+/// Print the entries that changed between the previous and the resolved lockfile
+fn print_diff(ctx: &Context, locking: &Locking) {
+    let mut changes = vec![];
+
+    for kind in SourceKind::ARRAY {
+        let (locked, resolved) = (locking.locked.map(*kind), locking.resolved.map(*kind));
+
+        for (name, new) in resolved {
+            match locked.get(name) {
+                Some(old) if old.url == new.url => {}
+                old => changes.push((name, old, Some(new))),
+            }
+        }
+        for (name, old) in locked {
+            if !resolved.contains_key(name) {
+                changes.push((name, Some(old), None));
+            }
+        }
+    }
+
+    if changes.is_empty() {
+        status!(ctx, "\n{}", "No sources were changed".bold());
+        return;
+    }
+
+    status!(ctx, "\n{}\n", "Changed Sources".bold());
+    for (name, old, new) in changes {
+        status!(
+            ctx,
+            "• {}  {} → {}",
+            name.bold(),
+            old.map_or_else(|| "none".dimmed().to_string(), describe),
+            new.map_or_else(|| "removed".red().to_string(), describe),
+        );
+    }
+}
+
+fn describe(file: &LockedFile) -> String {
+    format!(
+        "{} {}",
+        file.title,
+        format!("({})", file.filename()).dimmed()
+    )
+}
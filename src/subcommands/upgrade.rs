@@ -1,54 +1,208 @@
 use crate::{
-    default_semaphore,
-    download::{clean, download, read_overrides},
-    warn, CROSS, SEMAPHORE, STYLE_NO, TICK,
+    download::{clean, plan_clean, read_overrides, try_download, DownloadError},
+    status, warn, Context, CROSS, STYLE_NO, TICK,
 };
 use anyhow::{anyhow, bail, Context as _, Result};
 use colored::Colorize as _;
 use indicatif::ProgressBar;
 use libium::{
+    cache::Cache,
     config::{
+        lock::{self, lockfile_path, LockedFile, Lockfile},
         modpack::{curseforge, modrinth, read_file_from_zip, zip_extract},
         options::{Options, OptionsOverrides},
-        read_profile,
+        read_lockfile, read_profile,
         structs::{
-            Filters, ModLoader, Profile, ProfileItemConfig, Source, SourceId, SourceKind,
-            SourceKindWithModpack, Version,
+            Filters, Host, ModLoader, Profile, ProfileItemConfig, Side, Source, SourceId,
+            SourceKind, SourceKindWithModpack, Version,
         },
+        write_lockfile,
     },
-    get_tmp_dir,
+    get_tmp_dir, loader, rate_limit,
     upgrade::{
-        from_modpack_file, mod_downloadable, try_from_cf_file, DistributionDeniedError,
-        DownloadData, DownloadSource,
+        from_modpack_file, mod_downloadable,
+        resolve::{excluded_projects, DependencyGraph, Node, ProjectKey},
+        try_from_cf_file, DistributionDeniedError, DownloadData, DownloadSource,
     },
     CURSEFORGE_API,
 };
 use parking_lot::Mutex;
+use serde::Serialize;
 use std::{
+    collections::{BTreeMap, HashSet},
     fs::{self, File},
     io::BufReader,
-    mem::take,
     path::{Component, Path, PathBuf},
-    sync::{mpsc, Arc},
+    sync::Arc,
     time::Duration,
 };
-use tokio::task::JoinSet;
+use tokio::{
+    sync::mpsc,
+    task::{JoinHandle, JoinSet},
+};
+
+/// Which sources should be resolved again instead of being installed from the lockfile
+#[derive(Clone, Debug, Default)]
+pub enum Update {
+    /// Install the locked files, only resolving sources that aren't locked yet
+    #[default]
+    Locked,
+    /// Resolve every source again
+    All,
+    /// Resolve the sources with these (case-insensitive) names and of these kinds again
+    ///
+    /// An empty list selects everything.
+    Selected {
+        names: Vec<String>,
+        kinds: Vec<SourceKind>,
+    },
+}
+
+impl Update {
+    /// Whether the source `name` of `kind` should be resolved again
+    pub fn includes(&self, kind: SourceKind, name: &str) -> bool {
+        match self {
+            Update::Locked => false,
+            Update::All => true,
+            Update::Selected { names, kinds } => {
+                (names.is_empty() || names.iter().any(|n| n.eq_ignore_ascii_case(name)))
+                    && (kinds.is_empty() || kinds.contains(&kind))
+            }
+        }
+    }
+}
+
+impl From<Option<Vec<String>>> for Update {
+    fn from(value: Option<Vec<String>>) -> Self {
+        match value {
+            None => Self::Locked,
+            Some(names) if names.is_empty() => Self::All,
+            Some(names) => Self::Selected {
+                names,
+                kinds: vec![],
+            },
+        }
+    }
+}
+
+/// The lockfile state used while resolving sources
+pub struct Locking {
+    /// The path to the lockfile, or `None` if the profile is embedded
+    pub path: Option<PathBuf>,
+    /// The lockfile read before resolving
+    pub locked: Lockfile,
+    /// The lockfile built from the sources resolved in this run
+    pub resolved: Lockfile,
+    pub update: Update,
+}
+
+impl Locking {
+    /// Read the lockfile belonging to the profile at `profile_path`
+    ///
+    /// Embedded profiles don't have a lockfile, so every source is resolved again.
+    pub fn read(profile_path: Option<&Path>, update: Update) -> Result<Self> {
+        if profile_path.is_none() {
+            warn!("embedded profiles don't have a lockfile, so the latest compatible versions are resolved every time");
+        }
+        let path = profile_path.map(lockfile_path);
+        Ok(Self {
+            locked: match &path {
+                Some(path) => read_lockfile(path)?,
+                None => Lockfile::default(),
+            },
+            path,
+            resolved: Lockfile::default(),
+            update,
+        })
+    }
+}
 
 pub async fn upgrade(
-    // The path to the profiles parent or `None` if it is embedded
-    src_path: Option<&Path>,
+    ctx: &Context,
+    // The path to the profile file or `None` if it is embedded
+    profile_path: Option<&Path>,
+    profile_item: &ProfileItemConfig,
+    profile: &Profile,
+    filters: Filters,
+    update: Update,
+    dry_run: bool,
+) -> Result<()> {
+    let mut locking = Locking::read(profile_path, update)?;
+    upgrade_locked(
+        ctx,
+        profile_path,
+        profile_item,
+        profile,
+        filters,
+        &mut locking,
+        dry_run,
+    )
+    .await
+}
+
+/// Upgrade the profile using the lockfile state in `locking`, then write the resolved lockfile
+///
+/// If `dry_run` is set, only report the changes that would be made to the minecraft directory.
+pub async fn upgrade_locked(
+    ctx: &Context,
+    // The path to the profile file or `None` if it is embedded
+    profile_path: Option<&Path>,
     profile_item: &ProfileItemConfig,
     profile: &Profile,
     filters: Filters,
+    locking: &mut Locking,
+    dry_run: bool,
 ) -> Result<()> {
-    println!("{}", "Upgrading Sources".bold());
+    status!(ctx, "{}", "Upgrading Sources".bold());
+    let profile = &with_item_mirrors(profile, profile_item);
+
+    let Resolution {
+        options,
+        files: mut to_download,
+        errors,
+        ..
+    } = resolve(
+        ctx,
+        profile_path,
+        profile,
+        profile_item.side.unwrap_or(profile.side),
+        filters,
+        locking,
+    )
+    .await?;
+    let error = !errors.is_empty();
+
+    if ctx.json {
+        ctx.output(&UpgradeOutput {
+            files: &to_download,
+            errors: &errors,
+            failed_downloads: &[],
+        })?;
+    }
 
-    let mut options = OptionsOverrides::default();
-    let mut to_download = vec![];
+    if dry_run {
+        print_plan(ctx, &profile_item.minecraft_dir, to_download, &options)?;
+        return if error {
+            Err(anyhow!(
+                "\nCould not get the latest compatible version of some sources"
+            ))
+        } else {
+            Ok(())
+        };
+    }
 
-    let error =
-        get_platform_downloadables(src_path, &mut options, &mut to_download, profile, filters)
-            .await?;
+    // Every mod jar of the profile, including the ones that are already installed
+    let mods = to_download
+        .iter()
+        .filter(|file| {
+            file.output.starts_with("mods")
+                && file
+                    .output
+                    .extension()
+                    .is_some_and(|ext| ext.eq_ignore_ascii_case("jar"))
+        })
+        .map(|file| file.output.clone())
+        .collect::<Vec<_>>();
 
     for kind in SourceKind::ARRAY {
         let Some(dirname) = kind.dirname() else {
@@ -68,24 +222,269 @@ pub async fn upgrade(
         .await?;
     }
 
+    let files = to_download.clone();
+    let mut failed_downloads = vec![];
     if to_download.is_empty() {
-        println!("\n{}", "All up to date!".bold());
+        status!(ctx, "\n{}", "All up to date!".bold());
     } else {
-        println!("{}", "\nDownloading Source Files\n".bold());
-        download(profile_item.minecraft_dir.clone(), to_download).await?;
+        status!(ctx, "{}", "\nDownloading Source Files\n".bold());
+        for file in &mut to_download {
+            file.use_mirrors(&profile.mirrors);
+        }
+        failed_downloads =
+            try_download(ctx, profile_item.minecraft_dir.clone(), to_download).await?;
     }
+    let refused = refuse_incompatible_mods(
+        profile,
+        &profile_item.minecraft_dir,
+        &mods,
+        &mut locking.resolved,
+    )?;
 
     apply_options_overrides(&profile_item.minecraft_dir, options)?;
 
+    if let Some(path) = &locking.path {
+        write_lockfile(path, &locking.resolved)?;
+    }
+
+    if ctx.json {
+        ctx.output(&UpgradeOutput {
+            files: &files,
+            errors: &errors,
+            failed_downloads: &failed_downloads,
+        })?;
+    }
+
     if error {
         Err(anyhow!(
             "\nCould not get the latest compatible version of some sources"
         ))
+    } else if !failed_downloads.is_empty() {
+        Err(anyhow!("\nCould not download some files"))
+    } else if refused {
+        Err(anyhow!(
+            "\nSome mods can't be loaded by the mod loader version the profile pins"
+        ))
     } else {
         Ok(())
     }
 }
 
+/// Get the `profile` with the mirrors of its config entry `profile_item` added, overriding its own
+pub fn with_item_mirrors(profile: &Profile, profile_item: &ProfileItemConfig) -> Profile {
+    let mut profile = profile.clone();
+    profile.mirrors.extend(profile_item.mirrors.clone());
+    profile
+}
+
+/// Remove the installed `mods` that can't be loaded by any mod loader version the profile's `loader-version` allows
+///
+/// `mods` are relative to `minecraft_dir`, mods that aren't installed are skipped.
+/// The removed mods are dropped from `lockfile` too, so they're resolved again on the next upgrade.
+/// Returns whether any mods were removed.
+fn refuse_incompatible_mods(
+    profile: &Profile,
+    minecraft_dir: &Path,
+    mods: &[PathBuf],
+    lockfile: &mut Lockfile,
+) -> Result<bool> {
+    let (Some(&mod_loader), Some(requirement)) = (
+        profile
+            .filters
+            .mod_loaders
+            .as_ref()
+            .and_then(|loaders| loaders.first()),
+        profile.loader_version.as_ref(),
+    ) else {
+        return Ok(false);
+    };
+    // Mods that can be loaded by the newest allowed version can be loaded by some allowed version
+    let Some(loader_version) = requirement.exact().or_else(|| requirement.upper_bound()) else {
+        return Ok(false);
+    };
+
+    let mut refused = false;
+    for output in mods {
+        let path = minecraft_dir.join(output);
+        if !path.is_file() {
+            continue;
+        }
+        if let Some(needs) = loader::unmet_requirement(&path, mod_loader, &loader_version)? {
+            fs::remove_file(&path)?;
+            lockfile.mods.retain(|_, locked| &locked.output != output);
+            eprintln!(
+                "{}",
+                format!(
+                    "{CROSS} {} needs {mod_loader} {needs}, but the profile requires {requirement}",
+                    output.file_name().unwrap_or_default().to_string_lossy()
+                )
+                .red()
+            );
+            refused = true;
+        }
+    }
+    Ok(refused)
+}
+
+/// The files and options resolved from a profile
+pub struct Resolution {
+    pub options: OptionsOverrides,
+    /// The files to install, with every project appearing once
+    pub files: Vec<DownloadData>,
+    /// The files resolved from the sources of the profile and its imports, and their dependencies
+    pub nodes: Vec<Node>,
+    /// The sources that could not be resolved
+    pub errors: Vec<SourceError>,
+}
+
+/// Resolve the files of the profile using the lockfile state in `locking`, without installing them
+///
+/// Files that are exclusively for the other `side` are skipped.
+pub async fn resolve(
+    ctx: &Context,
+    // The path to the profile file or `None` if it is embedded
+    profile_path: Option<&Path>,
+    profile: &Profile,
+    side: Side,
+    filters: Filters,
+    locking: &mut Locking,
+) -> Result<Resolution> {
+    let src_path = profile_path
+        .map(|path| {
+            path.parent()
+                .context("Profile path should have a parent directory")
+        })
+        .transpose()?;
+
+    let mut resolution = Resolution {
+        options: OptionsOverrides::default(),
+        files: vec![],
+        nodes: vec![],
+        errors: vec![],
+    };
+    let mut graph = DependencyGraph::default();
+
+    resolution.errors = get_platform_downloadables(
+        ctx,
+        src_path,
+        &mut resolution,
+        &mut graph,
+        profile,
+        side,
+        filters,
+        locking,
+    )
+    .await?;
+
+    let resolved = graph.resolve()?;
+    for (name, kept) in &resolved.duplicates {
+        warn!("{name} is the same project as {kept}, only {kept} will be installed");
+    }
+    resolution
+        .files
+        .extend(resolved.nodes.iter().map(|node| node.file.clone()));
+    resolution.nodes = resolved.nodes;
+
+    Ok(resolution)
+}
+
+/// Print the changes an upgrade would make to the `minecraft_dir` without making them
+fn print_plan(
+    ctx: &Context,
+    minecraft_dir: &Path,
+    mut to_download: Vec<DownloadData>,
+    options: &OptionsOverrides,
+) -> Result<()> {
+    let mut up_to_date = vec![];
+    let mut to_move = vec![];
+    let mut to_delete = vec![];
+
+    for kind in SourceKind::ARRAY {
+        let Some(dirname) = kind.dirname() else {
+            continue;
+        };
+
+        let directory = minecraft_dir.join(dirname);
+        if !directory.exists() {
+            continue;
+        }
+
+        let plan = plan_clean(
+            &directory,
+            &mut to_download,
+            matches!(kind, SourceKind::Mods),
+        )?;
+        up_to_date.extend(plan.up_to_date);
+        to_move.extend(plan.to_move);
+        to_delete.extend(plan.to_delete);
+    }
+
+    let options_path = minecraft_dir.join("options.txt");
+    let old_options = if options_path.exists() {
+        Options::read(BufReader::new(File::open(&options_path)?), |err| {
+            warn!("read: {err}");
+        })?
+    } else {
+        Options::default()
+    };
+    let mut new_options = old_options.clone();
+    new_options.apply(options.clone(), |err| warn!("apply: {err}"));
+
+    status!(ctx, "\n{}", "Dry Run".bold());
+
+    if !to_download.is_empty() {
+        status!(ctx, "\n{}", "Would download".bold());
+        for downloadable in &to_download {
+            status!(
+                ctx,
+                "• {} {}",
+                downloadable.output.display(),
+                size::Size::from_bytes(downloadable.length)
+                    .format()
+                    .with_base(size::Base::Base10)
+                    .to_string()
+                    .dimmed(),
+            );
+        }
+    }
+
+    if !to_move.is_empty() {
+        status!(ctx, "\n{}", "Would move to .old".bold());
+        for path in &to_move {
+            status!(ctx, "• {}", path.display());
+        }
+    }
+
+    if !to_delete.is_empty() {
+        status!(ctx, "\n{}", "Would delete".bold());
+        for path in &to_delete {
+            status!(ctx, "• {}", path.display());
+        }
+    }
+
+    if !up_to_date.is_empty() {
+        status!(ctx, "\n{}", "Already up to date".bold());
+        for filename in &up_to_date {
+            status!(ctx, "• {}", filename.dimmed());
+        }
+    }
+
+    let options_diff = old_options.diff(&new_options);
+    if !options_diff.is_empty() {
+        status!(ctx, "\n{}", "Would change options.txt".bold());
+        for (key, old, new) in options_diff {
+            status!(
+                ctx,
+                "• {key}: {} → {}",
+                old.unwrap_or("none").dimmed(),
+                new.unwrap_or("none").green(),
+            );
+        }
+    }
+
+    Ok(())
+}
+
 /// Apply option overrides.
 pub fn apply_options_overrides(minecraft_dir: &Path, options: OptionsOverrides) -> Result<()> {
     let options_path = minecraft_dir.join("options.txt");
@@ -108,45 +507,62 @@ pub fn apply_options_overrides(minecraft_dir: &Path, options: OptionsOverrides)
 
 /// Get the latest compatible downloadable for the sources in `profile`
 ///
+/// Sources locked in `locking` are installed from the lockfile unless an update was requested for them.
+/// Files resolved from sources are added to `graph`, while overrides and options are added to `resolution` directly.
+///
+/// The `exclude-dependencies` of the profile and its imports are excluded in `graph`,
+/// those of imports are excluded before the profile's sources are resolved.
+///
 /// If an error occurs with a resolving task, instead of failing immediately,
-/// resolution will continue and the error return flag is set to true.
+/// resolution will continue and the error is returned with the others.
+#[expect(clippy::too_many_arguments)]
 async fn get_platform_downloadables(
+    ctx: &Context,
     src_path: Option<&Path>,
-    options: &mut OptionsOverrides,
-    to_download: &mut Vec<DownloadData>,
+    resolution: &mut Resolution,
+    graph: &mut DependencyGraph,
     profile: &Profile,
+    side: Side,
     filters: Filters,
-) -> Result<bool> {
+    locking: &mut Locking,
+) -> Result<Vec<SourceError>> {
     let filters = filters.concat(profile.filters.clone());
     check_unstrict_filter(&filters);
 
-    let mut error = false;
+    let mut errors = vec![];
 
-    options.join(&profile.options);
+    graph.exclude(excluded_dependencies(ctx, profile).await);
+
+    resolution.options.join(&profile.options);
 
     if let Some(src_path) = src_path {
         for import in &profile.imports {
-            let profile_path = import.download(src_path).await?;
-            let path = src_path.join(&profile_path);
+            // Imported paths are already relative to the importing profile
+            let path = import.download(src_path).await?;
             let Some(profile) = read_profile(&path)? else {
-                bail!("The profile at '{}' doesn't exist.", profile_path.display())
+                bail!("The profile at '{}' doesn't exist.", path.display())
             };
 
-            error |= Box::pin(get_platform_downloadables(
-                Some(
-                    path.parent()
-                        .context("Profile path should have a parent directory")?,
-                ),
-                options,
-                to_download,
-                &profile,
-                filters.clone(),
-            ))
-            .await?;
+            errors.extend(
+                Box::pin(get_platform_downloadables(
+                    ctx,
+                    Some(
+                        path.parent()
+                            .context("Profile path should have a parent directory")?,
+                    ),
+                    resolution,
+                    graph,
+                    &profile,
+                    side,
+                    filters.clone(),
+                    locking,
+                ))
+                .await?,
+            );
         }
 
         if let Some(overrides) = profile.overrides_path() {
-            read_overrides(to_download, &src_path.join(overrides))?;
+            read_overrides(&mut resolution.files, &src_path.join(overrides))?;
         }
 
         if let Some(files) = profile.overrides_files() {
@@ -155,13 +571,16 @@ async fn get_platform_downloadables(
                     continue;
                 }
 
-                to_download.push(DownloadData {
-                    src: DownloadSource::Contents(value.to_string()),
+                resolution.files.push(DownloadData {
+                    id: None,
+                    src: DownloadSource::Contents(value.clone()),
                     output: path.clone(),
                     length: value.len() as u64,
                     dependencies: vec![],
+                    optional_dependencies: vec![],
                     conflicts: vec![],
                     kind: None,
+                    side: Side::Both,
                     hash: None,
                     user_hash: vec![],
                 });
@@ -178,10 +597,27 @@ async fn get_platform_downloadables(
             continue;
         }
 
-        error |= get_source_downloadables(src_path, *kind, to_download, profile, &filters).await?;
+        errors.extend(
+            get_source_downloadables(
+                ctx, src_path, *kind, graph, profile, side, &filters, locking,
+            )
+            .await?,
+        );
     }
 
-    Ok(error)
+    Ok(errors)
+}
+
+/// Get the projects excluded by the `exclude-dependencies` of `profile`, warning about slugs that couldn't be looked up
+async fn excluded_dependencies(ctx: &Context, profile: &Profile) -> HashSet<ProjectKey> {
+    excluded_projects(
+        &ctx.download.cache,
+        &profile.exclude_dependencies,
+        |exclusion, err| {
+            warn!("could not look up the excluded dependency {exclusion}: {err}");
+        },
+    )
+    .await
 }
 
 fn sanitize_path(path: &Path) -> bool {
@@ -234,27 +670,70 @@ fn sanitize_path(path: &Path) -> bool {
     true
 }
 
+/// The outcome of resolving a single source
+struct SourceResolution {
+    name: String,
+    /// The lockfile entry for the source, `None` if the file cannot be locked
+    locked: Option<LockedFile>,
+    /// The resolved files, or the reason resolving failed
+    files: Result<Vec<Node>, SourceError>,
+}
+
+/// A source that could not be resolved
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SourceError {
+    pub kind: SourceKind,
+    pub name: String,
+    pub error: String,
+    /// The dependency the source was resolved for, `None` if it is one of the profile's sources
+    #[serde(skip)]
+    pub dependency: Option<SourceId>,
+    /// The `CurseForge` file that was resolved, if its developer denied third party downloads
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub distribution_denied: Option<SourceId>,
+}
+
+/// The machine-readable output of an upgrade
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct UpgradeOutput<'a> {
+    files: &'a [DownloadData],
+    errors: &'a [SourceError],
+    /// The files that were resolved but couldn't be downloaded
+    failed_downloads: &'a [DownloadError],
+}
+
+#[expect(clippy::too_many_arguments)]
 async fn get_source_downloadables(
+    ctx: &Context,
     src_path: Option<&Path>,
     kind: SourceKind,
-    to_download: &mut Vec<DownloadData>,
+    graph: &mut DependencyGraph,
     profile: &Profile,
+    side: Side,
     filters: &Filters,
-) -> Result<bool> {
+    locking: &mut Locking,
+) -> Result<Vec<SourceError>> {
     let progress_bar = Arc::new(Mutex::new(ProgressBar::new(0).with_style(STYLE_NO.clone())));
     let mut tasks = JoinSet::new();
     let mut done_sources = Vec::new();
+    // The projects that have been requested, so each dependency is only resolved once
+    let mut requested = HashSet::new();
     let client = reqwest::Client::new();
-    let (mod_sender, mod_rcvr) = mpsc::channel();
-
-    // Wrap it again in an Arc so that I can count the references to it,
-    // because I cannot drop the main thread's sender due to the recursion
-    let mod_sender = Arc::new(mod_sender);
-
-    println!("{}\n", "Determining the Latest Compatible Versions".bold());
+    let mut resolutions = Vec::new();
+    let (mod_sender, mut mod_rcvr) = mpsc::unbounded_channel();
+    let excluded = Arc::new(graph.excluded().clone());
+
+    status!(
+        ctx,
+        "{}\n",
+        "Determining the Latest Compatible Versions".bold()
+    );
     progress_bar
         .lock()
         .enable_steady_tick(Duration::from_millis(100));
+    let show_waits = show_rate_limit_waits(progress_bar.lock().clone());
     let sources = profile.map(kind);
     let pad_len = sources
         .keys()
@@ -263,134 +742,315 @@ async fn get_source_downloadables(
         .unwrap_or(20)
         .clamp(20, 50);
 
+    // The root is the name of the top level source that pulled in a dependency
     for (name, source) in sources {
-        mod_sender.send((name.to_owned(), source.clone()))?;
+        mod_sender.send((name.to_owned(), source.clone(), name.to_owned()))?;
     }
 
-    let mut initial = true;
-
-    // A race condition exists where if the last task drops its sender before this thread receives the message,
-    // that particular message will get ignored. I used the ostrich algorithm to solve this.
+    // Tasks send their dependencies before finishing, so once every task has finished
+    // only the messages left in the channel remain
+    loop {
+        let (name, source, root) = if tasks.is_empty() {
+            match mod_rcvr.try_recv() {
+                Ok(message) => message,
+                Err(_) => break,
+            }
+        } else {
+            tokio::select! {
+                message = mod_rcvr.recv() => match message {
+                    Some(message) => message,
+                    None => break,
+                },
+                Some(resolution) = tasks.join_next() => {
+                    resolutions.push(resolution??);
+                    continue;
+                }
+            }
+        };
 
-    // `initial` accounts for the edge case where at first,
-    // no tasks have been spawned yet but there are messages in the channel
-    while Arc::strong_count(&mod_sender) > 1 || initial {
-        if let Ok((name, source)) = mod_rcvr.try_recv() {
-            initial = false;
+        if done_sources.contains(&name) {
+            continue;
+        }
 
-            if done_sources.contains(&name) {
+        let mut ids = source.ids();
+        let project = match (ids.next(), ids.next()) {
+            (Some(id), None) => ProjectKey::of(id),
+            _ => None,
+        };
+        if let Some(project) = project {
+            // Always resolve the profile's own sources, duplicates are unified in the graph
+            if !requested.insert(project) && name != root {
                 continue;
             }
+        }
 
-            done_sources.push(name.clone());
-            progress_bar.lock().inc_length(1);
-
-            let filters = filters.clone();
-            let dep_sender = Arc::clone(&mod_sender);
-            let progress_bar = Arc::clone(&progress_bar);
-            let client = client.clone();
-            let src_path = src_path.map(ToOwned::to_owned);
-
-            tasks.spawn(async move {
-                let permit = SEMAPHORE.get_or_init(default_semaphore).acquire().await?;
-
+        done_sources.push(name.clone());
+        progress_bar.lock().inc_length(1);
+
+        let filters = filters.clone();
+        let mirrors = profile.mirrors.clone();
+        let dep_sender = mod_sender.clone();
+        let excluded = Arc::clone(&excluded);
+        let progress_bar = Arc::clone(&progress_bar);
+        let client = client.clone();
+        let ctx = ctx.clone();
+        let src_path = src_path.map(ToOwned::to_owned);
+        let checksum = lock::checksum(&source, &filters);
+        // Optional dependencies are only pulled in for the profile's own sources
+        let pull_optional = name == root
+            && source
+                .filters()
+                .and_then(|filters| filters.optional_dependencies)
+                .or(filters.optional_dependencies)
+                .unwrap_or(false);
+        // A dependency may have been locked under the name of another dependent
+        let locked = locking
+            .locked
+            .get(kind, &name)
+            .or_else(|| {
+                (name != root)
+                    .then(|| {
+                        locking
+                            .locked
+                            .map(kind)
+                            .values()
+                            .find(|locked| locked.checksum == checksum)
+                    })
+                    .flatten()
+            })
+            .cloned();
+        let update = locking.update.includes(kind, &root);
+        // Sources that are updated resolve the latest versions, not the cached lists of versions
+        let cache = Cache {
+            refresh: update,
+            ..ctx.download.cache.clone()
+        };
+        let use_locked = !update
+            && locked
+                .as_ref()
+                .is_some_and(|locked| locked.checksum == checksum);
+
+        tasks.spawn(async move {
+            let result = if let Some(locked) = locked.as_ref().filter(|_| use_locked) {
+                Ok((Some(locked.clone()), locked.to_download_data()))
+            } else {
+                let permits = ctx.acquire_permits(Host::of_source(&source)).await?;
                 let result = source
-                    .fetch_download_file(src_path.as_deref(), kind, vec![&filters])
+                    .fetch_download_file(&cache, src_path.as_deref(), kind, vec![&filters])
                     .await;
+                drop(permits);
+                result.map(|(metadata, download_file)| {
+                    let locked = LockedFile::new(checksum, &metadata, &download_file);
+                    (locked, download_file)
+                })
+            };
 
-                drop(permit);
-
-                progress_bar.lock().inc(1);
-                match result {
-                    Ok(mut download_file) => {
-                        progress_bar.lock().println(format!(
-                            "{} {name:pad_len$}  {}",
-                            TICK.clone(),
-                            download_file.filename().dimmed()
-                        ));
-                        for dep in take(&mut download_file.dependencies) {
-                            let id = format!(
-                                "Dependency of {name}: {}",
-                                match &dep {
-                                    SourceId::Curseforge(id) => id.to_string(),
-                                    SourceId::Modrinth(id) | SourceId::PinnedModrinth(id, _) =>
-                                        id.to_owned(),
-                                    _ => unreachable!(),
-                                }
-                            );
-                            let source = Source::from_id(dep, Filters::empty());
-                            dep_sender.send((id, source))?;
-                        }
-                        if let SourceKind::Modpacks = kind {
-                            let install_overrides = source
-                                .filters()
-                                .and_then(|filters| filters.install_overrides)
-                                .unwrap_or(true);
-
-                            let mut to_download = vec![];
-                            download_modpack(
-                                &mut to_download,
-                                client,
-                                download_file,
-                                install_overrides,
-                            )
-                            .await?;
-                            Ok(Some(to_download))
-                        } else {
-                            Ok(Some(vec![download_file]))
+            progress_bar.lock().inc(1);
+            match result {
+                Ok((locked, download_file)) if !side.includes(download_file.side) => {
+                    // The file is still locked, so it isn't resolved again for the other side
+                    progress_bar.lock().println(format!(
+                        "{} {name:pad_len$}  {}",
+                        "-".dimmed(),
+                        format!(
+                            "{} is only for the {} side",
+                            download_file.filename(),
+                            download_file.side
+                        )
+                        .dimmed()
+                    ));
+                    Ok(SourceResolution {
+                        name,
+                        locked,
+                        files: Ok(vec![]),
+                    })
+                }
+                Ok((locked, download_file)) => {
+                    progress_bar.lock().println(format!(
+                        "{} {name:pad_len$}  {}",
+                        TICK.clone(),
+                        download_file.filename().dimmed()
+                    ));
+                    let optional = if pull_optional {
+                        download_file.optional_dependencies.clone()
+                    } else {
+                        vec![]
+                    };
+                    for (dep, prefix) in download_file
+                        .dependencies
+                        .clone()
+                        .into_iter()
+                        .map(|dep| (dep, "Dependency"))
+                        .chain(optional.into_iter().map(|dep| (dep, "Optional dependency")))
+                    {
+                        if ProjectKey::of(&dep).is_some_and(|project| excluded.contains(&project)) {
+                            continue;
                         }
+                        let id = format!("{prefix} of {name}: {}", dependency_id(&dep));
+                        let source = Source::from_id(dep, Filters::empty());
+                        dep_sender.send((id, source, root.clone()))?;
                     }
-                    Err(err) => {
-                        if let mod_downloadable::Error::ModrinthError(
-                            ferinth::Error::RateLimitExceeded(_),
-                        ) = err
-                        {
-                            // Immediately fail if the rate limit has been exceeded
-                            progress_bar.lock().finish_and_clear();
-                            bail!(err);
-                        }
-                        progress_bar.lock().println(format!(
-                            "{}",
-                            format!("{CROSS} {name:pad_len$}  {err}").red()
-                        ));
-                        Ok(None)
+                    if let SourceKind::Modpacks = kind {
+                        let install_overrides = source
+                            .filters()
+                            .and_then(|filters| filters.install_overrides)
+                            .unwrap_or(true);
+
+                        let mut to_download = vec![];
+                        download_modpack(
+                            &ctx,
+                            &mut to_download,
+                            client,
+                            download_file,
+                            &mirrors,
+                            side,
+                            install_overrides,
+                        )
+                        .await?;
+                        let files = to_download
+                            .into_iter()
+                            .map(|file| Node {
+                                name: file.filename(),
+                                required_by: Some(name.clone()),
+                                file,
+                            })
+                            .collect();
+                        Ok(SourceResolution {
+                            name,
+                            locked,
+                            files: Ok(files),
+                        })
+                    } else {
+                        let required_by = (name != root).then_some(root);
+                        Ok(SourceResolution {
+                            name: name.clone(),
+                            locked,
+                            files: Ok(vec![Node {
+                                name,
+                                required_by,
+                                file: download_file,
+                            }]),
+                        })
                     }
                 }
-            });
-        }
+                Err(err) => {
+                    if let mod_downloadable::Error::ModrinthError(
+                        ferinth::Error::RateLimitExceeded(_),
+                    ) = err
+                    {
+                        // The rate limit is still exceeded after waiting for it to reset many times,
+                        // so every other source would fail too
+                        progress_bar.lock().finish_and_clear();
+                        bail!(err);
+                    }
+                    progress_bar.lock().println(format!(
+                        "{}",
+                        format!("{CROSS} {name:pad_len$}  {err}").red()
+                    ));
+                    let distribution_denied = match &err {
+                        mod_downloadable::Error::DistributionDenied(DistributionDeniedError(
+                            project,
+                            file,
+                        )) => Some(SourceId::PinnedCurseforge(*project, *file)),
+                        _ => None,
+                    };
+                    // Dependencies are resolved from a source of their single identifier
+                    let dependency = (name != root)
+                        .then(|| source.ids().next().cloned())
+                        .flatten();
+                    // Keep the previously locked file so a failed resolution doesn't drop it
+                    Ok(SourceResolution {
+                        name: name.clone(),
+                        locked,
+                        files: Err(SourceError {
+                            kind,
+                            name,
+                            error: err.to_string(),
+                            dependency,
+                            distribution_denied,
+                        }),
+                    })
+                }
+            }
+        });
     }
 
+    drop(show_waits);
     Arc::try_unwrap(progress_bar)
         .map_err(|_| anyhow!("Failed to run threads to completion"))?
         .into_inner()
         .finish_and_clear();
 
-    let tasks = tasks
-        .join_all()
-        .await
-        .into_iter()
-        .collect::<Result<Vec<_>>>()?;
-
-    let error = tasks.iter().any(Option::is_none);
-    for new_to_download in tasks.into_iter().flatten() {
-        for downloadable in new_to_download {
-            to_download.push(downloadable);
+    let mut errors = vec![];
+    for resolution in resolutions {
+        if let Some(locked) = resolution.locked {
+            locking
+                .resolved
+                .map_mut(kind)
+                .insert(resolution.name.clone(), locked);
         }
+        match resolution.files {
+            Ok(files) => graph.extend(files),
+            Err(error) => errors.push(error),
+        }
+    }
+
+    Ok(errors)
+}
+
+/// The identifier of the dependency `dep` that ends the names of the sources resolved for it,
+/// e.g. `Dependency of Iris: AANobbMI`
+fn dependency_id(dep: &SourceId) -> String {
+    match dep {
+        SourceId::Curseforge(id) | SourceId::PinnedCurseforge(id, _) => id.to_string(),
+        SourceId::Modrinth(id) | SourceId::PinnedModrinth(id, _) => id.to_owned(),
+        dep => dep.to_string(),
+    }
+}
+
+/// Aborts the task when dropped, including when returning early with an error
+struct AbortOnDrop(JoinHandle<()>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
     }
+}
 
-    Ok(error)
+/// Keep showing on `progress_bar` how long requests are waiting for a rate limit to reset,
+/// until the returned guard is dropped
+fn show_rate_limit_waits(progress_bar: ProgressBar) -> AbortOnDrop {
+    AbortOnDrop(tokio::spawn(async move {
+        loop {
+            progress_bar.set_message(rate_limit::waiting().map_or_else(
+                String::new,
+                |(host, wait)| {
+                    format!("{host} rate limit reached, waiting {}s", wait.as_secs() + 1)
+                        .yellow()
+                        .to_string()
+                },
+            ));
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }))
 }
 
 async fn download_modpack(
+    ctx: &Context,
     to_download: &mut Vec<DownloadData>,
     client: reqwest::Client,
-    downloadable: DownloadData,
+    mut downloadable: DownloadData,
+    mirrors: &BTreeMap<String, String>,
+    side: Side,
     install_overrides: bool,
 ) -> Result<()> {
+    downloadable.use_mirrors(mirrors);
     let tmp_dir = get_tmp_dir()?;
-    let (_size, filename) = downloadable.download(client, tmp_dir, |_| {}).await?;
+    let (_size, filename) = downloadable
+        .download(client, tmp_dir, &ctx.download, |_| {})
+        .await?;
     let path = tmp_dir.join(filename);
-    let res = download_modpack_inner(to_download, &path, install_overrides).await;
+    let res = download_modpack_inner(to_download, &path, side, install_overrides).await;
     fs::remove_file(path)?;
     res
 }
@@ -398,6 +1058,7 @@ async fn download_modpack(
 async fn download_modpack_inner(
     to_download: &mut Vec<DownloadData>,
     path: &PathBuf,
+    side: Side,
     install_overrides: bool,
 ) -> Result<()> {
     let Some(kind) = SourceKindWithModpack::infer(path)? else {
@@ -413,8 +1074,11 @@ async fn download_modpack_inner(
                     .context("Does not contain manifest")?,
             )?;
 
-            let file_ids = manifest.files.iter().map(|file| file.file_id).collect();
-            let files = CURSEFORGE_API.get_files(file_ids).await?;
+            let file_ids: Vec<_> = manifest.files.iter().map(|file| file.file_id).collect();
+            let files = rate_limit::send(Host::Curseforge, || {
+                CURSEFORGE_API.get_files(file_ids.clone())
+            })
+            .await?;
 
             let mut tasks = JoinSet::new();
             let mut msg_shown = false;
@@ -436,11 +1100,14 @@ async fn download_modpack_inner(
                     }
                     Err(DistributionDeniedError(mod_id, file_id)) => {
                         if !msg_shown {
-                            println!("\n{}", "The following mod(s) have denied 3rd parties such as Ferium from downloading it".red().bold());
+                            eprintln!("\n{}", "The following mod(s) have denied 3rd parties such as Ferium from downloading it".red().bold());
                         }
                         msg_shown = true;
                         tasks.spawn(async move {
-                            let project = CURSEFORGE_API.get_mod(mod_id).await?;
+                            let project = rate_limit::send(Host::Curseforge, || {
+                                CURSEFORGE_API.get_mod(mod_id)
+                            })
+                            .await?;
                             eprintln!(
                                 "- {}
                            \r  {}",
@@ -467,14 +1134,28 @@ async fn download_modpack_inner(
                     .context("Does not contain metadata file")?,
             )?;
 
-            for file in metadata.files {
-                to_download.push(from_modpack_file(file));
-            }
+            to_download.extend(
+                metadata
+                    .files
+                    .into_iter()
+                    .map(from_modpack_file)
+                    .filter(|file| side.includes(file.side)),
+            );
 
             if install_overrides {
                 let tmp_dir = get_tmp_dir()?.join(metadata.name);
                 zip_extract(path, &tmp_dir)?;
                 read_overrides(to_download, &tmp_dir.join("overrides"))?;
+                // The overrides for only one side are installed if this is that side
+                for (dir, dir_side) in [
+                    ("client-overrides", Side::Client),
+                    ("server-overrides", Side::Server),
+                ] {
+                    let dir = tmp_dir.join(dir);
+                    if side.includes(dir_side) && dir.is_dir() {
+                        read_overrides(to_download, &dir)?;
+                    }
+                }
             }
         }
         _ => bail!("That is not a modpack!"),
@@ -2,30 +2,84 @@
 
 use crate::{
     actual_main,
-    cli::{Ferium, FilterArguments, Platform, ProfileSubCommands, SubCommands},
-    subcommands::profile,
+    cli::{
+        ExportFormat, Ferium, FilterArguments, OutputFormat, Platform, ProfileSubCommands,
+        SubCommands,
+    },
+    run, scanned_source,
+    subcommands::{
+        export, profile,
+        tui::{fit, with_id, App},
+    },
+    Context, JsonDocument,
 };
-use libium::config::{
-    read_config,
-    structs::{ModLoader, ProfileSource, Version},
-    write_config,
+use libium::{
+    cache::{Cache, NotCached, PROJECT_TTL},
+    config::{
+        lock::{checksum, lockfile_path, LockedFile, LockedHashes, Lockfile},
+        modpack::{
+            curseforge,
+            modrinth::{self, DependencyID},
+            read_file_from_zip, write_zip, ZipEntry,
+        },
+        read_config, read_lockfile, read_profile,
+        structs::{
+            ExcludedDependency, Filters, ModLoader, ProfileImport, ProfileImportSource,
+            ProfileSource, Side, Source, SourceId, Version,
+        },
+        write_config, write_lockfile, write_profile,
+    },
+    upgrade::{
+        calculate_sha1, calculate_sha512,
+        resolve::{ConflictError, ProjectKey},
+    },
 };
 use std::{
-    assert_matches::assert_matches,
+    assert_matches,
+    collections::HashMap,
     env::current_dir,
-    fs::{copy, create_dir_all},
-    path::PathBuf,
+    fs::{copy, create_dir_all, metadata, read, read_dir, read_to_string, remove_dir_all, File},
+    io::{BufRead as _, BufReader, Write as _},
+    net::TcpListener,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
 };
 
 const DEFAULT: Ferium = Ferium {
     subcommand: SubCommands::Profile { subcommand: None },
     threads: None,
     parallel_tasks: 10,
+    retries: 3,
+    max_bandwidth: None,
     github_token: None,
     curseforge_api_key: None,
     config_file: None,
+    cache_dir: None,
+    output: OutputFormat::Text,
+    offline: false,
 };
 
+/// Run `args` with JSON output, returning the result and the JSON document it printed
+async fn run_json(args: Ferium) -> (anyhow::Result<()>, serde_json::Value) {
+    let document = JsonDocument::default();
+    let result = run(
+        Ferium {
+            output: OutputFormat::Json,
+            ..args
+        },
+        &document,
+    )
+    .await;
+    let document = document
+        .lock()
+        .take()
+        .unwrap_or_else(|| panic!("No JSON document was printed: {result:?}"));
+    // Check that it is printed as a single JSON document
+    let document = serde_json::from_str(&document.to_string()).unwrap();
+    (result, document)
+}
+
 fn get_args(subcommand: SubCommands, config_file: Option<&str>) -> Ferium {
     let running = get_running();
 
@@ -46,11 +100,220 @@ fn get_args(subcommand: SubCommands, config_file: Option<&str>) -> Ferium {
     }
     Ferium {
         subcommand,
+        // Every test has its own cache, so that they don't share cached responses and files
+        cache_dir: Some(running.with_extension("cache")),
         config_file: Some(running),
         ..DEFAULT
     }
 }
 
+/// The projects every mod locked by [`lock_mods`] depends on or conflicts with, as Modrinth project IDs
+#[derive(Default)]
+struct Relations<'a> {
+    dependencies: &'a [&'a str],
+    optional_dependencies: &'a [&'a str],
+    conflicts: &'a [&'a str],
+}
+
+/// Lock every mod of the profiles in `args` to a file of its Modrinth project,
+/// related to the projects in `relations` other than itself.
+/// The files get fake hashes unique to each mod.
+fn lock_mods(args: &Ferium, relations: &Relations) {
+    let config = read_config(args.config_file.as_ref().unwrap()).unwrap();
+    for item in &config.profiles {
+        let ProfileSource::Path(path) = &item.profile else {
+            continue;
+        };
+        let profile = read_profile(path).unwrap().unwrap();
+        let filters = Filters::from(FilterArguments::default()).concat(profile.filters.clone());
+
+        let mut lockfile = Lockfile::default();
+        for (name, source) in &profile.mods {
+            let Some(SourceId::Modrinth(project)) = source.ids().next() else {
+                continue;
+            };
+            let filename = format!("{name}.jar");
+            let checksum = checksum(source, &filters);
+            lockfile.mods.insert(
+                name.clone(),
+                LockedFile {
+                    hashes: LockedHashes {
+                        sha1: Some(checksum.clone()),
+                        sha512: Some(checksum.repeat(4)[..128].to_owned()),
+                        md5: None,
+                    },
+                    checksum,
+                    id: Some(SourceId::PinnedModrinth(
+                        project.clone(),
+                        "AAAAAAAA".to_owned(),
+                    )),
+                    title: name.clone(),
+                    url: format!(
+                        "https://cdn.modrinth.com/data/{project}/versions/AAAAAAAA/{filename}"
+                    )
+                    .parse()
+                    .unwrap(),
+                    fallback_urls: vec![],
+                    output: Path::new("mods").join(filename),
+                    length: 0,
+                    user_hashes: vec![],
+                    dependencies: relations
+                        .dependencies
+                        .iter()
+                        .filter(|id| *id != project)
+                        .map(|id| SourceId::Modrinth((*id).to_owned()))
+                        .collect(),
+                    optional_dependencies: relations
+                        .optional_dependencies
+                        .iter()
+                        .filter(|id| *id != project)
+                        .map(|id| SourceId::Modrinth((*id).to_owned()))
+                        .collect(),
+                    conflicts: relations
+                        .conflicts
+                        .iter()
+                        .filter(|id| *id != project)
+                        .map(|id| SourceId::Modrinth((*id).to_owned()))
+                        .collect(),
+                    kind: None,
+                    side: Side::Both,
+                },
+            );
+        }
+        write_lockfile(lockfile_path(path), &lockfile).unwrap();
+    }
+}
+
+/// Mark every locked mod of the profiles in `args` as exclusively for `side`
+fn lock_side(args: &Ferium, side: Side) {
+    let config = read_config(args.config_file.as_ref().unwrap()).unwrap();
+    for item in &config.profiles {
+        let ProfileSource::Path(path) = &item.profile else {
+            continue;
+        };
+        let mut lockfile = read_lockfile(lockfile_path(path)).unwrap();
+        for locked in lockfile.mods.values_mut() {
+            locked.side = side;
+        }
+        write_lockfile(lockfile_path(path), &lockfile).unwrap();
+    }
+}
+
+/// Get the arguments to run `subcommand` on a copy of the `config_file` config with its mods locked by [`lock_mods`],
+/// the first profile installing to a new minecraft directory
+///
+/// Returns the arguments, the path to the first profile, and its minecraft directory.
+fn get_locked_args(
+    subcommand: SubCommands,
+    config_file: &str,
+    relations: &Relations,
+) -> (Ferium, PathBuf, PathBuf) {
+    let args = get_args(subcommand, Some(config_file));
+    lock_mods(&args, relations);
+
+    let minecraft_dir = get_running().with_extension("");
+    let config_file = args.config_file.as_ref().unwrap();
+    let mut config = read_config(config_file).unwrap();
+    config.profiles[0]
+        .config
+        .minecraft_dir
+        .clone_from(&minecraft_dir);
+    write_config(config_file, &config).unwrap();
+    let ProfileSource::Path(profile_path) = &config.profiles[0].profile else {
+        unreachable!()
+    };
+
+    (args, profile_path.clone(), minecraft_dir)
+}
+
+/// Serve the `routes` from a local HTTP server, returning its base URL
+///
+/// Paths without a route are answered with a 404.
+fn serve(routes: HashMap<String, Vec<u8>>) -> String {
+    serve_dropping(routes, 0)
+}
+
+/// Like [`serve`], but drop the connection halfway through the first `drops` responses of every route
+///
+/// A `Range: bytes=<start>-` header is answered with the rest of the body from `start`.
+fn serve_dropping(routes: HashMap<String, Vec<u8>>, drops: usize) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+    thread::spawn(move || {
+        let mut dropped = HashMap::<String, usize>::new();
+        for mut stream in listener.incoming().map_while(Result::ok) {
+            let mut lines = BufReader::new(&stream).lines().map_while(Result::ok);
+            let request = lines.next().unwrap_or_default();
+            let mut start = 0;
+            for header in lines.by_ref().take_while(|line| !line.is_empty()) {
+                if let Some(range) = header.to_ascii_lowercase().strip_prefix("range: bytes=") {
+                    start = range.trim_end_matches('-').parse().unwrap_or_default();
+                }
+            }
+            let path = request.split(' ').nth(1).unwrap_or_default();
+
+            let (status, body) = match routes.get(path) {
+                Some(body) if start > 0 => ("206 Partial Content", &body[start.min(body.len())..]),
+                Some(body) => ("200 OK", body.as_slice()),
+                None => ("404 Not Found", [].as_slice()),
+            };
+            let _ = write!(
+                stream,
+                "HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len()
+            );
+            let times_dropped = dropped.entry(path.to_owned()).or_default();
+            if *times_dropped < drops {
+                *times_dropped += 1;
+                let _ = stream.write_all(&body[..body.len() / 2]);
+            } else {
+                let _ = stream.write_all(body);
+            }
+        }
+    });
+    base
+}
+
+/// Lock every mod of the profile at `profile_path` to a jar served from a local server
+/// that drops the first `drops` connections for each jar
+///
+/// Each jar contains the file and contents `entry` returns for the mod's name.
+/// Returns the new lockfile.
+fn lock_served_jars(
+    profile_path: &Path,
+    drops: usize,
+    entry: impl Fn(&str) -> (&'static str, String),
+) -> Lockfile {
+    let mut lockfile = read_lockfile(lockfile_path(profile_path)).unwrap();
+    let mut routes = HashMap::new();
+    let mut hashes = HashMap::new();
+    for name in lockfile.mods.keys() {
+        let jar = get_running().with_extension("jar");
+        let (path, contents) = entry(name);
+        write_zip(
+            File::create(&jar).unwrap(),
+            [(Path::new(path), ZipEntry::Contents(contents.as_bytes()))],
+        )
+        .unwrap();
+        routes.insert(format!("/{name}.jar"), read(&jar).unwrap());
+        hashes.insert(
+            name.clone(),
+            LockedHashes {
+                sha1: Some(calculate_sha1(&jar).unwrap()),
+                sha512: Some(calculate_sha512(&jar).unwrap()),
+                md5: None,
+            },
+        );
+    }
+    let base = serve_dropping(routes, drops);
+    for (name, locked) in &mut lockfile.mods {
+        locked.url = format!("{base}/{name}.jar").parse().unwrap();
+        locked.hashes = hashes.remove(name).unwrap();
+    }
+    write_lockfile(lockfile_path(profile_path), &lockfile).unwrap();
+    lockfile
+}
+
 fn get_running() -> PathBuf {
     let running_dir = PathBuf::from("./tests/configs/running");
     let _ = create_dir_all(&running_dir);
@@ -79,6 +342,8 @@ async fn create_profile_no_profiles_to_import() {
                         )
                     ),
                     embed: false,
+                    side: None,
+                    from_modpack: None,
                 }))
             },
             None,
@@ -106,6 +371,8 @@ async fn create_profile_rel_dir() {
                             .join("tests/configs/running/create_profile_rel_dir.toml")
                     ),
                     embed: false,
+                    side: None,
+                    from_modpack: None,
                 }))
             },
             None,
@@ -133,6 +400,8 @@ async fn create_profile_import_mods() {
                             .join("tests/configs/running/create_profile_import_mods.toml")
                     ),
                     embed: false,
+                    side: None,
+                    from_modpack: None,
                 }))
             },
             Some("one_profile_full"),
@@ -159,6 +428,8 @@ async fn create_profile_existing_name() {
                             .join("tests/configs/running/create_profile_existing_name.toml")
                     ),
                     embed: false,
+                    side: None,
+                    from_modpack: None,
                 }))
             },
             None,
@@ -168,6 +439,105 @@ async fn create_profile_existing_name() {
     );
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn create_profile_from_modpack() {
+    let modpack = get_running().with_extension("mrpack");
+    let index = r#"{
+        "formatVersion": 1,
+        "game": "minecraft",
+        "versionId": "1.0.0",
+        "name": "Modpack",
+        "files": [
+            {
+                "path": "mods/sodium.jar",
+                "hashes": { "sha1": "a", "sha512": "b" },
+                "downloads": ["https://cdn.modrinth.com/data/AANobbMI/versions/u1OEbNKx/sodium.jar"],
+                "fileSize": 0
+            },
+            {
+                "path": "resourcepacks/pack.zip",
+                "hashes": { "sha1": "c", "sha512": "d" },
+                "downloads": ["https://example.com/pack.zip"],
+                "fileSize": 0
+            }
+        ],
+        "dependencies": { "minecraft": "1.21.1", "fabric-loader": "0.16.5" }
+    }"#;
+    write_zip(
+        File::create(&modpack).unwrap(),
+        [
+            (
+                Path::new("modrinth.index.json"),
+                ZipEntry::Contents(index.as_bytes()),
+            ),
+            (
+                Path::new("overrides/config/sodium.json"),
+                ZipEntry::Contents(b"{}"),
+            ),
+            (
+                Path::new("client-overrides/options.txt"),
+                ZipEntry::Contents(b"fov:90"),
+            ),
+            (
+                Path::new("server-overrides/server.properties"),
+                ZipEntry::Contents(b"motd=Modpack"),
+            ),
+        ],
+    )
+    .unwrap();
+
+    let profile_path = get_running();
+    assert_matches!(
+        actual_main(get_args(
+            SubCommands::Profile {
+                subcommand: Some(ProfileSubCommands::Create(profile::create::Args {
+                    import: None,
+                    game_versions: None,
+                    mod_loader: None,
+                    name: None,
+                    minecraft_dir: Some(current_dir().unwrap().join("tests").join(".minecraft")),
+                    profile_path: Some(profile_path.clone()),
+                    embed: false,
+                    side: Some(Side::Client),
+                    from_modpack: Some(modpack.display().to_string()),
+                }))
+            },
+            None,
+        ))
+        .await,
+        Ok(()),
+    );
+
+    let profile = read_profile(&profile_path).unwrap().unwrap();
+    // Named after the project, or after the file when it can't be looked up
+    assert_matches!(
+        profile.mods.iter().next(),
+        Some((name, Source::Single(SourceId::PinnedModrinth(project, version))))
+            if ["Sodium", "sodium"].contains(&name.as_str())
+                && project == "AANobbMI"
+                && version == "u1OEbNKx"
+    );
+    assert_matches!(&profile.resourcepacks["pack"], Source::Detailed { .. });
+    assert!(profile.filters.versions.as_ref().unwrap()[0].matches("1.21.1"));
+    assert_eq!(
+        profile
+            .loader_version
+            .as_ref()
+            .and_then(Version::exact)
+            .as_deref(),
+        Some("0.16.5")
+    );
+    let overrides = profile_path
+        .parent()
+        .unwrap()
+        .join(profile.overrides_path().unwrap());
+    assert!(overrides.join("config").join("sodium.json").exists());
+    // Only the overrides for the profile's side are extracted
+    assert_eq!(profile.side, Side::Client);
+    assert!(overrides.join("options.txt").exists());
+    assert!(!overrides.join("server.properties").exists());
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn create_profile() {
     assert_matches!(
@@ -185,6 +555,8 @@ async fn create_profile() {
                             .join("tests/configs/running/create_profile.toml")
                     ),
                     embed: false,
+                    side: None,
+                    from_modpack: None,
                 }))
             },
             None,
@@ -194,6 +566,7 @@ async fn create_profile() {
     );
 }
 
+#[expect(clippy::expect_used, clippy::unnecessary_wraps)]
 fn game_version_from_str(version: &str) -> Option<Vec<Version>> {
     Some(vec![version.parse().expect("malformed version str")])
 }
@@ -291,6 +664,48 @@ async fn already_added() {
     );
 }
 
+/// Website URLs that can be recognised without looking anything up
+#[tokio::test(flavor = "multi_thread")]
+async fn parse_website_urls() {
+    // Offline, so that nothing can be looked up
+    let cache = Cache {
+        dir: get_running().with_extension("cache"),
+        offline: true,
+        refresh: false,
+    };
+    let parse = |id: &str| libium::add::parse_id(&cache, id.to_owned());
+
+    assert_matches!(
+        parse("https://modrinth.com/mod/sodium").await,
+        Ok(SourceId::Modrinth(id)) if id == "sodium"
+    );
+    assert_matches!(
+        parse("https://cdn.modrinth.com/data/AANobbMI/versions/u1OEbNKx/sodium.jar").await,
+        Ok(SourceId::PinnedModrinth(id, pin)) if id == "AANobbMI" && pin == "u1OEbNKx"
+    );
+    assert_matches!(
+        parse("https://www.curseforge.com/projects/591388").await,
+        Ok(SourceId::Curseforge(591_388))
+    );
+    assert_matches!(
+        parse("https://github.com/CaffeineMC/sodium.git").await,
+        Ok(SourceId::Github(owner, repo)) if owner == "CaffeineMC" && repo == "sodium"
+    );
+    assert_matches!(
+        parse("https://api.github.com/repos/CaffeineMC/sodium/releases/assets/123").await,
+        Ok(SourceId::PinnedGithub((owner, repo), 123)) if owner == "CaffeineMC" && repo == "sodium"
+    );
+    assert_matches!(
+        parse("https://example.com/mod.jar").await,
+        Ok(SourceId::Url(_))
+    );
+    // The file ID would overflow, so it is downloaded as it is
+    assert_matches!(
+        parse("https://mediafilez.forgecdn.net/files/9999999/999/mod.jar").await,
+        Ok(SourceId::Url(_))
+    );
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn scan() {
     assert_matches!(
@@ -299,6 +714,7 @@ async fn scan() {
                 platform: Platform::default(),
                 mods_dir: Some(current_dir().unwrap().join("tests").join("test_mods")),
                 force: false,
+                pin: false,
                 minecraft_dir: None,
                 resourcepacks_dir: None,
                 shaderpacks_dir: None,
@@ -310,13 +726,75 @@ async fn scan() {
     );
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn scan_pin() {
+    for platform in [Platform::Modrinth, Platform::Curseforge] {
+        let args = get_args(
+            SubCommands::Scan {
+                platform,
+                mods_dir: Some(current_dir().unwrap().join("tests").join("test_mods")),
+                force: false,
+                pin: true,
+                minecraft_dir: None,
+                resourcepacks_dir: None,
+                shaderpacks_dir: None,
+            },
+            Some("empty_profile"),
+        );
+        let config_file = args.config_file.clone().unwrap();
+        assert_matches!(actual_main(args).await, Ok(()));
+
+        let config = read_config(config_file).unwrap();
+        let ProfileSource::Path(path) = &config.profiles[0].profile else {
+            unreachable!()
+        };
+        let profile = read_profile(path).unwrap().unwrap();
+        assert!(!profile.mods.is_empty());
+        // Files only on the other platform are pinned to that platform's version
+        for source in profile.mods.values() {
+            assert_matches!(
+                source,
+                Source::Single(SourceId::PinnedModrinth(..) | SourceId::PinnedCurseforge(..))
+            );
+        }
+    }
+}
+
+#[test]
+fn scan_pin_sources() {
+    let modrinth = || Some(("AANobbMI".to_owned(), "AAAAAAAA".to_owned()));
+    let curseforge = Some((394_468, 5_000_000));
+
+    assert_matches!(
+        scanned_source(Platform::Modrinth, true, modrinth(), curseforge),
+        Some(SourceId::PinnedModrinth(project, version))
+            if project == "AANobbMI" && version == "AAAAAAAA"
+    );
+    assert_matches!(
+        scanned_source(Platform::Curseforge, true, modrinth(), curseforge),
+        Some(SourceId::PinnedCurseforge(394_468, 5_000_000))
+    );
+    // Files only on the other platform are still added
+    assert_matches!(
+        scanned_source(Platform::Curseforge, true, modrinth(), None),
+        Some(SourceId::PinnedModrinth(..))
+    );
+    assert_matches!(
+        scanned_source(Platform::Modrinth, false, modrinth(), curseforge),
+        Some(SourceId::Modrinth(project)) if project == "AANobbMI"
+    );
+    assert_matches!(scanned_source(Platform::Modrinth, true, None, None), None);
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn list_no_profile() {
     assert_matches!(
         actual_main(get_args(
             SubCommands::List {
                 verbose: false,
-                markdown: false
+                markdown: false,
+                tree: false,
+                optional: false,
             },
             Some("empty"),
         ))
@@ -331,7 +809,9 @@ async fn list_empty_profile() {
         actual_main(get_args(
             SubCommands::List {
                 verbose: false,
-                markdown: false
+                markdown: false,
+                tree: false,
+                optional: false,
             },
             Some("empty_profile"),
         ))
@@ -346,7 +826,9 @@ async fn list() {
         actual_main(get_args(
             SubCommands::List {
                 verbose: false,
-                markdown: false
+                markdown: false,
+                tree: false,
+                optional: false,
             },
             Some("one_profile_full"),
         ))
@@ -355,13 +837,40 @@ async fn list() {
     );
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn concurrency_zero() {
+    let args = get_args(
+        SubCommands::List {
+            verbose: false,
+            markdown: false,
+            tree: false,
+            optional: false,
+        },
+        Some("one_profile_full"),
+    );
+    let config_path = args.config_file.as_ref().unwrap();
+    let config = read_to_string(config_path).unwrap();
+    std::fs::write(
+        config_path,
+        format!("{config}\n[concurrency]\ngithub = 0\n"),
+    )
+    .unwrap();
+
+    assert_matches!(
+        actual_main(args).await,
+        Err(err) if format!("{err:#}").contains("nonzero"),
+    );
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn list_verbose() {
     assert_matches!(
         actual_main(get_args(
             SubCommands::List {
                 verbose: true,
-                markdown: false
+                markdown: false,
+                tree: false,
+                optional: false,
             },
             Some("one_profile_full"),
         ))
@@ -376,7 +885,9 @@ async fn list_markdown() {
         actual_main(get_args(
             SubCommands::List {
                 verbose: true,
-                markdown: true
+                markdown: true,
+                tree: false,
+                optional: false,
             },
             Some("one_profile_full"),
         ))
@@ -385,6 +896,85 @@ async fn list_markdown() {
     );
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn list_tree() {
+    let (args, ..) = get_locked_args(
+        SubCommands::List {
+            verbose: false,
+            markdown: false,
+            tree: true,
+            optional: false,
+        },
+        "locked_profile",
+        &Relations {
+            dependencies: &["AANobbMI"],
+            ..Relations::default()
+        },
+    );
+    assert_matches!(actual_main(args).await, Ok(()));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn list_tree_imports() {
+    let (args, profile_path, _) = get_locked_args(
+        SubCommands::List {
+            verbose: false,
+            markdown: false,
+            tree: true,
+            optional: false,
+        },
+        "locked_profile",
+        &Relations {
+            dependencies: &["AANobbMI"],
+            ..Relations::default()
+        },
+    );
+
+    // The mods are moved to an imported profile, but are still locked by the importing profile
+    let mut profile = read_profile(&profile_path).unwrap().unwrap();
+    let imported = get_running();
+    write_profile(&imported, &profile).unwrap();
+    profile.mods.clear();
+    profile.imports = vec![ProfileImport::Short(ProfileImportSource::Path(
+        imported.file_name().unwrap().into(),
+    ))];
+    write_profile(&profile_path, &profile).unwrap();
+
+    let (result, document) = run_json(args).await;
+    assert_matches!(result, Ok(()));
+    assert_eq!(
+        document,
+        serde_json::json!([
+            {
+                "name": "Iris",
+                "filename": "Iris.jar",
+                "dependencies": [
+                    { "name": "Sodium", "filename": "Sodium.jar", "declared-as": "Sodium" },
+                ],
+            },
+            { "name": "Sodium", "filename": "Sodium.jar" },
+        ])
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn list_optional() {
+    let (args, ..) = get_locked_args(
+        SubCommands::List {
+            verbose: false,
+            markdown: false,
+            tree: false,
+            optional: true,
+        },
+        "locked_profile",
+        &Relations {
+            optional_dependencies: &["AANobbMI", "P7dR8mSH"],
+            ..Relations::default()
+        },
+    );
+    assert_matches!(actual_main(args).await, Ok(()));
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn list_profiles() {
     assert_matches!(
@@ -397,13 +987,109 @@ async fn list_profiles() {
     );
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn list_json() {
+    let (result, document) = run_json(get_args(
+        SubCommands::List {
+            verbose: false,
+            markdown: false,
+            tree: false,
+            optional: false,
+        },
+        Some("one_profile_full"),
+    ))
+    .await;
+    assert_matches!(result, Ok(()));
+    assert_eq!(document["name"], "Default Modded");
+    assert_eq!(
+        document["sources"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|source| (
+                source["kind"].as_str().unwrap(),
+                source["name"].as_str().unwrap()
+            ))
+            .collect::<Vec<_>>(),
+        [
+            ("mods", "Incendium"),
+            ("mods", "sodium"),
+            ("mods", "Starlight (Fabric)"),
+        ],
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn list_profiles_json() {
+    let (result, document) = run_json(get_args(
+        SubCommands::Profiles,
+        Some("two_profiles_one_empty"),
+    ))
+    .await;
+    assert_matches!(result, Ok(()));
+    assert_eq!(
+        document
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|info| (
+                info["name"].as_str().unwrap(),
+                info["active"].as_bool().unwrap(),
+                info["sources"].as_u64().unwrap(),
+            ))
+            .collect::<Vec<_>>(),
+        [("Profile One", true, 3), ("Profile Two", false, 0)],
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn remove_json() {
+    let (result, document) = run_json(get_args(
+        SubCommands::Remove {
+            mod_names: vec!["incendium".to_owned()],
+        },
+        Some("two_profiles_one_empty"),
+    ))
+    .await;
+    assert_matches!(result, Ok(()));
+    assert_eq!(document, serde_json::json!({ "removed": ["Incendium"] }));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn upgrade_json_failed_download() {
+    let (args, profile_path, _) = get_locked_args(
+        SubCommands::Upgrade {
+            filters: FilterArguments::default(),
+            update: None,
+            dry_run: false,
+            install_loader: false,
+        },
+        "locked_profile",
+        &Relations::default(),
+    );
+    let server = serve(HashMap::new());
+    let mut lockfile = read_lockfile(lockfile_path(&profile_path)).unwrap();
+    for locked in lockfile.mods.values_mut() {
+        locked.url = format!("{server}/missing.jar").parse().unwrap();
+    }
+    write_lockfile(lockfile_path(&profile_path), &lockfile).unwrap();
+
+    let (result, document) = run_json(Ferium { retries: 0, ..args }).await;
+    assert_matches!(result, Err(err) if err.to_string().contains("Could not download"));
+    assert_eq!(document["files"].as_array().unwrap().len(), 2);
+    assert_eq!(document["failed-downloads"].as_array().unwrap().len(), 2);
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn upgrade() {
     create_dir_all("./tests/.minecraft").unwrap();
     assert_matches!(
         actual_main(get_args(
             SubCommands::Upgrade {
-                filters: Default::default()
+                filters: FilterArguments::default(),
+                update: None,
+                dry_run: false,
+                install_loader: false,
             },
             Some("one_profile_full")
         ))
@@ -412,6 +1098,684 @@ async fn upgrade() {
     );
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn upgrade_update_all() {
+    create_dir_all("./tests/.minecraft").unwrap();
+    assert_matches!(
+        actual_main(get_args(
+            SubCommands::Upgrade {
+                filters: FilterArguments::default(),
+                update: Some(vec![]),
+                dry_run: false,
+                install_loader: false,
+            },
+            Some("one_profile_full")
+        ))
+        .await,
+        Ok(()),
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn upgrade_dry_run() {
+    let (args, _, minecraft_dir) = get_locked_args(
+        SubCommands::Upgrade {
+            filters: FilterArguments::default(),
+            update: None,
+            dry_run: true,
+            install_loader: false,
+        },
+        "local_profile",
+        &Relations::default(),
+    );
+    let mods_dir = minecraft_dir.join("mods");
+    create_dir_all(&mods_dir).unwrap();
+    File::create(mods_dir.join("Old.jar")).unwrap();
+    File::create(mods_dir.join("Old.part")).unwrap();
+
+    assert_matches!(actual_main(args).await, Ok(()));
+
+    // Nothing is downloaded, moved, deleted, or written
+    let mut entries = read_dir(&mods_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect::<Vec<_>>();
+    entries.sort();
+    assert_eq!(entries, ["Old.jar", "Old.part"]);
+    assert!(!minecraft_dir.join("options.txt").exists());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn upgrade_locked_dry_run() {
+    let (args, ..) = get_locked_args(
+        SubCommands::Upgrade {
+            filters: FilterArguments::default(),
+            update: None,
+            dry_run: true,
+            install_loader: false,
+        },
+        "locked_profile",
+        &Relations::default(),
+    );
+    assert_matches!(actual_main(args).await, Ok(()));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn upgrade_skips_other_side() {
+    let (args, _, minecraft_dir) = get_locked_args(
+        SubCommands::Upgrade {
+            filters: FilterArguments::default(),
+            update: None,
+            dry_run: false,
+            install_loader: false,
+        },
+        "locked_profile",
+        &Relations::default(),
+    );
+    lock_side(&args, Side::Client);
+
+    create_dir_all(&minecraft_dir).unwrap();
+    let config_file = args.config_file.as_ref().unwrap();
+    let mut config = read_config(config_file).unwrap();
+    config.profiles[0].config.side = Some(Side::Server);
+    write_config(config_file, &config).unwrap();
+
+    // Every mod is only for the client, so nothing is downloaded
+    assert_matches!(actual_main(args).await, Ok(()));
+    assert!(!minecraft_dir.join("mods").exists());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn export_mrpack() {
+    let output = get_running().with_extension("mrpack");
+    let (args, ..) = get_locked_args(
+        SubCommands::Export(export::Args {
+            format: ExportFormat::Mrpack,
+            path: Some(output.clone()),
+            pack_version: "1.0.0".to_owned(),
+            loader_version: Some("0.16.5".to_owned()),
+            author: None,
+        }),
+        "locked_profile",
+        &Relations::default(),
+    );
+    assert_matches!(actual_main(args).await, Ok(()));
+
+    let index = read_file_from_zip(File::open(output).unwrap(), "modrinth.index.json")
+        .unwrap()
+        .unwrap();
+    let metadata: modrinth::Metadata = serde_json::from_str(&index).unwrap();
+    assert_eq!(metadata.files.len(), 2);
+    assert_eq!(metadata.dependencies[&DependencyID::Minecraft], "1.21.1");
+    assert_eq!(metadata.dependencies[&DependencyID::FabricLoader], "0.16.5");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn export_mrpack_env() {
+    let output = get_running().with_extension("mrpack");
+    let (args, ..) = get_locked_args(
+        SubCommands::Export(export::Args {
+            format: ExportFormat::Mrpack,
+            path: Some(output.clone()),
+            pack_version: "1.0.0".to_owned(),
+            loader_version: Some("0.16.5".to_owned()),
+            author: None,
+        }),
+        "locked_profile",
+        &Relations::default(),
+    );
+    lock_side(&args, Side::Server);
+    assert_matches!(actual_main(args).await, Ok(()));
+
+    let index = read_file_from_zip(File::open(output).unwrap(), "modrinth.index.json")
+        .unwrap()
+        .unwrap();
+    let metadata: modrinth::Metadata = serde_json::from_str(&index).unwrap();
+    assert_eq!(metadata.files.len(), 2);
+    for file in metadata.files {
+        let env = file.env.unwrap();
+        assert_eq!(Side::from_support(&env.client, &env.server), Side::Server);
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn export_curseforge() {
+    let output = get_running().with_extension("zip");
+    assert_matches!(
+        actual_main(get_args(
+            SubCommands::Export(export::Args {
+                format: ExportFormat::Curseforge,
+                path: Some(output.clone()),
+                pack_version: "1.0.0".to_owned(),
+                loader_version: Some("0.16.5".to_owned()),
+                author: None,
+            }),
+            Some("local_profile"),
+        ))
+        .await,
+        Ok(())
+    );
+
+    let manifest = read_file_from_zip(File::open(&output).unwrap(), "manifest.json")
+        .unwrap()
+        .unwrap();
+    let manifest: curseforge::Manifest = serde_json::from_str(&manifest).unwrap();
+    assert_eq!(manifest.minecraft.version, "1.18.2");
+    assert_eq!(manifest.minecraft.mod_loaders[0].id, "fabric-0.16.5");
+    assert!(manifest.files.is_empty());
+    let options = read_file_from_zip(File::open(&output).unwrap(), "overrides/options.txt")
+        .unwrap()
+        .unwrap();
+    assert!(options.contains("fov:90"));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn upgrade_install_loader() {
+    let meta = serve(HashMap::from([
+        (
+            "/versions/loader/1.18.2".to_owned(),
+            br#"[{"loader":{"version":"0.16.5","stable":true}}]"#.to_vec(),
+        ),
+        (
+            "/versions/loader/1.18.2/0.16.5/profile/json".to_owned(),
+            br#"{"id":"fabric-loader-0.16.5-1.18.2"}"#.to_vec(),
+        ),
+    ]));
+
+    let (args, profile_path, minecraft_dir) = get_locked_args(
+        SubCommands::Upgrade {
+            filters: FilterArguments::default(),
+            update: None,
+            dry_run: false,
+            install_loader: true,
+        },
+        "local_profile",
+        &Relations::default(),
+    );
+    let config_path = args.config_file.as_ref().unwrap();
+    let mut config = read_config(config_path).unwrap();
+    config.loader_servers.fabric_meta = meta;
+    write_config(config_path, &config).unwrap();
+
+    assert_matches!(actual_main(args).await, Ok(()));
+
+    let id = "fabric-loader-0.16.5-1.18.2";
+    let json = read_to_string(
+        minecraft_dir
+            .join("versions")
+            .join(id)
+            .join(format!("{id}.json")),
+    );
+    assert!(json.unwrap().contains(id));
+    let profile = read_profile(&profile_path).unwrap().unwrap();
+    assert_eq!(
+        profile
+            .loader_version
+            .as_ref()
+            .and_then(Version::exact)
+            .as_deref(),
+        Some("0.16.5")
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn upgrade_refuses_newer_loader() {
+    let (args, profile_path, minecraft_dir) = get_locked_args(
+        SubCommands::Upgrade {
+            filters: FilterArguments::default(),
+            update: None,
+            dry_run: false,
+            install_loader: false,
+        },
+        "locked_profile",
+        &Relations::default(),
+    );
+
+    // Every locked mod is a jar from the local server that needs Fabric Loader 0.16
+    let lockfile = lock_served_jars(&profile_path, 0, |name| {
+        (
+            "fabric.mod.json",
+            format!(r#"{{"id":"{name}","depends":{{"fabricloader":">=0.16.0"}}}}"#),
+        )
+    });
+    let sodium = minecraft_dir.join("mods").join("Sodium.jar");
+
+    // Refused mods are removed and dropped from the lockfile
+    let mut profile = read_profile(&profile_path).unwrap().unwrap();
+    profile.loader_version = Some("=0.15.11".parse().unwrap());
+    write_profile(&profile_path, &profile).unwrap();
+    assert_matches!(actual_main(args.clone()).await, Err(_));
+    assert!(!sodium.exists());
+    let locked = read_lockfile(lockfile_path(&profile_path)).unwrap();
+    assert!(!locked.mods.contains_key("Sodium"));
+
+    // Ranges are checked against the newest version they allow
+    write_lockfile(lockfile_path(&profile_path), &lockfile).unwrap();
+    profile.loader_version = Some("<0.16".parse().unwrap());
+    write_profile(&profile_path, &profile).unwrap();
+    assert_matches!(actual_main(args.clone()).await, Err(_));
+    assert!(!sodium.exists());
+
+    write_lockfile(lockfile_path(&profile_path), &lockfile).unwrap();
+    profile.loader_version = Some("0.16.x".parse().unwrap());
+    write_profile(&profile_path, &profile).unwrap();
+    assert_matches!(actual_main(args.clone()).await, Ok(()));
+    assert!(sodium.exists());
+
+    // Mods that are already installed are checked too
+    profile.loader_version = Some("0.15.x".parse().unwrap());
+    write_profile(&profile_path, &profile).unwrap();
+    assert_matches!(actual_main(args).await, Err(_));
+    assert!(!sodium.exists());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn upgrade_offline() {
+    let (mut args, profile_path, minecraft_dir) = get_locked_args(
+        SubCommands::Upgrade {
+            filters: FilterArguments::default(),
+            update: None,
+            dry_run: false,
+            install_loader: false,
+        },
+        "locked_profile",
+        &Relations::default(),
+    );
+
+    // Every locked mod is a jar from the local server that hasn't been cached before
+    let mut lockfile = lock_served_jars(&profile_path, 0, |name| {
+        ("name.txt", format!("{name} {:X}", rand::random::<u64>()))
+    });
+    assert_matches!(actual_main(args.clone()).await, Ok(()));
+
+    // Nothing can be downloaded anymore, so the mods have to come from the cache
+    remove_dir_all(minecraft_dir.join("mods")).unwrap();
+    for locked in lockfile.mods.values_mut() {
+        locked.url = "http://127.0.0.1:1/unreachable.jar".parse().unwrap();
+    }
+    write_lockfile(lockfile_path(&profile_path), &lockfile).unwrap();
+    args.offline = true;
+    let cache_dir = args.cache_dir.clone().unwrap();
+    assert_matches!(actual_main(args).await, Ok(()));
+    assert!(minecraft_dir.join("mods").join("Sodium.jar").exists());
+    assert!(minecraft_dir.join("mods").join("Iris.jar").exists());
+
+    // The installed files are linked to the cached ones if both are on the same filesystem
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt as _;
+
+        let installed = metadata(minecraft_dir.join("mods").join("Sodium.jar")).unwrap();
+        let cache = metadata(cache_dir).unwrap();
+        if installed.dev() == cache.dev() {
+            assert!(installed.nlink() > 1);
+        }
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn cache_refresh() {
+    async fn fetch(cache: &Cache, value: u32) -> Result<u32, NotCached> {
+        cache
+            .fetch(&["versions"], cache.versions_ttl(), async { Ok(value) })
+            .await
+    }
+
+    let cache = Cache {
+        dir: get_running().with_extension("cache"),
+        offline: false,
+        refresh: false,
+    };
+    assert_matches!(fetch(&cache, 1).await, Ok(1));
+    assert_matches!(fetch(&cache, 2).await, Ok(1));
+
+    // Refreshing fetches the versions again even though the cached ones are recent
+    let refreshing = Cache {
+        refresh: true,
+        ..cache.clone()
+    };
+    assert_matches!(fetch(&refreshing, 3).await, Ok(3));
+    assert_matches!(fetch(&cache, 4).await, Ok(3));
+
+    // Offline, the cached versions are used anyway
+    let offline = Cache {
+        offline: true,
+        ..refreshing
+    };
+    assert_matches!(fetch(&offline, 5).await, Ok(3));
+}
+
+/// A Modrinth mod project with `id` and `slug`, as returned by the API
+fn modrinth_project(id: &str, slug: &str) -> serde_json::Value {
+    serde_json::json!({
+        "slug": slug, "title": slug, "description": "", "body": "",
+        "categories": [], "additional_categories": [], "client_side": "required",
+        "server_side": "required", "status": "approved", "requested_status": null,
+        "issues_url": null, "source_url": null, "wiki_url": null, "discord_url": null,
+        "donation_urls": [], "project_type": "mod", "downloads": 0, "icon_url": null,
+        "color": null, "thread_id": null, "monetization_status": null, "id": id,
+        "team": "BZoBsPo6", "published": "2020-01-01T00:00:00Z",
+        "updated": "2020-01-01T00:00:00Z", "approved": null, "followers": 0,
+        "license": { "id": "Apache-2.0", "name": "", "url": null }, "versions": [],
+        "game_versions": [], "loaders": [], "gallery": [],
+    })
+}
+
+/// A Fabric version of the Modrinth project with `project_id`, for `game_versions`, as returned by the API
+fn modrinth_version(project_id: &str, id: &str, game_versions: &[&str]) -> serde_json::Value {
+    serde_json::json!({
+        "name": id, "version_number": id, "changelog": null, "dependencies": [],
+        "game_versions": game_versions, "version_type": "release", "loaders": ["fabric"],
+        "featured": false, "status": "listed", "requested_status": null, "id": id,
+        "project_id": project_id, "author_id": "BZoBsPo6",
+        "date_published": "2020-01-01T00:00:00Z", "downloads": 0,
+        "files": [{
+            "hashes": { "sha512": "", "sha1": "" },
+            "url": format!("https://cdn.modrinth.com/data/{project_id}/versions/{id}/{id}.jar"),
+            "filename": format!("{id}.jar"), "primary": true, "size": 0, "file_type": null,
+        }],
+    })
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn add_pinned_already_added() {
+    let mut profile = read_profile("./tests/configs/profiles/Locked.toml")
+        .unwrap()
+        .unwrap();
+    let project = serde_json::from_value(modrinth_project("AANobbMI", "sodium")).unwrap();
+    let version =
+        serde_json::from_value(modrinth_version("AANobbMI", "AAAAAAAA", &["1.21.1"])).unwrap();
+
+    // A version of a project is the same project as its unpinned source
+    assert_matches!(
+        libium::add::modrinth(
+            &project,
+            Some(&version),
+            &mut profile,
+            true,
+            Filters::empty()
+        )
+        .await,
+        Err(libium::add::Error::AlreadyAdded)
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn add_pinned_incompatible() {
+    let mut profile = read_profile("./tests/configs/profiles/Locked.toml")
+        .unwrap()
+        .unwrap();
+    let project = serde_json::from_value(modrinth_project("gvQqBUqZ", "lithium")).unwrap();
+
+    // The pinned version is checked, rather than the project's latest one
+    let version =
+        serde_json::from_value(modrinth_version("gvQqBUqZ", "AAAAAAAA", &["1.20.1"])).unwrap();
+    assert_matches!(
+        libium::add::modrinth(
+            &project,
+            Some(&version),
+            &mut profile,
+            true,
+            Filters::empty()
+        )
+        .await,
+        Err(libium::add::Error::Incompatible(_))
+    );
+    assert!(!profile.mods.contains_key("lithium"));
+
+    let version =
+        serde_json::from_value(modrinth_version("gvQqBUqZ", "BBBBBBBB", &["1.21.1"])).unwrap();
+    assert_matches!(
+        libium::add::modrinth(
+            &project,
+            Some(&version),
+            &mut profile,
+            true,
+            Filters::empty()
+        )
+        .await,
+        Ok(())
+    );
+    assert_matches!(
+        &profile.mods["lithium"],
+        Source::Single(SourceId::PinnedModrinth(project, version))
+            if project == "gvQqBUqZ" && version == "BBBBBBBB"
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn excluded_slug_cached() {
+    let cache = Cache {
+        dir: get_running().with_extension("cache"),
+        offline: false,
+        refresh: false,
+    };
+    let project = modrinth_project("P7dR8mSH", "fabric-api");
+    assert_matches!(
+        cache
+            .fetch::<_, NotCached, NotCached>(
+                &["modrinth", "project", "fabric-api"],
+                PROJECT_TTL,
+                async { Ok(project) },
+            )
+            .await,
+        Ok(_)
+    );
+
+    // Offline, the slug can only be looked up from the cache
+    let offline = Cache {
+        offline: true,
+        ..cache
+    };
+    let projects = ExcludedDependency::Slug("fabric-api".to_owned())
+        .projects(&offline)
+        .await
+        .unwrap();
+    assert!(projects.contains(&ProjectKey::Modrinth("P7dR8mSH".to_owned())));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn upgrade_resumes_dropped_downloads() {
+    let (args, profile_path, minecraft_dir) = get_locked_args(
+        SubCommands::Upgrade {
+            filters: FilterArguments::default(),
+            update: None,
+            dry_run: false,
+            install_loader: false,
+        },
+        "locked_profile",
+        &Relations::default(),
+    );
+
+    // The connection drops twice for every jar, which the default 3 retries can recover from
+    lock_served_jars(&profile_path, 2, |name| {
+        let contents = format!("{name} {:X}\n", rand::random::<u64>());
+        ("name.txt", contents.repeat(1000))
+    });
+    assert_matches!(actual_main(args).await, Ok(()));
+    assert!(minecraft_dir.join("mods").join("Sodium.jar").exists());
+    assert!(minecraft_dir.join("mods").join("Iris.jar").exists());
+    assert!(!minecraft_dir.join("mods").join("Sodium.part").exists());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn upgrade_max_bandwidth() {
+    const MAX_BANDWIDTH: u64 = 40_000;
+
+    let (mut args, profile_path, minecraft_dir) = get_locked_args(
+        SubCommands::Upgrade {
+            filters: FilterArguments::default(),
+            update: None,
+            dry_run: false,
+            install_loader: false,
+        },
+        "locked_profile",
+        &Relations::default(),
+    );
+    args.max_bandwidth = Some(size::Size::from_bytes(MAX_BANDWIDTH));
+
+    // Random contents can't be compressed much
+    lock_served_jars(&profile_path, 0, |_| {
+        let contents = (0..40_000)
+            .map(|_| char::from(b'a' + rand::random::<u8>() % 26))
+            .collect();
+        ("name.txt", contents)
+    });
+    let start = Instant::now();
+    assert_matches!(actual_main(args).await, Ok(()));
+    let elapsed = start.elapsed();
+
+    let downloaded: u64 = ["Sodium.jar", "Iris.jar"]
+        .iter()
+        .map(|jar| {
+            metadata(minecraft_dir.join("mods").join(jar))
+                .unwrap()
+                .len()
+        })
+        .sum();
+    assert!(elapsed >= Duration::from_millis(downloaded * 1000 / MAX_BANDWIDTH));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn upgrade_mirrors() {
+    let (mut args, profile_path, minecraft_dir) = get_locked_args(
+        SubCommands::Upgrade {
+            filters: FilterArguments::default(),
+            update: None,
+            dry_run: false,
+            install_loader: false,
+        },
+        "locked_profile",
+        &Relations::default(),
+    );
+    // The unreachable URLs shouldn't be retried
+    args.retries = 0;
+
+    let mut lockfile = lock_served_jars(&profile_path, 0, |name| {
+        ("name.txt", format!("{name} {:X}", rand::random::<u64>()))
+    });
+    let unreachable = "http://127.0.0.1:1/";
+    let served = lockfile.mods["Sodium"].url.clone();
+    let (base, _) = served.as_str().rsplit_once('/').unwrap();
+
+    // Sodium falls back to the served URL, Iris is rewritten to the served mirror
+    let sodium = lockfile.mods.get_mut("Sodium").unwrap();
+    sodium.url = format!("{unreachable}Sodium.jar").parse().unwrap();
+    sodium.fallback_urls = vec![served.clone()];
+    lockfile.mods.get_mut("Iris").unwrap().url = format!("{unreachable}Iris.jar").parse().unwrap();
+    write_lockfile(lockfile_path(&profile_path), &lockfile).unwrap();
+
+    let config_file = args.config_file.as_ref().unwrap();
+    let mut config = read_config(config_file).unwrap();
+    config.profiles[0]
+        .config
+        .mirrors
+        .insert(unreachable.to_owned(), format!("{base}/"));
+    write_config(config_file, &config).unwrap();
+
+    assert_matches!(actual_main(args).await, Ok(()));
+    assert!(minecraft_dir.join("mods").join("Sodium.jar").exists());
+    assert!(minecraft_dir.join("mods").join("Iris.jar").exists());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn upgrade_conflict() {
+    let (args, ..) = get_locked_args(
+        SubCommands::Upgrade {
+            filters: FilterArguments::default(),
+            update: None,
+            dry_run: true,
+            install_loader: false,
+        },
+        "locked_profile",
+        &Relations {
+            conflicts: &["AANobbMI"],
+            ..Relations::default()
+        },
+    );
+    assert_matches!(
+        actual_main(args).await,
+        Err(err) if err.downcast_ref::<ConflictError>().is_some()
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn upgrade_excluded_dependency() {
+    // Resolving the dependency would fail without a network connection
+    let (args, ..) = get_locked_args(
+        SubCommands::Upgrade {
+            filters: FilterArguments::default(),
+            update: None,
+            dry_run: true,
+            install_loader: false,
+        },
+        "excluded_profile",
+        &Relations {
+            dependencies: &["P7dR8mSH"],
+            ..Relations::default()
+        },
+    );
+    assert_matches!(actual_main(args).await, Ok(()));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn update_missing_name() {
+    assert_matches!(
+        actual_main(get_args(
+            SubCommands::Update {
+                names: vec!["not a mod".to_owned()],
+                kind: vec![],
+                filters: FilterArguments::default(),
+            },
+            Some("one_profile_full")
+        ))
+        .await,
+        Err(_),
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn update_unlocked() {
+    // Iris isn't locked, so updating Sodium would update it too
+    assert_matches!(
+        actual_main(get_args(
+            SubCommands::Update {
+                names: vec!["Sodium".to_owned()],
+                kind: vec![],
+                filters: FilterArguments::default(),
+            },
+            Some("locked_profile")
+        ))
+        .await,
+        Err(err) if err.to_string().contains("not locked yet"),
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn update_name() {
+    let (args, profile_path, _) = get_locked_args(
+        SubCommands::Update {
+            names: vec!["sodium".to_owned()],
+            kind: vec![],
+            filters: FilterArguments::default(),
+        },
+        "locked_profile",
+        &Relations::default(),
+    );
+    let locked = lock_served_jars(&profile_path, 0, |name| ("name.txt", name.to_owned()));
+    assert_matches!(actual_main(args).await, Ok(()));
+
+    // Only the named source is resolved again, the other one is installed from the lockfile
+    let lockfile = read_lockfile(lockfile_path(&profile_path)).unwrap();
+    assert_eq!(lockfile.mods["Iris"].url, locked.mods["Iris"].url);
+    assert_ne!(lockfile.mods["Sodium"].url, locked.mods["Sodium"].url);
+    assert_matches!(
+        &lockfile.mods["Sodium"].id,
+        Some(SourceId::PinnedModrinth(project, version))
+            if project == "AANobbMI" && version != "AAAAAAAA"
+    );
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn profile_switch() {
     assert_matches!(
@@ -498,3 +1862,66 @@ async fn delete_profile() {
         Ok(()),
     );
 }
+
+#[tokio::test(flavor = "multi_thread")]
+async fn tui_toggle_pin() {
+    let (args, profile_path, _) = get_locked_args(
+        SubCommands::Profile { subcommand: None },
+        "locked_profile",
+        &Relations::default(),
+    );
+    let config = read_config(args.config_file.as_ref().unwrap()).unwrap();
+    let ctx = Context::new(&args, &config, JsonDocument::default());
+    let item = &config.profiles[0].config;
+    let mut profile = read_profile(&profile_path).unwrap().unwrap();
+
+    // The first source, Iris, is pinned to its locked version and then unpinned
+    App::new(&ctx, Some(&profile_path), item, &mut profile)
+        .unwrap()
+        .toggle_pin();
+    assert_matches!(
+        &profile.mods["Iris"],
+        Source::Single(SourceId::PinnedModrinth(project, version))
+            if project == "YL57xq9U" && version == "AAAAAAAA"
+    );
+    App::new(&ctx, Some(&profile_path), item, &mut profile)
+        .unwrap()
+        .toggle_pin();
+    assert_matches!(&profile.mods["Iris"], Source::Single(SourceId::Modrinth(project)) if project == "YL57xq9U");
+
+    // Without a lockfile there is no version to pin to
+    App::new(&ctx, None, item, &mut profile)
+        .unwrap()
+        .toggle_pin();
+    assert_matches!(&profile.mods["Iris"], Source::Single(SourceId::Modrinth(_)));
+}
+
+#[test]
+fn tui_with_id() {
+    let id = SourceId::PinnedModrinth("AANobbMI".to_owned(), "AAAAAAAA".to_owned());
+    assert_matches!(
+        with_id(&Source::Single(SourceId::Modrinth("AANobbMI".to_owned())), id.clone()),
+        Source::Single(pinned) if pinned == id
+    );
+
+    // Filters of the source are kept
+    let filters = Filters {
+        versions: Some(vec!["=1.21.1".parse().unwrap()]),
+        ..Filters::empty()
+    };
+    let source = Source::from_id(SourceId::Modrinth("AANobbMI".to_owned()), filters);
+    assert_matches!(
+        with_id(&source, id.clone()),
+        Source::Detailed { filters, src }
+            if filters.versions.as_ref().is_some_and(|versions| versions.len() == 1)
+                && matches!(*src, Source::Single(ref pinned) if *pinned == id)
+    );
+}
+
+#[test]
+fn tui_fit() {
+    assert_eq!(fit("Sodium", 8), "Sodium  ");
+    assert_eq!(fit("Sodium", 6), "Sodium");
+    assert_eq!(fit("Sodium", 4), "Sod…");
+    assert_eq!(fit("Sodium", 0), "");
+}